@@ -1,9 +1,9 @@
-use std::error::Error;
 use std::fs::File;
 use std::io::Read;
-use image::{RgbaImage, Rgba};
 use serde::Deserialize;
+use crate::error::SourceError;
 use crate::layer_trait::SourceLayer;
+use crate::pdf_text::{self, TextRun};
 
 #[derive(Debug, Clone)]
 pub struct AiLayer {
@@ -11,6 +11,7 @@ pub struct AiLayer {
     pub content: String,
     pub bounds: Option<(f64, f64, f64, f64)>, // x1, y1, x2, y2
     pub font_name: Option<String>,
+    pub font_size: Option<f32>,
     pub color: Option<(u8, u8, u8)>, // RGB
 }
 
@@ -31,6 +32,10 @@ impl SourceLayer for AiLayer {
         self.font_name.as_deref()
     }
 
+    fn font_size(&self) -> Option<f32> {
+        self.font_size
+    }
+
     fn color(&self) -> Option<(u8, u8, u8)> {
         self.color
     }
@@ -42,52 +47,180 @@ struct DesignMetafield {
     value: String,
 }
 
+// Optional per-layer details; layers named only by a metafield get empty content
+#[derive(Deserialize, Debug)]
+struct LayerMetadata {
+    name: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    bounds: Option<(f64, f64, f64, f64)>,
+    #[serde(default)]
+    font_name: Option<String>,
+    #[serde(default)]
+    font_size: Option<f32>,
+    #[serde(default)]
+    color: Option<String>,
+}
+
 #[derive(Deserialize, Debug)]
 struct AiFileData {
     design_metafields: Vec<DesignMetafield>,
+    #[serde(default)]
+    layers: Vec<LayerMetadata>,
+}
+
+fn parse_rgb(color: &str) -> Result<(u8, u8, u8), csscolorparser::ParseColorError> {
+    let [r, g, b, _] = csscolorparser::parse(color)?.to_rgba8();
+    Ok((r, g, b))
 }
 
 pub struct AiData {
-    layer_names: Vec<String>,
+    layers: Vec<AiLayer>,
 }
 
 impl AiData {
-    pub fn new(json_path: &str, _source_image_path: Option<&str>) -> Result<Self, Box<dyn Error>> {
-        println!("Loading AI file from path: {}", json_path);
-        let mut file = File::open(json_path)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
-        println!("Raw JSON content: {}", contents);
+    pub fn new(path: &str) -> Result<Self, SourceError> {
+        let mut file = File::open(path)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+
+        // PDF-compatible Illustrator files carry a PDF header; anything else is
+        // treated as a JSON metadata export
+        if contents.starts_with(b"%PDF") {
+            return Self::from_pdf(&contents);
+        }
+
+        let contents = String::from_utf8(contents)?;
         let ai_data: AiFileData = serde_json::from_str(&contents)?;
-        println!("Parsed design_metafields: {:?}", ai_data.design_metafields);
-        let layer_names = ai_data.design_metafields
+
+        let mut layers: Vec<AiLayer> = ai_data.design_metafields
             .into_iter()
             .filter(|m| m.namespace == "layer")
-            .map(|m| m.value)
+            .map(|m| AiLayer {
+                name: m.value,
+                content: String::new(),
+                bounds: None,
+                font_name: None,
+                font_size: None,
+                color: None,
+            })
             .collect();
-        Ok(Self { layer_names })
+
+        for metadata in ai_data.layers {
+            let color = metadata.color.as_deref().map(parse_rgb).transpose()
+                .map_err(|source| SourceError::LayerColor { layer: metadata.name.clone(), source })?;
+            let layer = AiLayer {
+                name: metadata.name,
+                content: metadata.content,
+                bounds: metadata.bounds,
+                font_name: metadata.font_name,
+                font_size: metadata.font_size,
+                color,
+            };
+            match layers.iter_mut().find(|existing| existing.name == layer.name) {
+                Some(existing) => *existing = layer,
+                None => layers.push(layer),
+            }
+        }
+
+        Ok(Self { layers })
     }
 
-    pub fn get_layer_by_name(&self, name: &str) -> Option<&dyn SourceLayer> {
-        if self.layer_names.iter().any(|n| n == name) {
-            // Return a dummy AiLayer with the correct name; content will be injected from the template
-            // Use a static dummy so the reference is valid
-            thread_local! {
-                static DUMMY: AiLayer = AiLayer {
-                    name: String::new(),
-                    content: String::new(),
-                    bounds: Some((0.1, 0.1, 0.9, 0.9)),
-                    font_name: Some("Arial".to_string()),
-                    color: Some((0, 0, 0)),
-                };
+    /// Builds one layer per optional content group (Illustrator layer) from the
+    /// text drawn inside it. Text outside any layer becomes `Text 1`, `Text 2`, ...
+    /// per text block.
+    fn from_pdf(contents: &[u8]) -> Result<Self, SourceError> {
+        let doc = lopdf::Document::load_mem(contents)?;
+        let group_names = pdf_text::optional_content_groups(&doc);
+
+        let mut grouped: Vec<(String, Vec<TextRun>)> = group_names.into_iter()
+            .map(|name| (name, Vec::new()))
+            .collect();
+
+        let mut unlayered_blocks = Vec::new();
+        for run in pdf_text::extract_text_runs(&doc)? {
+            let name = match &run.layer {
+                Some(name) => name.clone(),
+                None => {
+                    if !unlayered_blocks.contains(&run.block) {
+                        unlayered_blocks.push(run.block);
+                    }
+                    let index = unlayered_blocks.iter().position(|b| *b == run.block).unwrap_or(0);
+                    format!("Text {}", index + 1)
+                }
+            };
+
+            match grouped.iter_mut().find(|(existing, _)| *existing == name) {
+                Some((_, runs)) => runs.push(run),
+                None => grouped.push((name, vec![run])),
             }
-            DUMMY.with(|dummy| unsafe {
-                let mut_ref = &*(dummy as *const AiLayer);
-                // This is safe because we only use the name field for matching
-                Some(mut_ref as &dyn SourceLayer)
-            })
-        } else {
-            None
         }
+
+        let layers = grouped.into_iter()
+            .map(|(name, runs)| layer_from_runs(name, &runs))
+            .collect();
+        Ok(Self { layers })
+    }
+
+    /// Multiplies every layer's bounds and font size by `factor`.
+    pub fn scaled(mut self, factor: f32) -> Self {
+        for layer in &mut self.layers {
+            layer.bounds = layer.bounds.map(|(x1, y1, x2, y2)| {
+                let factor = factor as f64;
+                (x1 * factor, y1 * factor, x2 * factor, y2 * factor)
+            });
+            layer.font_size = layer.font_size.map(|size| size * factor);
+        }
+        self
+    }
+
+    pub fn layers(&self) -> &[AiLayer] {
+        &self.layers
+    }
+
+    pub fn get_layer_by_name(&self, name: &str) -> Option<&dyn SourceLayer> {
+        self.layers.iter()
+            .find(|layer| layer.name == name)
+            .map(|layer| layer as &dyn SourceLayer)
+    }
+}
+
+/// Joins a layer's text runs, starting a new line whenever the baseline moves.
+/// The first run decides the layer's font and color; the bounds cover every run.
+fn layer_from_runs(name: String, runs: &[TextRun]) -> AiLayer {
+    let mut layer = AiLayer {
+        name,
+        content: String::new(),
+        bounds: None,
+        font_name: None,
+        font_size: None,
+        color: None,
+    };
+
+    let Some(first) = runs.first() else {
+        return layer;
+    };
+    layer.font_name = first.font_name.clone();
+    layer.font_size = Some(first.font_size);
+    layer.color = Some(first.color);
+
+    let mut previous_baseline = first.baseline;
+    let mut bounds = first.bounds;
+    for run in runs {
+        if !layer.content.is_empty() && (run.baseline - previous_baseline).abs() > run.font_size as f64 / 2.0 {
+            layer.content.push('\n');
+        }
+        layer.content.push_str(&run.text);
+        previous_baseline = run.baseline;
+        bounds = (
+            bounds.0.min(run.bounds.0),
+            bounds.1.min(run.bounds.1),
+            bounds.2.max(run.bounds.2),
+            bounds.3.max(run.bounds.3),
+        );
     }
-} 
\ No newline at end of file
+    layer.bounds = Some(bounds);
+
+    layer
+}
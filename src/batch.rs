@@ -0,0 +1,156 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Component, Path, PathBuf};
+use serde_json::Value;
+use crate::error::{DataError, KitError};
+use crate::variables::{self, Variables};
+
+/// Reads one set of values per row from a `.jsonl`/`.ndjson` or `.csv` data file.
+pub fn read_rows(path: &Path) -> Result<Vec<Variables>, KitError> {
+    let extension = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "jsonl" | "ndjson" => read_jsonl(path),
+        "csv" => read_csv(path),
+        _ => Err(DataError::UnsupportedType),
+    }
+    .map_err(|source| KitError::Data { path: path.to_path_buf(), source })
+}
+
+fn read_jsonl(path: &Path) -> Result<Vec<Variables>, DataError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut rows = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let object = match serde_json::from_str(&line).map_err(|source| DataError::Json { line: index + 1, source })? {
+            Value::Object(object) => object,
+            _ => return Err(DataError::NotObject { line: index + 1 }),
+        };
+
+        let row = object.into_iter()
+            .filter_map(|(key, value)| match value {
+                Value::Null => None,
+                Value::String(text) => Some((key, text)),
+                other => Some((key, other.to_string())),
+            })
+            .collect();
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+fn read_csv(path: &Path) -> Result<Vec<Variables>, DataError> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers = reader.headers()?.clone();
+    let mut rows = Vec::new();
+
+    for record in reader.records() {
+        let record = record?;
+        let row = headers.iter()
+            .zip(record.iter())
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// Builds the output path for a row by filling `{{name}}` placeholders in `pattern`
+/// with the row's values. `{{row}}` is the 1-based row number.
+pub fn output_path(pattern: &str, row_number: usize, row: &Variables) -> Result<PathBuf, KitError> {
+    // Keep values from introducing extra directories into the path
+    let mut values: Variables = row.iter()
+        .map(|(key, value)| (key.clone(), value.replace(['/', '\\'], "_")))
+        .collect();
+    values.insert("row".to_string(), row_number.to_string());
+
+    let path = variables::substitute(pattern, &values)
+        .map_err(|source| KitError::OutputPattern { pattern: pattern.to_string(), source })?;
+
+    // Values can still spell out `..` on their own or together, so everything
+    // from the directory of the first placeholder on must stay below it
+    let fixed = pattern.find("{{")
+        .map_or(pattern.len(), |start| pattern[..start].rfind(['/', '\\']).map_or(0, |end| end + 1));
+    let leaves = Path::new(&path[fixed..]).components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+    if leaves {
+        return Err(KitError::OutputOutside { pattern: pattern.to_string(), path: path.into() });
+    }
+    Ok(PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_path_fills_row_values_and_number() {
+        let row: Variables = [("city".to_string(), "Springfield".to_string())].into_iter().collect();
+        let path = output_path("out/{{city}}-{{row}}.png", 3, &row).unwrap();
+        assert_eq!(path, PathBuf::from("out/Springfield-3.png"));
+    }
+
+    #[test]
+    fn output_path_keeps_values_out_of_other_directories() {
+        let row: Variables = [("city".to_string(), "../etc\\passwd".to_string())].into_iter().collect();
+        let path = output_path("out/{{city}}.png", 1, &row).unwrap();
+        assert_eq!(path, PathBuf::from("out/.._etc_passwd.png"));
+    }
+
+    #[test]
+    fn output_path_rejects_values_that_climb_out() {
+        let row: Variables = [("a".to_string(), "..".to_string()), ("b".to_string(), ".".to_string())].into_iter().collect();
+        for pattern in ["out/{{a}}/x.png", "out/{{b}}{{b}}/x.png", "{{a}}", "out/{{row}}/{{a}}/x.png"] {
+            let error = output_path(pattern, 1, &row).unwrap_err();
+            assert!(matches!(error, KitError::OutputOutside { .. }), "{}", pattern);
+        }
+        // The pattern's own directory may point anywhere
+        let path = output_path("../renders/{{b}}x.png", 1, &row).unwrap();
+        assert_eq!(path, PathBuf::from("../renders/.x.png"));
+    }
+
+    #[test]
+    fn output_path_reports_unbound_placeholders() {
+        let error = output_path("out/{{missing}}.png", 1, &Variables::new()).unwrap_err();
+        assert!(matches!(error, KitError::OutputPattern { source: crate::error::VariableError::Unbound(name), .. } if name == "missing"));
+    }
+
+    fn read_data(name: &str, contents: &str) -> Result<Vec<Variables>, KitError> {
+        let path = std::env::temp_dir().join(format!("kit-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        let rows = read_rows(&path);
+        std::fs::remove_file(&path).unwrap();
+        rows
+    }
+
+    #[test]
+    fn read_rows_takes_jsonl_and_csv() {
+        let rows = read_data("rows.jsonl", "{\"city\": \"Springfield\", \"count\": 2, \"gone\": null}\n\n").unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get("count").map(String::as_str), Some("2"));
+        assert!(!rows[0].contains_key("gone"));
+
+        let rows = read_data("rows.csv", "city,count\nSpringfield,2\nShelbyville,3\n").unwrap();
+        assert_eq!(rows[1].get("city").map(String::as_str), Some("Shelbyville"));
+    }
+
+    #[test]
+    fn read_rows_reports_the_bad_line() {
+        let error = read_data("object.jsonl", "{\"city\": \"Springfield\"}\n[1]\n").unwrap_err();
+        assert!(matches!(error, KitError::Data { source: DataError::NotObject { line: 2 }, .. }));
+        let error = read_data("json.jsonl", "{\"city\": \"Springfield\"}\n\n{oops\n").unwrap_err();
+        assert!(matches!(error, KitError::Data { source: DataError::Json { line: 3, .. }, .. }));
+        let error = read_data("rows.txt", "").unwrap_err();
+        assert!(matches!(error, KitError::Data { source: DataError::UnsupportedType, .. }));
+    }
+}
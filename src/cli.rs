@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+#[derive(Parser)]
+#[command(name = "kit", version, about = "Render print templates to images")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Render a template to an output file
+    Render {
+        /// Path to the template JSON file
+        template: PathBuf,
+        /// Where to write the rendered output
+        #[arg(short, long, default_value = "output/result.png")]
+        output: PathBuf,
+        /// Output format; inferred from the output extension when omitted
+        #[arg(short, long, value_enum)]
+        format: Option<OutputFormat>,
+        /// Override a text layer's content, e.g. --set CITY="Springfield"
+        #[arg(long = "set", value_name = "LAYER=TEXT", value_parser = parse_key_value)]
+        overrides: Vec<(String, String)>,
+        /// Bind a template variable, e.g. --var city="Springfield"
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_key_value)]
+        variables: Vec<(String, String)>,
+        #[command(flatten)]
+        fonts: FontArgs,
+    },
+    /// Render a template once per row of a JSONL or CSV data file
+    Batch {
+        /// Path to the template JSON file
+        template: PathBuf,
+        /// Rows to render; each column or key sets the text layer of the same name
+        /// and is also bound as a template variable
+        data: PathBuf,
+        /// Output path pattern; {{row}} is the row number and {{NAME}} a row value
+        #[arg(short, long, default_value = "output/{{row}}.png")]
+        output: String,
+        /// Output format; inferred from the output extension when omitted
+        #[arg(short, long, value_enum)]
+        format: Option<OutputFormat>,
+        /// Bind a template variable for every row, e.g. --var city="Springfield"
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_key_value)]
+        variables: Vec<(String, String)>,
+        #[command(flatten)]
+        fonts: FontArgs,
+        /// Keep rendering remaining rows after a row fails
+        #[arg(long)]
+        keep_going: bool,
+    },
+    /// Check a template and its fonts, images and source file without rendering
+    Validate {
+        /// Path to the template JSON file
+        template: PathBuf,
+        /// Bind a template variable, e.g. --var city="Springfield"
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_key_value)]
+        variables: Vec<(String, String)>,
+        #[command(flatten)]
+        fonts: FontArgs,
+    },
+    /// Print the groups, layers and computed layout of a template
+    Inspect {
+        /// Path to the template JSON file
+        template: PathBuf,
+        /// Bind a template variable, e.g. --var city="Springfield"
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_key_value)]
+        variables: Vec<(String, String)>,
+        #[command(flatten)]
+        fonts: FontArgs,
+    },
+}
+
+/// Where to find fonts, on top of the template's `font_dirs`.
+#[derive(Args)]
+pub struct FontArgs {
+    /// Load fonts from this directory; may be given more than once
+    #[arg(long = "font-dir", value_name = "DIR")]
+    pub font_dirs: Vec<PathBuf>,
+    /// Fall back to system fonts for families missing from the font directories
+    #[arg(long)]
+    pub system_fonts: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Pdf,
+    Svg,
+}
+
+impl OutputFormat {
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "pdf" => Some(OutputFormat::Pdf),
+            "svg" => Some(OutputFormat::Svg),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "PNG",
+            OutputFormat::Jpeg => "JPEG",
+            OutputFormat::Pdf => "PDF",
+            OutputFormat::Svg => "SVG",
+        }
+    }
+}
+
+fn parse_key_value(value: &str) -> Result<(String, String), String> {
+    let (name, text) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got '{}'", value))?;
+    if name.is_empty() {
+        return Err("name cannot be empty".to_string());
+    }
+    Ok((name.to_string(), text.to_string()))
+}
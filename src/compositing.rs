@@ -0,0 +1,171 @@
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+
+/// How a layer's colors mix with the pixels already under it, as the
+/// separable blend modes of Illustrator, Photoshop, PDF and CSS define them.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+}
+
+impl BlendMode {
+    /// The name PDF and CSS both use for the mode.
+    pub fn name(self) -> &'static str {
+        match self {
+            BlendMode::Normal => "Normal",
+            BlendMode::Multiply => "Multiply",
+            BlendMode::Screen => "Screen",
+            BlendMode::Overlay => "Overlay",
+            BlendMode::Darken => "Darken",
+            BlendMode::Lighten => "Lighten",
+        }
+    }
+
+    // Mixes one channel of the source over the backdrop, both from 0 to 1
+    fn mix(self, backdrop: f32, source: f32) -> f32 {
+        match self {
+            BlendMode::Normal => source,
+            BlendMode::Multiply => backdrop * source,
+            BlendMode::Screen => backdrop + source - backdrop * source,
+            // Hard light with the layers swapped: the backdrop picks multiply or screen
+            BlendMode::Overlay => {
+                if backdrop <= 0.5 {
+                    2.0 * backdrop * source
+                } else {
+                    let backdrop = 2.0 * backdrop - 1.0;
+                    backdrop + source - backdrop * source
+                }
+            }
+            BlendMode::Darken => backdrop.min(source),
+            BlendMode::Lighten => backdrop.max(source),
+        }
+    }
+}
+
+/// Composites `color` at `coverage` onto a non-premultiplied pixel whose
+/// channels run from 0 to 255 and alpha from 0 to 1. Where the pixel is
+/// transparent the color is painted as is, whatever the blend mode.
+pub fn paint(pixel: &mut [f32; 4], color: Rgba<u8>, coverage: f32, mode: BlendMode) {
+    let alpha = color[3] as f32 / 255.0 * coverage;
+    if alpha <= 0.0 {
+        return;
+    }
+    let backdrop_alpha = pixel[3];
+    let out_alpha = alpha + backdrop_alpha * (1.0 - alpha);
+    for channel in 0..3 {
+        let (backdrop, source) = (pixel[channel] / 255.0, color[channel] as f32 / 255.0);
+        let mixed = (1.0 - backdrop_alpha) * source + backdrop_alpha * mode.mix(backdrop, source);
+        let value = alpha * mixed + backdrop_alpha * (1.0 - alpha) * backdrop;
+        pixel[channel] = value / out_alpha * 255.0;
+    }
+    pixel[3] = out_alpha;
+}
+
+pub fn to_rgba(pixel: [f32; 4]) -> Rgba<u8> {
+    Rgba([pixel[0].round() as u8, pixel[1].round() as u8, pixel[2].round() as u8, (pixel[3] * 255.0).round() as u8])
+}
+
+/// Composites `image`, placed with its top-left pixel at `(x, y)`, onto the
+/// canvas with its alpha scaled by `opacity`. Pixels off the canvas are dropped.
+pub fn draw(canvas: &mut RgbaImage, image: &RgbaImage, x: i32, y: i32, opacity: f32, mode: BlendMode) {
+    for (dx, dy, source) in image.enumerate_pixels() {
+        let (canvas_x, canvas_y) = (x + dx as i32, y + dy as i32);
+        if source[3] == 0
+            || canvas_x < 0
+            || canvas_y < 0
+            || canvas_x as u32 >= canvas.width()
+            || canvas_y as u32 >= canvas.height()
+        {
+            continue;
+        }
+        let target = canvas.get_pixel_mut(canvas_x as u32, canvas_y as u32);
+        let mut pixel = [target[0] as f32, target[1] as f32, target[2] as f32, target[3] as f32 / 255.0];
+        paint(&mut pixel, *source, opacity, mode);
+        *target = to_rgba(pixel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws a single pixel of `source` over `backdrop` and returns the result
+    fn drawn(backdrop: [u8; 4], source: [u8; 4], opacity: f32, mode: BlendMode) -> [u8; 4] {
+        let mut canvas = RgbaImage::from_pixel(1, 1, Rgba(backdrop));
+        draw(&mut canvas, &RgbaImage::from_pixel(1, 1, Rgba(source)), 0, 0, opacity, mode);
+        canvas.get_pixel(0, 0).0
+    }
+
+    #[test]
+    fn opacity_mixes_with_the_backdrop() {
+        let (backdrop, source) = ([200, 100, 50, 255], [100, 200, 0, 255]);
+        assert_eq!(drawn(backdrop, source, 0.0, BlendMode::Normal), backdrop);
+        assert_eq!(drawn(backdrop, source, 0.5, BlendMode::Normal), [150, 150, 25, 255]);
+        assert_eq!(drawn(backdrop, source, 1.0, BlendMode::Normal), source);
+    }
+
+    #[test]
+    fn transparent_backdrop_keeps_the_source_color() {
+        let clear = [0, 0, 0, 0];
+        assert_eq!(drawn(clear, [100, 200, 0, 255], 0.5, BlendMode::Normal), [100, 200, 0, 128]);
+        assert_eq!(drawn(clear, [100, 200, 0, 102], 1.0, BlendMode::Normal), [100, 200, 0, 102]);
+    }
+
+    #[test]
+    fn source_alpha_and_coverage_multiply() {
+        let mut pixel = [200.0, 100.0, 50.0, 1.0];
+        paint(&mut pixel, Rgba([100, 200, 0, 255]), 0.5, BlendMode::Normal);
+        assert_eq!(to_rgba(pixel).0, [150, 150, 25, 255]);
+
+        // Half coverage of a half transparent color is a quarter of the way there
+        let mut pixel = [200.0, 100.0, 50.0, 1.0];
+        paint(&mut pixel, Rgba([0, 200, 250, 128]), 0.5, BlendMode::Normal);
+        assert_eq!(to_rgba(pixel).0, [150, 125, 100, 255]);
+    }
+
+    #[test]
+    fn draw_skips_pixels_off_the_canvas() {
+        let mut canvas = RgbaImage::from_pixel(2, 2, Rgba([0, 0, 0, 255]));
+        draw(&mut canvas, &RgbaImage::from_pixel(2, 2, Rgba([255, 255, 255, 255])), 1, -1, 1.0, BlendMode::Normal);
+        assert_eq!(canvas.get_pixel(1, 0).0, [255, 255, 255, 255]);
+        assert_eq!([canvas.get_pixel(0, 0)[0], canvas.get_pixel(0, 1)[0], canvas.get_pixel(1, 1)[0]], [0, 0, 0]);
+    }
+
+    #[test]
+    fn blend_modes_mix_with_an_opaque_backdrop() {
+        let (backdrop, source) = ([200, 100, 50, 255], [100, 200, 0, 255]);
+        let expected = [
+            (BlendMode::Normal, [100, 200, 0, 255]),
+            (BlendMode::Multiply, [78, 78, 0, 255]),
+            (BlendMode::Screen, [222, 222, 50, 255]),
+            // Red's backdrop is light enough to screen, green's dark enough to multiply
+            (BlendMode::Overlay, [188, 157, 0, 255]),
+            (BlendMode::Darken, [100, 100, 0, 255]),
+            (BlendMode::Lighten, [200, 200, 50, 255]),
+        ];
+        for (mode, pixel) in expected {
+            assert_eq!(drawn(backdrop, source, 1.0, mode), pixel, "{}", mode.name());
+        }
+    }
+
+    #[test]
+    fn blend_modes_fade_with_opacity() {
+        let (backdrop, source) = ([200, 100, 50, 255], [100, 200, 0, 255]);
+        assert_eq!(drawn(backdrop, source, 0.0, BlendMode::Multiply), backdrop);
+        // Halfway between the backdrop and the multiplied color
+        assert_eq!(drawn(backdrop, source, 0.5, BlendMode::Multiply), [139, 89, 25, 255]);
+    }
+
+    #[test]
+    fn blend_modes_paint_as_normal_over_transparency() {
+        for mode in [BlendMode::Multiply, BlendMode::Screen, BlendMode::Overlay, BlendMode::Darken, BlendMode::Lighten] {
+            assert_eq!(drawn([0, 0, 0, 0], [100, 200, 0, 255], 1.0, mode), [100, 200, 0, 255], "{}", mode.name());
+        }
+    }
+}
@@ -0,0 +1,667 @@
+use ab_glyph_rasterizer::{point, Point, Rasterizer};
+use image::{ImageBuffer, Luma, Rgba, RgbaImage};
+use rusttype::{OutlineBuilder, ScaledGlyph};
+use serde::Deserialize;
+use crate::compositing::{paint, to_rgba, BlendMode};
+use crate::error::{KitError, Location};
+use crate::parse_rgba;
+
+// Miter joins longer than this many stroke widths are beveled instead, as in SVG
+pub const MITER_LIMIT: f32 = 4.0;
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StrokeJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+/// An outline around the text, reaching `width` pixels out from the glyph edges.
+#[derive(Deserialize, Clone)]
+pub struct TextStroke {
+    pub color: String,
+    pub width: f32,
+    #[serde(default = "default_stroke_join")]
+    pub join: StrokeJoin,
+}
+
+fn default_stroke_join() -> StrokeJoin {
+    StrokeJoin::Round
+}
+
+impl TextStroke {
+    pub fn validate(&self) -> Result<(), KitError> {
+        self.rgba_color()?;
+        if self.width <= 0.0 {
+            return Err(KitError::Invalid {
+                at: Location::field("stroke.width"),
+                message: "stroke width must be positive".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn rgba_color(&self) -> Result<Rgba<u8>, KitError> {
+        parse_rgba(&self.color, "stroke.color")
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ShadowOffset {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// A copy of the text's silhouette, stroke included, drawn behind it.
+#[derive(Deserialize, Clone)]
+pub struct TextShadow {
+    pub color: String,
+    pub offset: ShadowOffset,
+    // Distance over which the shadow's edge fades out
+    #[serde(default)]
+    pub blur: f32,
+    #[serde(default = "default_shadow_opacity")]
+    pub opacity: f32,
+}
+
+fn default_shadow_opacity() -> f32 {
+    1.0
+}
+
+impl TextShadow {
+    pub fn validate(&self) -> Result<(), KitError> {
+        self.rgba_color()?;
+        if self.blur < 0.0 {
+            return Err(KitError::Invalid {
+                at: Location::field("shadow.blur"),
+                message: "shadow blur cannot be negative".to_string(),
+            });
+        }
+        if !(0.0..=1.0).contains(&self.opacity) {
+            return Err(KitError::Invalid {
+                at: Location::field("shadow.opacity"),
+                message: "shadow opacity must be between 0 and 1".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The shadow color with `opacity` folded into its alpha.
+    pub fn rgba_color(&self) -> Result<Rgba<u8>, KitError> {
+        let mut color = parse_rgba(&self.color, "shadow.color")?;
+        color[3] = (color[3] as f32 * self.opacity) as u8;
+        Ok(color)
+    }
+
+    // Standard deviation of the Gaussian blur; the fade reaches `blur` pixels
+    // at three deviations
+    pub fn blur_sigma(&self) -> f32 {
+        self.blur / 3.0
+    }
+}
+
+/// Room the stroke and shadow take up around the text itself, in pixels.
+#[derive(Clone, Copy, Default)]
+pub struct EffectPadding {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl EffectPadding {
+    pub fn new(stroke: Option<&TextStroke>, shadow: Option<&TextShadow>) -> Self {
+        let stroke_width = stroke.map_or(0.0, |stroke| stroke.width);
+        let (mut left, mut top, mut right, mut bottom) = (stroke_width, stroke_width, stroke_width, stroke_width);
+        if let Some(shadow) = shadow {
+            let spread = stroke_width + shadow.blur;
+            left = left.max(spread - shadow.offset.x);
+            right = right.max(spread + shadow.offset.x);
+            top = top.max(spread - shadow.offset.y);
+            bottom = bottom.max(spread + shadow.offset.y);
+        }
+        EffectPadding {
+            left: left.ceil() as u32,
+            top: top.ceil() as u32,
+            right: right.ceil() as u32,
+            bottom: bottom.ceil() as u32,
+        }
+    }
+}
+
+/// How much of each pixel of a canvas region a shape covers, from 0 to 1.
+pub struct Mask {
+    // Canvas position of the region's top-left pixel
+    x: i32,
+    y: i32,
+    coverage: ImageBuffer<Luma<f32>, Vec<f32>>,
+}
+
+impl Mask {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Mask { x, y, coverage: ImageBuffer::new(width, height) }
+    }
+
+    fn width(&self) -> u32 {
+        self.coverage.width()
+    }
+
+    fn height(&self) -> u32 {
+        self.coverage.height()
+    }
+
+    /// Covers the canvas pixel at `(x, y)` by at least `value`.
+    pub fn add(&mut self, x: i32, y: i32, value: f32) {
+        let (mx, my) = (x - self.x, y - self.y);
+        if mx >= 0 && my >= 0 && (mx as u32) < self.width() && (my as u32) < self.height() {
+            let pixel = self.coverage.get_pixel_mut(mx as u32, my as u32);
+            pixel[0] = pixel[0].max(value.min(1.0));
+        }
+    }
+
+    /// Fully covers a rectangle given in canvas pixels.
+    pub fn add_rect(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        for dy in 0..height {
+            for dx in 0..width {
+                self.add((x + dx) as i32, (y + dy) as i32, 1.0);
+            }
+        }
+    }
+
+    // Coverage at region coordinates, zero outside the region
+    fn at(&self, x: i32, y: i32) -> f32 {
+        if x >= 0 && y >= 0 && (x as u32) < self.width() && (y as u32) < self.height() {
+            self.coverage.get_pixel(x as u32, y as u32)[0]
+        } else {
+            0.0
+        }
+    }
+
+    /// This mask moved by `(dx, dy)` pixels within the same region and
+    /// softened by a Gaussian blur of deviation `sigma`.
+    pub fn shadow(&self, dx: f32, dy: f32, sigma: f32) -> Mask {
+        let (dx, dy) = (dx.round() as i32, dy.round() as i32);
+        let shifted = ImageBuffer::from_fn(self.width(), self.height(), |x, y| {
+            Luma([self.at(x as i32 - dx, y as i32 - dy)])
+        });
+        let coverage = if sigma > 0.0 { image::imageops::blur(&shifted, sigma) } else { shifted };
+        Mask { x: self.x, y: self.y, coverage }
+    }
+}
+
+/// Paints the text's effects and fill, bottom to top, into one image the size
+/// of the fill mask's region. The fill takes its color at each canvas pixel
+/// from `fill_color`. Returns the image with its canvas position.
+pub fn composite(
+    fill: &Mask,
+    fill_color: impl Fn(i32, i32) -> Rgba<u8>,
+    stroke: Option<(&Mask, Rgba<u8>)>,
+    shadow: Option<(&Mask, Rgba<u8>)>,
+) -> (RgbaImage, i32, i32) {
+    let image = RgbaImage::from_fn(fill.width(), fill.height(), |x, y| {
+        let (x, y) = (x as i32, y as i32);
+        let mut pixel = [0.0; 4];
+        if let Some((mask, color)) = shadow {
+            paint(&mut pixel, color, mask.at(x, y), BlendMode::Normal);
+        }
+        if let Some((mask, color)) = stroke {
+            paint(&mut pixel, color, mask.at(x, y), BlendMode::Normal);
+        }
+        let coverage = fill.at(x, y);
+        if coverage > 0.0 {
+            paint(&mut pixel, fill_color(fill.x + x, fill.y + y), coverage, BlendMode::Normal);
+        }
+        to_rgba(pixel)
+    });
+    (image, fill.x, fill.y)
+}
+
+/// How far past the stroke width a region must reach to hold the stroke,
+/// since miter joins can poke out further than the width.
+pub fn stroke_reach(stroke: &TextStroke) -> u32 {
+    let reach = match stroke.join {
+        StrokeJoin::Miter => stroke.width * MITER_LIMIT,
+        StrokeJoin::Round | StrokeJoin::Bevel => stroke.width,
+    };
+    reach.ceil() as u32 + 2
+}
+
+/// Builds a stroke mask from glyph outlines and decoration rectangles. Each
+/// outline segment becomes a band `width` pixels either side of it, with the
+/// join shape at every corner, and the bands are unioned.
+pub struct Stroker {
+    x: i32,
+    y: i32,
+    width: f32,
+    join: StrokeJoin,
+    rasterizer: Rasterizer,
+}
+
+impl Stroker {
+    /// A stroker covering the same region as `fill`.
+    pub fn new(fill: &Mask, stroke: &TextStroke) -> Self {
+        Stroker {
+            x: fill.x,
+            y: fill.y,
+            width: stroke.width,
+            join: stroke.join,
+            rasterizer: Rasterizer::new(fill.width() as usize, fill.height() as usize),
+        }
+    }
+
+    pub fn add_outline(&mut self, outline: &Outline) {
+        let origin = point(self.x as f32, self.y as f32);
+        for contour in &outline.contours {
+            let points: Vec<Point> = flatten(contour).into_iter().map(|p| sub(p, origin)).collect();
+            self.stroke_contour(&points);
+        }
+    }
+
+    pub fn add_rect(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        let (left, top) = ((x as i32 - self.x) as f32, (y as i32 - self.y) as f32);
+        let (right, bottom) = (left + width as f32, top + height as f32);
+        self.stroke_contour(&[point(left, top), point(right, top), point(right, bottom), point(left, bottom)]);
+    }
+
+    /// The stroke's coverage, which takes in the fill so the stroke has no
+    /// gap against the glyph edges.
+    pub fn finish(self, fill: &Mask) -> Mask {
+        let mut mask = Mask::new(self.x, self.y, fill.width(), fill.height());
+        self.rasterizer.for_each_pixel_2d(|x, y, coverage| {
+            let value = coverage.min(1.0).max(fill.at(x as i32, y as i32));
+            mask.coverage.put_pixel(x, y, Luma([value]));
+        });
+        mask
+    }
+
+    fn stroke_contour(&mut self, contour: &[Point]) {
+        let mut points: Vec<Point> = Vec::with_capacity(contour.len());
+        for &p in contour {
+            if points.last().is_none_or(|last| distance(*last, p) > 1e-3) {
+                points.push(p);
+            }
+        }
+        while points.len() > 1 && distance(points[0], points[points.len() - 1]) <= 1e-3 {
+            points.pop();
+        }
+        if points.len() < 2 {
+            return;
+        }
+
+        let count = points.len();
+        for index in 0..count {
+            let previous = points[(index + count - 1) % count];
+            let current = points[index];
+            let next = points[(index + 1) % count];
+
+            // The band along the segment leaving this point
+            let normal = left_normal(direction(current, next));
+            let offset = scale(normal, self.width);
+            self.fill_polygon(&[add(current, offset), add(next, offset), sub(next, offset), sub(current, offset)]);
+
+            self.join(previous, current, next);
+        }
+    }
+
+    // Fills the corner between the bands of the segments meeting at `corner`
+    fn join(&mut self, previous: Point, corner: Point, next: Point) {
+        if self.join == StrokeJoin::Round {
+            self.fill_circle(corner);
+            return;
+        }
+
+        let (incoming, outgoing) = (direction(previous, corner), direction(corner, next));
+        let turn = incoming.x * outgoing.y - incoming.y * outgoing.x;
+        if turn.abs() < 1e-6 && incoming.x * outgoing.x + incoming.y * outgoing.y > 0.0 {
+            return;
+        }
+
+        // The bands only leave a gap on the outside of the turn
+        let side = if turn > 0.0 { -1.0 } else { 1.0 };
+        let incoming_normal = scale(left_normal(incoming), side);
+        let outgoing_normal = scale(left_normal(outgoing), side);
+        let incoming_edge = add(corner, scale(incoming_normal, self.width));
+        let outgoing_edge = add(corner, scale(outgoing_normal, self.width));
+
+        if self.join == StrokeJoin::Miter {
+            let bisector = add(incoming_normal, outgoing_normal);
+            let length = distance(bisector, point(0.0, 0.0));
+            if length > 1e-6 {
+                let bisector = scale(bisector, 1.0 / length);
+                let cos_half = bisector.x * incoming_normal.x + bisector.y * incoming_normal.y;
+                if cos_half >= 1.0 / MITER_LIMIT {
+                    let tip = add(corner, scale(bisector, self.width / cos_half));
+                    self.fill_polygon(&[corner, incoming_edge, tip, outgoing_edge]);
+                    return;
+                }
+            }
+        }
+        self.fill_polygon(&[corner, incoming_edge, outgoing_edge]);
+    }
+
+    fn fill_circle(&mut self, center: Point) {
+        let segments = (self.width * 2.0).ceil().clamp(8.0, 64.0) as usize;
+        let points: Vec<Point> = (0..segments)
+            .map(|index| {
+                let angle = index as f32 / segments as f32 * std::f32::consts::TAU;
+                point(center.x + self.width * angle.cos(), center.y + self.width * angle.sin())
+            })
+            .collect();
+        self.fill_polygon(&points);
+    }
+
+    // Every polygon is drawn with the same winding so overlaps add up instead
+    // of cancelling out
+    fn fill_polygon(&mut self, points: &[Point]) {
+        let doubled_area: f32 = (0..points.len())
+            .map(|index| {
+                let (a, b) = (points[index], points[(index + 1) % points.len()]);
+                a.x * b.y - b.x * a.y
+            })
+            .sum();
+        for index in 0..points.len() {
+            let (a, b) = (points[index], points[(index + 1) % points.len()]);
+            if doubled_area >= 0.0 {
+                self.rasterizer.draw_line(a, b);
+            } else {
+                self.rasterizer.draw_line(b, a);
+            }
+        }
+    }
+}
+
+pub fn add(a: Point, b: Point) -> Point {
+    point(a.x + b.x, a.y + b.y)
+}
+
+pub fn sub(a: Point, b: Point) -> Point {
+    point(a.x - b.x, a.y - b.y)
+}
+
+pub fn scale(a: Point, factor: f32) -> Point {
+    point(a.x * factor, a.y * factor)
+}
+
+pub fn distance(a: Point, b: Point) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+pub fn direction(from: Point, to: Point) -> Point {
+    let length = distance(from, to).max(1e-6);
+    point((to.x - from.x) / length, (to.y - from.y) / length)
+}
+
+pub fn left_normal(direction: Point) -> Point {
+    point(-direction.y, direction.x)
+}
+
+#[derive(Clone, Copy)]
+enum Segment {
+    Line(Point, Point),
+    Quad(Point, Point, Point),
+    Cubic(Point, Point, Point, Point),
+}
+
+/// A glyph's outline in canvas pixels, kept as curves so filling it matches
+/// rusttype's own rasterizing.
+pub struct Outline {
+    contours: Vec<Vec<Segment>>,
+}
+
+impl Outline {
+    /// The outline of a glyph with its origin at `origin` and its baseline
+    /// turned `angle` radians clockwise.
+    pub fn rotated(glyph: &ScaledGlyph, origin: Point, angle: f32) -> Self {
+        let mut builder = OutlineCollector {
+            origin,
+            x_axis: point(angle.cos(), angle.sin()),
+            y_axis: point(-angle.sin(), angle.cos()),
+            contours: Vec::new(),
+            current: Vec::new(),
+            start: origin,
+            last: origin,
+        };
+        glyph.build_outline(&mut builder);
+        builder.finish_contour();
+        Outline { contours: builder.contours }
+    }
+
+    /// The smallest box, `(min_x, min_y, max_x, max_y)`, holding the outline.
+    pub fn bounds(&self) -> Option<(f32, f32, f32, f32)> {
+        let mut points = self.contours.iter().flat_map(|contour| flatten(contour));
+        let first = points.next()?;
+        Some(points.fold((first.x, first.y, first.x, first.y), |(x0, y0, x1, y1), p| {
+            (x0.min(p.x), y0.min(p.y), x1.max(p.x), y1.max(p.y))
+        }))
+    }
+}
+
+impl Mask {
+    /// Covers the inside of `outline`, antialiased.
+    pub fn fill(&mut self, outline: &Outline) {
+        let Some((min_x, min_y, max_x, max_y)) = outline.bounds() else {
+            return;
+        };
+        let (left, top) = (min_x.floor() as i32, min_y.floor() as i32);
+        let width = (max_x.ceil() as i32 - left).max(1) as usize;
+        let height = (max_y.ceil() as i32 - top).max(1) as usize;
+
+        // Rasterize within the outline's own box, as rusttype does for a glyph
+        let origin = point(left as f32, top as f32);
+        let mut rasterizer = Rasterizer::new(width, height);
+        for segment in outline.contours.iter().flatten() {
+            match *segment {
+                Segment::Line(a, b) => rasterizer.draw_line(sub(a, origin), sub(b, origin)),
+                Segment::Quad(a, b, c) => rasterizer.draw_quad(sub(a, origin), sub(b, origin), sub(c, origin)),
+                Segment::Cubic(a, b, c, d) => {
+                    rasterizer.draw_cubic(sub(a, origin), sub(b, origin), sub(c, origin), sub(d, origin))
+                }
+            }
+        }
+        rasterizer.for_each_pixel_2d(|x, y, coverage| self.add(left + x as i32, top + y as i32, coverage));
+    }
+}
+
+// Collects a glyph's outline in canvas pixels, mapping glyph space through
+// the given origin and axes
+struct OutlineCollector {
+    origin: Point,
+    x_axis: Point,
+    y_axis: Point,
+    contours: Vec<Vec<Segment>>,
+    current: Vec<Segment>,
+    start: Point,
+    last: Point,
+}
+
+impl OutlineCollector {
+    fn map(&self, x: f32, y: f32) -> Point {
+        add(self.origin, add(scale(self.x_axis, x), scale(self.y_axis, y)))
+    }
+
+    fn finish_contour(&mut self) {
+        // Filling needs every contour closed
+        if !self.current.is_empty() && distance(self.last, self.start) > 1e-3 {
+            self.current.push(Segment::Line(self.last, self.start));
+        }
+        if !self.current.is_empty() {
+            self.contours.push(std::mem::take(&mut self.current));
+        }
+        self.last = self.start;
+    }
+}
+
+impl OutlineBuilder for OutlineCollector {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.finish_contour();
+        self.start = self.map(x, y);
+        self.last = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let end = self.map(x, y);
+        self.current.push(Segment::Line(self.last, end));
+        self.last = end;
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let end = self.map(x, y);
+        self.current.push(Segment::Quad(self.last, self.map(x1, y1), end));
+        self.last = end;
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let end = self.map(x, y);
+        self.current.push(Segment::Cubic(self.last, self.map(x1, y1), self.map(x2, y2), end));
+        self.last = end;
+    }
+
+    fn close(&mut self) {
+        self.finish_contour();
+    }
+}
+
+// Pieces to split a curve into; roughly one per two pixels of its control polygon
+fn curve_steps(length: f32) -> usize {
+    (length / 2.0).ceil().clamp(1.0, 32.0) as usize
+}
+
+// A contour as a closed polyline, its curves split into short straight pieces
+fn flatten(contour: &[Segment]) -> Vec<Point> {
+    let mut points = Vec::new();
+    for segment in contour {
+        match *segment {
+            Segment::Line(start, end) => {
+                if points.is_empty() {
+                    points.push(start);
+                }
+                points.push(end);
+            }
+            Segment::Quad(start, control, end) => {
+                if points.is_empty() {
+                    points.push(start);
+                }
+                let steps = curve_steps(distance(start, control) + distance(control, end));
+                for step in 1..=steps {
+                    let t = step as f32 / steps as f32;
+                    let u = 1.0 - t;
+                    points.push(point(
+                        u * u * start.x + 2.0 * u * t * control.x + t * t * end.x,
+                        u * u * start.y + 2.0 * u * t * control.y + t * t * end.y,
+                    ));
+                }
+            }
+            Segment::Cubic(start, first, second, end) => {
+                if points.is_empty() {
+                    points.push(start);
+                }
+                let steps = curve_steps(distance(start, first) + distance(first, second) + distance(second, end));
+                for step in 1..=steps {
+                    let t = step as f32 / steps as f32;
+                    let u = 1.0 - t;
+                    points.push(point(
+                        u * u * u * start.x + 3.0 * u * u * t * first.x + 3.0 * u * t * t * second.x + t * t * t * end.x,
+                        u * u * u * start.y + 3.0 * u * u * t * first.y + 3.0 * u * t * t * second.y + t * t * t * end.y,
+                    ));
+                }
+            }
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stroke(width: f32, join: StrokeJoin) -> TextStroke {
+        TextStroke { color: "#000000".to_string(), width, join }
+    }
+
+    // Rasterized coverage adds up to whole or none only within rounding
+    fn full(value: f32) -> bool {
+        value > 0.999
+    }
+
+    fn none(value: f32) -> bool {
+        value < 0.001
+    }
+
+    // A 10 pixel square at (10, 10), stroked within a 40 pixel region
+    fn stroked_square(stroke: &TextStroke) -> Mask {
+        let mut fill = Mask::new(0, 0, 40, 40);
+        fill.add_rect(10, 10, 10, 10);
+        let mut stroker = Stroker::new(&fill, stroke);
+        stroker.add_rect(10, 10, 10, 10);
+        stroker.finish(&fill)
+    }
+
+    #[test]
+    fn stroke_reaches_its_width_past_the_edge() {
+        let mask = stroked_square(&stroke(3.0, StrokeJoin::Round));
+        assert!(full(mask.at(15, 15)));
+        assert!(full(mask.at(7, 15)));
+        assert!(full(mask.at(22, 15)));
+        assert!(none(mask.at(6, 15)));
+        assert!(none(mask.at(23, 15)));
+
+        let mask = stroked_square(&stroke(6.0, StrokeJoin::Round));
+        assert!(full(mask.at(4, 15)));
+        assert!(none(mask.at(3, 15)));
+    }
+
+    #[test]
+    fn joins_shape_the_corners() {
+        // The pixel just inside the corner of the miter's square
+        let miter = stroked_square(&stroke(3.0, StrokeJoin::Miter));
+        assert!(full(miter.at(7, 7)));
+        // Partly inside the round join's circle
+        let round = stroked_square(&stroke(3.0, StrokeJoin::Round));
+        assert!(round.at(7, 7) > 0.0 && round.at(7, 7) < 0.5, "{}", round.at(7, 7));
+        // Wholly outside the bevel's cut
+        let bevel = stroked_square(&stroke(3.0, StrokeJoin::Bevel));
+        assert!(none(bevel.at(7, 7)));
+        assert!(full(bevel.at(8, 9)));
+    }
+
+    #[test]
+    fn shadow_moves_by_the_offset() {
+        let mut mask = Mask::new(0, 0, 40, 40);
+        mask.add_rect(10, 10, 5, 5);
+        let shadow = mask.shadow(4.0, 6.0, 0.0);
+        assert_eq!((shadow.at(14, 16), shadow.at(18, 20)), (1.0, 1.0));
+        assert_eq!((shadow.at(13, 16), shadow.at(14, 15), shadow.at(19, 20), shadow.at(18, 21)), (0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn shadow_blur_spreads_the_same_coverage() {
+        let mut mask = Mask::new(0, 0, 40, 40);
+        mask.add_rect(10, 10, 5, 5);
+        let shadow = mask.shadow(4.0, 6.0, 2.0);
+        let total: f32 = shadow.coverage.pixels().map(|p| p[0]).sum();
+        assert!((total - 25.0).abs() < 0.5, "{}", total);
+        // Soft edges either side of where the sharp shadow ends
+        assert!(shadow.at(16, 18) < 1.0 && shadow.at(16, 18) > 0.5);
+        assert!(shadow.at(20, 18) > 0.0 && shadow.at(20, 18) < 0.5);
+    }
+
+    #[test]
+    fn padding_makes_room_for_stroke_and_shadow() {
+        assert_eq!(EffectPadding::new(None, None).right, 0);
+
+        let stroke = stroke(2.0, StrokeJoin::Round);
+        let padding = EffectPadding::new(Some(&stroke), None);
+        assert_eq!((padding.left, padding.top, padding.right, padding.bottom), (2, 2, 2, 2));
+
+        // The shadow reaches blur plus stroke past the text, moved by its offset
+        let shadow = TextShadow {
+            color: "#000000".to_string(),
+            offset: ShadowOffset { x: 3.0, y: -4.5 },
+            blur: 1.0,
+            opacity: 1.0,
+        };
+        let padding = EffectPadding::new(Some(&stroke), Some(&shadow));
+        assert_eq!((padding.left, padding.top, padding.right, padding.bottom), (2, 8, 6, 2));
+    }
+}
@@ -0,0 +1,253 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Where in a template a problem was found.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Location {
+    /// JSON path of the offending value, e.g. `groups[0].layers[1].font.color`
+    pub path: String,
+    pub group: Option<String>,
+    pub layer: Option<String>,
+}
+
+impl Location {
+    /// A top-level template field, or a field relative to the layer being checked.
+    pub fn field(path: &str) -> Self {
+        Location { path: path.to_string(), ..Location::default() }
+    }
+
+    pub fn layer(group_index: usize, group: &str, layer_index: usize, layer: &str) -> Self {
+        Location {
+            path: format!("groups[{}].layers[{}]", group_index, layer_index),
+            group: Some(group.to_string()),
+            layer: Some(layer.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.layer, &self.group) {
+            (Some(layer), Some(group)) => write!(f, "Layer '{}' in group '{}' ({})", layer, group, self.path),
+            _ => write!(f, "{}", self.path),
+        }
+    }
+}
+
+/// Problems with `{{variable}}` placeholders.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum VariableError {
+    #[error("unterminated placeholder in '{0}'")]
+    Unterminated(String),
+    #[error("empty placeholder name in '{0}'")]
+    EmptyName(String),
+    #[error("missing value for variable '{0}'")]
+    Unbound(String),
+}
+
+/// Why an Illustrator or Photoshop source file couldn't be read.
+#[derive(Debug, Error)]
+pub enum SourceError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("layer metadata is not valid UTF-8: {0}")]
+    Utf8(#[from] std::string::FromUtf8Error),
+    #[error("invalid layer metadata: {0}")]
+    Metadata(#[from] serde_json::Error),
+    #[error("invalid color for layer '{layer}': {source}")]
+    LayerColor { layer: String, source: csscolorparser::ParseColorError },
+    #[error("invalid PDF: {0}")]
+    Pdf(#[from] lopdf::Error),
+    #[error("PDF has no pages")]
+    NoPages,
+    #[error("invalid PSD: {0}")]
+    Psd(#[from] psd::PsdError),
+    #[error("unexpected end of PSD data")]
+    Truncated,
+    #[error("type tool block has no text")]
+    NoText,
+}
+
+/// Why a font that was found couldn't be used.
+#[derive(Debug, Error)]
+pub enum FontError {
+    #[error("{0}")]
+    Loading(#[from] font_kit::error::FontLoadingError),
+    #[error("font data is not available")]
+    NoData,
+    #[error("unsupported font data")]
+    Unsupported,
+}
+
+/// Why a batch data file couldn't be read.
+#[derive(Debug, Error)]
+pub enum DataError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("line {line}: {source}")]
+    Json { line: usize, source: serde_json::Error },
+    #[error("line {line} is not a JSON object")]
+    NotObject { line: usize },
+    #[error("{0}")]
+    Csv(#[from] csv::Error),
+    #[error("unsupported data file type")]
+    UnsupportedType,
+}
+
+/// Why an output file couldn't be written.
+#[derive(Debug, Error)]
+pub enum OutputError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Image(#[from] image::ImageError),
+    #[error("{0}")]
+    Pdf(#[from] lopdf::Error),
+}
+
+/// A problem worth reporting that doesn't stop a template from rendering.
+#[derive(Debug, Clone, Error)]
+pub enum Warning {
+    #[error("Could not read text layers of '{path}': {source}")]
+    SourceTextLayers { path: String, source: Arc<SourceError> },
+
+    /// A placeholder with no default that the variables given don't bind.
+    #[error("Variable '{name}' has no value or default")]
+    UnboundVariable { name: String },
+
+    /// Characters of a text layer that no font in its chain has a glyph for,
+    /// which draw as nothing.
+    #[error("{at}: no font in the chain has glyphs for {}", list_chars(.chars))]
+    UncoveredChars { at: Location, chars: Vec<char> },
+}
+
+fn list_chars(chars: &[char]) -> String {
+    let listed: Vec<String> = chars.iter()
+        .map(|character| format!("'{}' (U+{:04X})", character, *character as u32))
+        .collect();
+    listed.join(", ")
+}
+
+#[derive(Debug, Error)]
+pub enum KitError {
+    #[error("Failed to read '{}': {source}", path.display())]
+    Io { path: PathBuf, source: std::io::Error },
+
+    #[error("Failed to parse template '{}': {source}", path.display())]
+    Parse { path: PathBuf, source: serde_json::Error },
+
+    /// A value that is present but not allowed, such as a non-positive size.
+    #[error("{at}: {message}")]
+    Invalid { at: Location, message: String },
+
+    /// A value left out of the template that no source layer filled in.
+    #[error("{at}: must be set in the template or source layer")]
+    Missing { at: Location },
+
+    #[error("{at}: invalid color '{value}': {source}")]
+    InvalidColor { at: Location, value: String, source: csscolorparser::ParseColorError },
+
+    #[error("{at}: font family '{family}' not found")]
+    FontNotFound { at: Location, family: String },
+
+    #[error("{at}: failed to load font '{family}': {source}")]
+    FontLoad { at: Location, family: String, source: FontError },
+
+    #[error("Failed to load font file '{}': {source}", path.display())]
+    FontFile { path: PathBuf, source: font_kit::error::FontLoadingError },
+
+    #[error("{at}: image file not found: {}", file.display())]
+    ImageNotFound { at: Location, file: PathBuf },
+
+    #[error("{at}: failed to load image '{}': {source}", file.display())]
+    ImageLoad { at: Location, file: PathBuf, source: Box<image::ImageError> },
+
+    #[error("{at}: {source}")]
+    Variable { at: Location, source: VariableError },
+
+    #[error("Unsupported source file type: {0}")]
+    UnsupportedSource(String),
+
+    #[error("Failed to load source file '{path}': {source}")]
+    SourceLoad { path: String, source: SourceError },
+
+    #[error("{at}: layer '{name}' not found in source file")]
+    SourceLayerNotFound { at: Location, name: String },
+
+    #[error("No text layer named '{0}' to override")]
+    UnknownOverride(String),
+
+    #[error("Failed to read data file '{}': {source}", path.display())]
+    Data { path: PathBuf, source: DataError },
+
+    #[error("Invalid output pattern '{pattern}': {source}")]
+    OutputPattern { pattern: String, source: VariableError },
+
+    /// A row whose values would put its output outside the pattern's directory.
+    #[error("Output path '{}' from pattern '{pattern}' leaves the pattern's directory", path.display())]
+    OutputOutside { pattern: String, path: PathBuf },
+
+    #[error("Cannot infer output format from '{}'; pass --format", .0.display())]
+    UnknownFormat(PathBuf),
+
+    #[error("Failed to write '{}': {source}", path.display())]
+    Output { path: PathBuf, source: OutputError },
+
+    #[error("Row {row}: {source}")]
+    Row { row: usize, source: Box<KitError> },
+
+    #[error("{failed} of {total} rows failed")]
+    RowsFailed { failed: usize, total: usize },
+}
+
+impl KitError {
+    /// The template location the error refers to, if any.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            KitError::Invalid { at, .. }
+            | KitError::Missing { at }
+            | KitError::InvalidColor { at, .. }
+            | KitError::FontNotFound { at, .. }
+            | KitError::FontLoad { at, .. }
+            | KitError::ImageNotFound { at, .. }
+            | KitError::ImageLoad { at, .. }
+            | KitError::Variable { at, .. }
+            | KitError::SourceLayerNotFound { at, .. } => Some(at),
+            KitError::Row { source, .. } => source.location(),
+            _ => None,
+        }
+    }
+
+    fn location_mut(&mut self) -> Option<&mut Location> {
+        match self {
+            KitError::Invalid { at, .. }
+            | KitError::Missing { at }
+            | KitError::InvalidColor { at, .. }
+            | KitError::FontNotFound { at, .. }
+            | KitError::FontLoad { at, .. }
+            | KitError::ImageNotFound { at, .. }
+            | KitError::ImageLoad { at, .. }
+            | KitError::Variable { at, .. }
+            | KitError::SourceLayerNotFound { at, .. } => Some(at),
+            KitError::Row { source, .. } => source.location_mut(),
+            _ => None,
+        }
+    }
+
+    /// Places an error raised by a layer, whose path is relative to that layer,
+    /// at the layer's position in the template.
+    pub fn within(mut self, outer: &Location) -> Self {
+        if let Some(at) = self.location_mut() {
+            at.path = match (outer.path.is_empty(), at.path.is_empty()) {
+                (_, true) => outer.path.clone(),
+                (true, false) => at.path.clone(),
+                (false, false) => format!("{}.{}", outer.path, at.path),
+            };
+            at.group = at.group.take().or_else(|| outer.group.clone());
+            at.layer = at.layer.take().or_else(|| outer.layer.clone());
+        }
+        self
+    }
+}
@@ -0,0 +1,364 @@
+use std::path::Path;
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+use crate::error::{KitError, Location};
+use crate::parse_rgba;
+
+#[derive(Deserialize, Clone)]
+pub struct GradientStop {
+    pub color: String,
+    // Position along the gradient from 0 to 1; stops without one are spread evenly
+    #[serde(default)]
+    pub offset: Option<f32>,
+}
+
+// A point as fractions of the text's box
+#[derive(Deserialize, Clone, Copy)]
+pub struct GradientCenter {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// What the glyphs of a text layer are painted with, in place of the font
+/// color. Gradients and patterns are laid over the box around the text's ink.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TextFill {
+    Solid {
+        color: String,
+    },
+    // The angle is in degrees as in CSS: 0 runs bottom to top, 90 left to right
+    LinearGradient {
+        #[serde(default = "default_angle")]
+        angle: f32,
+        stops: Vec<GradientStop>,
+    },
+    // Center and radius are fractions of the box, so the circle stretches
+    // into an ellipse with it
+    RadialGradient {
+        #[serde(default = "default_center")]
+        center: GradientCenter,
+        #[serde(default = "default_radius")]
+        radius: f32,
+        stops: Vec<GradientStop>,
+    },
+    // An image tiled from the box's top-left corner
+    Pattern {
+        source: String,
+        #[serde(default = "default_pattern_scale")]
+        scale: f32,
+    },
+}
+
+fn default_angle() -> f32 {
+    180.0
+}
+
+fn default_center() -> GradientCenter {
+    GradientCenter { x: 0.5, y: 0.5 }
+}
+
+fn default_radius() -> f32 {
+    0.5
+}
+
+fn default_pattern_scale() -> f32 {
+    1.0
+}
+
+/// A fill with its colors parsed and its image loaded, ready to paint.
+pub enum Paint {
+    Solid(Rgba<u8>),
+    Linear {
+        angle: f32,
+        stops: Vec<(f32, Rgba<u8>)>,
+    },
+    Radial {
+        center: GradientCenter,
+        radius: f32,
+        stops: Vec<(f32, Rgba<u8>)>,
+    },
+    Pattern {
+        image: RgbaImage,
+        scale: f32,
+    },
+}
+
+/// The box a fill is laid over: `(min_x, min_y, max_x, max_y)` in canvas pixels.
+pub type FillBox = (f32, f32, f32, f32);
+
+impl TextFill {
+    /// Every string setting of the fill with its path under `font.fill`.
+    pub fn strings_mut(&mut self) -> Vec<(String, &mut String)> {
+        match self {
+            TextFill::Solid { color } => vec![("color".to_string(), color)],
+            TextFill::LinearGradient { stops, .. } | TextFill::RadialGradient { stops, .. } => stops.iter_mut()
+                .enumerate()
+                .map(|(index, stop)| (format!("stops[{}].color", index), &mut stop.color))
+                .collect(),
+            TextFill::Pattern { source, .. } => vec![("source".to_string(), source)],
+        }
+    }
+
+    /// The image file the fill reads, for a pattern.
+    pub fn file_mut(&mut self) -> Option<&mut String> {
+        match self {
+            TextFill::Pattern { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), KitError> {
+        let invalid = |field: &str, message: &str| KitError::Invalid {
+            at: Location::field(&format!("font.fill.{}", field)),
+            message: message.to_string(),
+        };
+        match self {
+            TextFill::Solid { color } => {
+                parse_rgba(color, "font.fill.color")?;
+            }
+            TextFill::LinearGradient { stops, .. } => validate_stops(stops)?,
+            TextFill::RadialGradient { radius, stops, .. } => {
+                if *radius <= 0.0 {
+                    return Err(invalid("radius", "gradient radius must be positive"));
+                }
+                validate_stops(stops)?;
+            }
+            TextFill::Pattern { source, scale } => {
+                if *scale <= 0.0 {
+                    return Err(invalid("scale", "pattern scale must be positive"));
+                }
+                if source.is_empty() {
+                    return Err(KitError::Missing { at: Location::field("font.fill.source") });
+                }
+                if !Path::new(source).exists() {
+                    return Err(KitError::ImageNotFound { at: Location::field("font.fill.source"), file: source.into() });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn paint(&self) -> Result<Paint, KitError> {
+        Ok(match self {
+            TextFill::Solid { color } => Paint::Solid(parse_rgba(color, "font.fill.color")?),
+            TextFill::LinearGradient { angle, stops } => Paint::Linear { angle: *angle, stops: resolve_stops(stops)? },
+            TextFill::RadialGradient { center, radius, stops } => Paint::Radial {
+                center: *center,
+                radius: *radius,
+                stops: resolve_stops(stops)?,
+            },
+            TextFill::Pattern { source, scale } => {
+                let image = image::open(source).map_err(|error| KitError::ImageLoad {
+                    at: Location::field("font.fill.source"),
+                    file: source.into(),
+                    source: Box::new(error),
+                })?;
+                Paint::Pattern { image: image.to_rgba8(), scale: *scale }
+            }
+        })
+    }
+}
+
+fn validate_stops(stops: &[GradientStop]) -> Result<(), KitError> {
+    if stops.len() < 2 {
+        return Err(KitError::Invalid {
+            at: Location::field("font.fill.stops"),
+            message: "a gradient needs at least two stops".to_string(),
+        });
+    }
+    for (index, stop) in stops.iter().enumerate() {
+        parse_rgba(&stop.color, &format!("font.fill.stops[{}].color", index))?;
+        if let Some(offset) = stop.offset
+            && !(0.0..=1.0).contains(&offset)
+        {
+            return Err(KitError::Invalid {
+                at: Location::field(&format!("font.fill.stops[{}].offset", index)),
+                message: "stop offset must be between 0 and 1".to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Parses the stop colors and settles their offsets. As in CSS, a stop left
+// without an offset is spread evenly, and one before the stop ahead of it is
+// moved up to that stop.
+fn resolve_stops(stops: &[GradientStop]) -> Result<Vec<(f32, Rgba<u8>)>, KitError> {
+    let last = stops.len().saturating_sub(1).max(1) as f32;
+    let mut resolved: Vec<(f32, Rgba<u8>)> = Vec::new();
+    for (index, stop) in stops.iter().enumerate() {
+        let color = parse_rgba(&stop.color, &format!("font.fill.stops[{}].color", index))?;
+        let offset = stop.offset.unwrap_or(index as f32 / last);
+        let floor = resolved.last().map_or(0.0, |&(previous, _)| previous);
+        resolved.push((offset.max(floor), color));
+    }
+    Ok(resolved)
+}
+
+/// The color at `t` along a gradient, holding the end colors past either end.
+pub fn stop_color(stops: &[(f32, Rgba<u8>)], t: f32) -> Rgba<u8> {
+    let Some(&(first_offset, first)) = stops.first() else {
+        return Rgba([0, 0, 0, 0]);
+    };
+    if t <= first_offset {
+        return first;
+    }
+    for pair in stops.windows(2) {
+        let ((start, from), (end, to)) = (pair[0], pair[1]);
+        if t <= end {
+            let amount = if end > start { (t - start) / (end - start) } else { 1.0 };
+            let mix = |channel: usize| (from[channel] as f32 + (to[channel] as f32 - from[channel] as f32) * amount).round() as u8;
+            return Rgba([mix(0), mix(1), mix(2), mix(3)]);
+        }
+    }
+    stops[stops.len() - 1].1
+}
+
+/// Start and end of a linear gradient's line across `area`. As in CSS, the
+/// line runs through the middle at `angle` and is long enough that the
+/// corners get the end colors.
+pub fn linear_line(angle: f32, area: FillBox) -> ((f32, f32), (f32, f32)) {
+    let (min_x, min_y, max_x, max_y) = area;
+    let (width, height) = (max_x - min_x, max_y - min_y);
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let half = (width * sin.abs() + height * cos.abs()) / 2.0;
+    let center = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+    // Canvas y points down, so 0 degrees points up the canvas
+    let direction = (sin, -cos);
+    (
+        (center.0 - direction.0 * half, center.1 - direction.1 * half),
+        (center.0 + direction.0 * half, center.1 + direction.1 * half),
+    )
+}
+
+/// Center and radii of a radial gradient's ellipse over `area`.
+pub fn radial_ellipse(center: GradientCenter, radius: f32, area: FillBox) -> ((f32, f32), (f32, f32)) {
+    let (min_x, min_y, max_x, max_y) = area;
+    let (width, height) = (max_x - min_x, max_y - min_y);
+    (
+        (min_x + center.x * width, min_y + center.y * height),
+        ((radius * width).max(f32::EPSILON), (radius * height).max(f32::EPSILON)),
+    )
+}
+
+impl Paint {
+    /// The color of the pixel whose center is `(x, y)` on the canvas, with
+    /// the fill laid over `area`.
+    pub fn color_at(&self, x: f32, y: f32, area: FillBox) -> Rgba<u8> {
+        match self {
+            Paint::Solid(color) => *color,
+            Paint::Linear { angle, stops } => {
+                let ((x0, y0), (x1, y1)) = linear_line(*angle, area);
+                let (dx, dy) = (x1 - x0, y1 - y0);
+                let length = dx * dx + dy * dy;
+                let t = if length > 0.0 { ((x - x0) * dx + (y - y0) * dy) / length } else { 0.0 };
+                stop_color(stops, t)
+            }
+            Paint::Radial { center, radius, stops } => {
+                let ((cx, cy), (rx, ry)) = radial_ellipse(*center, *radius, area);
+                stop_color(stops, ((x - cx) / rx).hypot((y - cy) / ry))
+            }
+            Paint::Pattern { image, scale } => {
+                let (width, height) = (image.width() as i64, image.height() as i64);
+                if width == 0 || height == 0 {
+                    return Rgba([0, 0, 0, 0]);
+                }
+                let px = (((x - area.0) / scale).floor() as i64).rem_euclid(width);
+                let py = (((y - area.1) / scale).floor() as i64).rem_euclid(height);
+                *image.get_pixel(px as u32, py as u32)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    fn stop(color: &str, offset: Option<f32>) -> GradientStop {
+        GradientStop { color: color.to_string(), offset }
+    }
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3
+    }
+
+    #[test]
+    fn stop_color_blends_and_holds_the_ends() {
+        let stops = [(0.25, BLACK), (0.75, WHITE)];
+        assert_eq!(stop_color(&stops, 0.0), BLACK);
+        assert_eq!(stop_color(&stops, 0.5), Rgba([128, 128, 128, 255]));
+        assert_eq!(stop_color(&stops, 1.0), WHITE);
+        assert_eq!(stop_color(&[], 0.5), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn stop_color_switches_at_coinciding_stops() {
+        let red = Rgba([255, 0, 0, 255]);
+        let stops = [(0.0, BLACK), (0.5, BLACK), (0.5, red), (1.0, red)];
+        assert_eq!(stop_color(&stops, 0.49), BLACK);
+        assert_eq!(stop_color(&stops, 0.51), red);
+    }
+
+    #[test]
+    fn stops_spread_evenly_and_never_go_back() {
+        let resolved = resolve_stops(&[stop("#000", None), stop("#fff", Some(0.8)), stop("#000", Some(0.2)), stop("#fff", None)]).unwrap();
+        let offsets: Vec<f32> = resolved.iter().map(|&(offset, _)| offset).collect();
+        assert_eq!(offsets, vec![0.0, 0.8, 0.8, 1.0]);
+        assert_eq!(resolved[1].1, WHITE);
+    }
+
+    #[test]
+    fn linear_line_follows_css_angles() {
+        let area = (0.0, 0.0, 100.0, 50.0);
+        // 180 runs top to bottom, 90 left to right
+        assert!(close(linear_line(180.0, area).0, (50.0, 0.0)));
+        assert!(close(linear_line(180.0, area).1, (50.0, 50.0)));
+        assert!(close(linear_line(90.0, area).0, (0.0, 25.0)));
+        assert!(close(linear_line(90.0, area).1, (100.0, 25.0)));
+        assert!(close(linear_line(0.0, area).1, (50.0, 0.0)));
+    }
+
+    #[test]
+    fn linear_line_reaches_the_corners_on_a_diagonal() {
+        let area = (0.0, 0.0, 100.0, 100.0);
+        let paint = Paint::Linear { angle: 135.0, stops: vec![(0.0, BLACK), (1.0, WHITE)] };
+        assert_eq!(paint.color_at(0.0, 0.0, area), BLACK);
+        assert_eq!(paint.color_at(100.0, 100.0, area), WHITE);
+        assert_eq!(paint.color_at(100.0, 0.0, area), Rgba([128, 128, 128, 255]));
+    }
+
+    #[test]
+    fn radial_ellipse_stretches_with_the_box() {
+        let center = GradientCenter { x: 0.5, y: 0.25 };
+        let (middle, radii) = radial_ellipse(center, 0.5, (10.0, 20.0, 210.0, 120.0));
+        assert!(close(middle, (110.0, 45.0)));
+        assert!(close(radii, (100.0, 50.0)));
+    }
+
+    #[test]
+    fn radial_color_grows_from_the_center() {
+        let area = (0.0, 0.0, 200.0, 100.0);
+        let paint = Paint::Radial { center: default_center(), radius: 0.5, stops: vec![(0.0, BLACK), (1.0, WHITE)] };
+        assert_eq!(paint.color_at(100.0, 50.0, area), BLACK);
+        assert_eq!(paint.color_at(150.0, 50.0, area), Rgba([128, 128, 128, 255]));
+        assert_eq!(paint.color_at(100.0, 75.0, area), Rgba([128, 128, 128, 255]));
+        assert_eq!(paint.color_at(0.0, 0.0, area), WHITE);
+    }
+
+    #[test]
+    fn pattern_tiles_from_the_box_corner() {
+        let mut image = RgbaImage::from_pixel(2, 1, BLACK);
+        image.put_pixel(1, 0, WHITE);
+        let paint = Paint::Pattern { image, scale: 2.0 };
+        let area = (10.0, 10.0, 100.0, 100.0);
+        assert_eq!(paint.color_at(10.5, 10.5, area), BLACK);
+        assert_eq!(paint.color_at(12.5, 10.5, area), WHITE);
+        assert_eq!(paint.color_at(14.5, 30.5, area), BLACK);
+        assert_eq!(paint.color_at(8.5, 10.5, area), WHITE);
+    }
+}
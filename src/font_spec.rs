@@ -0,0 +1,268 @@
+use font_kit::properties::{Properties, Style, Weight};
+use image::Rgba;
+use rusttype::Font as RustFont;
+use serde::{Deserialize, Deserializer};
+use crate::error::{FontError, KitError, Location};
+use crate::fill::{Paint, TextFill};
+use crate::fonts::{FontLookupError, FontRegistry};
+use crate::parse_rgba;
+use crate::shaping::LoadedFont;
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FontWeight {
+    Normal,
+    Bold,
+    #[serde(rename = "100")]
+    Weight100,
+    #[serde(rename = "200")]
+    Weight200,
+    #[serde(rename = "300")]
+    Weight300,
+    #[serde(rename = "400")]
+    Weight400,
+    #[serde(rename = "500")]
+    Weight500,
+    #[serde(rename = "600")]
+    Weight600,
+    #[serde(rename = "700")]
+    Weight700,
+    #[serde(rename = "800")]
+    Weight800,
+    #[serde(rename = "900")]
+    Weight900,
+}
+
+impl FontWeight {
+    pub(crate) fn to_font_kit_weight(&self) -> Weight {
+        match self {
+            FontWeight::Normal => Weight::NORMAL,
+            FontWeight::Bold => Weight::BOLD,
+            FontWeight::Weight100 => Weight::THIN,
+            FontWeight::Weight200 => Weight::EXTRA_LIGHT,
+            FontWeight::Weight300 => Weight::LIGHT,
+            FontWeight::Weight400 => Weight::NORMAL,
+            FontWeight::Weight500 => Weight::MEDIUM,
+            FontWeight::Weight600 => Weight::SEMIBOLD,
+            FontWeight::Weight700 => Weight::BOLD,
+            FontWeight::Weight800 => Weight::EXTRA_BOLD,
+            FontWeight::Weight900 => Weight::BLACK,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+impl FontStyle {
+    pub(crate) fn to_font_kit_style(&self) -> Style {
+        match self {
+            FontStyle::Normal => Style::Normal,
+            FontStyle::Italic => Style::Italic,
+            FontStyle::Oblique => Style::Oblique,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FontDecoration {
+    None,
+    Underline,
+    LineThrough,
+    Overline,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FontVariant {
+    Normal,
+    // Lowercase letters drawn as smaller capitals
+    SmallCaps,
+}
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct FontSpec {
+    // Family, size and color may be left out when a source layer supplies them.
+    // A list of families is a fallback chain for glyphs the first one lacks.
+    #[serde(default, deserialize_with = "deserialize_families")]
+    pub(crate) family: Vec<String>,
+    #[serde(default)]
+    pub(crate) size: f32,
+    #[serde(default)]
+    pub(crate) color: String,
+    #[serde(default = "default_font_weight")]
+    pub(crate) weight: FontWeight,
+    #[serde(default = "default_font_style")]
+    pub(crate) style: FontStyle,
+    #[serde(default = "default_font_decoration")]
+    pub(crate) decoration: FontDecoration,
+    #[serde(default = "default_font_variant")]
+    pub(crate) variant: FontVariant,
+    // A gradient or pattern painted instead of `color`
+    #[serde(default)]
+    pub(crate) fill: Option<TextFill>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum FamilyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+fn deserialize_families<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Ok(match FamilyList::deserialize(deserializer)? {
+        FamilyList::One(family) if family.is_empty() => Vec::new(),
+        FamilyList::One(family) => vec![family],
+        FamilyList::Many(families) => families,
+    })
+}
+
+fn default_font_weight() -> FontWeight {
+    FontWeight::Normal
+}
+
+fn default_font_style() -> FontStyle {
+    FontStyle::Normal
+}
+
+fn default_font_decoration() -> FontDecoration {
+    FontDecoration::None
+}
+
+fn default_font_variant() -> FontVariant {
+    FontVariant::Normal
+}
+
+impl FontSpec {
+    pub(crate) fn validate(&self, fonts: &FontRegistry) -> Result<(), KitError> {
+        if self.family.is_empty() {
+            return Err(KitError::Missing { at: Location::field("font.family") });
+        }
+
+        match &self.fill {
+            Some(fill) => fill.validate()?,
+            None if self.color.is_empty() => return Err(KitError::Missing { at: Location::field("font.color") }),
+            None => {}
+        }
+
+        // Validate color format
+        if !self.color.is_empty() {
+            self.rgba_color()?;
+        }
+        
+        // Validate font size
+        if self.size <= 0.0 {
+            return Err(KitError::Invalid {
+                at: Location::field("font.size"),
+                message: "font size must be positive".to_string(),
+            });
+        }
+        
+        // Every family in the chain must exist and load
+        self.load_fonts(fonts)?;
+        
+        Ok(())
+    }
+
+    /// Every string setting of the font with its path under `font`.
+    pub(crate) fn strings_mut(&mut self) -> Vec<(String, &mut String)> {
+        let single = self.family.len() == 1;
+        let mut strings: Vec<(String, &mut String)> = self.family.iter_mut()
+            .enumerate()
+            .map(|(index, family)| match single {
+                true => ("family".to_string(), family),
+                false => (format!("family[{}]", index), family),
+            })
+            .collect();
+        strings.push(("color".to_string(), &mut self.color));
+        if let Some(fill) = &mut self.fill {
+            strings.extend(fill.strings_mut().into_iter().map(|(path, value)| (format!("fill.{}", path), value)));
+        }
+        strings
+    }
+
+    // A single family is reported at `font.family`, one from a chain by its index
+    pub(crate) fn family_location(&self, index: usize) -> Location {
+        if self.family.len() > 1 {
+            Location::field(&format!("font.family[{}]", index))
+        } else {
+            Location::field("font.family")
+        }
+    }
+
+    fn font_not_found(&self, index: usize) -> KitError {
+        KitError::FontNotFound { at: self.family_location(index), family: self.family[index].clone() }
+    }
+
+    fn font_load_error(&self, index: usize, source: FontError) -> KitError {
+        KitError::FontLoad {
+            at: self.family_location(index),
+            family: self.family[index].clone(),
+            source,
+        }
+    }
+
+    /// Loads the fallback chain, first family first. Glyphs are drawn from the
+    /// first font in the chain that has them.
+    pub(crate) fn load_fonts(&self, fonts: &FontRegistry) -> Result<Vec<LoadedFont>, KitError> {
+        if self.family.is_empty() {
+            return Err(KitError::Missing { at: Location::field("font.family") });
+        }
+        (0..self.family.len()).map(|index| self.load_family(index, fonts)).collect()
+    }
+
+    pub(crate) fn load_family(&self, index: usize, fonts: &FontRegistry) -> Result<LoadedFont, KitError> {
+        let properties = Properties {
+            weight: self.weight.to_font_kit_weight(),
+            style: self.style.to_font_kit_style(),
+            ..Properties::default()
+        };
+
+        fonts.load(&self.family[index], &properties).map_err(|e| match e {
+            FontLookupError::NotFound => self.font_not_found(index),
+            FontLookupError::Load(source) => self.font_load_error(index, source),
+        })
+    }
+
+    /// Where the decoration line goes for a run of text at `(x, y)`, as
+    /// `(line_y, thickness)`, or `None` when the font is undecorated.
+    pub(crate) fn decoration_line(&self, y: u32, height: u32) -> Option<(u32, u32)> {
+        let line_thickness = (self.size / 16.0).max(1.0) as u32;
+        
+        match self.decoration {
+            FontDecoration::None => None,
+            // Draw underline at the bottom of text
+            FontDecoration::Underline => Some((y + height + line_thickness, line_thickness)),
+            // Draw line through middle of text
+            FontDecoration::LineThrough => Some((y + (height / 2), line_thickness)),
+            // Draw line above text
+            FontDecoration::Overline => Some((y.saturating_sub(line_thickness * 2), line_thickness)),
+        }
+    }
+
+    /// The font size as an em size. `size` is the pixel height from ascent to
+    /// descent, which is how rusttype scales, while PDF and SVG size by the em.
+    pub(crate) fn em_size(&self, font: &RustFont) -> f32 {
+        let v_metrics = font.v_metrics_unscaled();
+        self.size * font.units_per_em() as f32 / (v_metrics.ascent - v_metrics.descent)
+    }
+
+    fn rgba_color(&self) -> Result<Rgba<u8>, KitError> {
+        parse_rgba(&self.color, "font.color")
+    }
+
+    /// What the glyphs are painted with: the fill if there is one, else the color.
+    pub(crate) fn paint(&self) -> Result<Paint, KitError> {
+        match &self.fill {
+            Some(fill) => fill.paint(),
+            None => Ok(Paint::Solid(self.rgba_color()?)),
+        }
+    }
+}
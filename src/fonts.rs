@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use font_kit::family_name::FamilyName;
+use font_kit::file_type::FileType;
+use font_kit::font::Font;
+use font_kit::handle::Handle;
+use font_kit::properties::{Properties, Style};
+use font_kit::source::SystemSource;
+use font_kit::sources::mem::MemSource;
+use crate::error::{FontError, KitError};
+use crate::shaping::LoadedFont;
+
+// Extensions of the font files picked up from a font directory
+const FONT_EXTENSIONS: [&str; 4] = ["ttf", "otf", "ttc", "otc"];
+
+/// Why a font could not be loaded from the registry.
+pub enum FontLookupError {
+    NotFound,
+    Load(FontError),
+}
+
+// Font-kit's weight and style aren't hashable, so the cache keys on their bits
+#[derive(PartialEq, Eq, Hash)]
+struct FontKey {
+    family: String,
+    weight: u32,
+    style: u8,
+}
+
+impl FontKey {
+    fn new(family: &str, properties: &Properties) -> Self {
+        FontKey {
+            family: family.to_string(),
+            weight: properties.weight.0.to_bits(),
+            style: match properties.style {
+                Style::Normal => 0,
+                Style::Italic => 1,
+                Style::Oblique => 2,
+            },
+        }
+    }
+}
+
+/// Where text layers find their fonts: the font files in the configured
+/// directories, indexed by family, weight and style, and then, when allowed,
+/// the fonts installed on the system. Loaded fonts are cached for as long as
+/// the registry lives, which is shared by every render of a template.
+pub struct FontRegistry {
+    directory_fonts: MemSource,
+    system_fonts: bool,
+    loaded: Mutex<HashMap<FontKey, LoadedFont>>,
+}
+
+impl Default for FontRegistry {
+    // With no directories configured, fonts come from the system alone
+    fn default() -> Self {
+        FontRegistry { directory_fonts: MemSource::empty(), system_fonts: true, loaded: Mutex::default() }
+    }
+}
+
+impl FontRegistry {
+    pub fn new(directories: &[PathBuf], system_fonts: bool) -> Result<Self, KitError> {
+        let mut directory_fonts = MemSource::empty();
+        for directory in directories {
+            for (path, handle) in font_handles(directory)? {
+                directory_fonts.add_font(handle)
+                    .map_err(|source| KitError::FontFile { path, source })?;
+            }
+        }
+        Ok(FontRegistry { directory_fonts, system_fonts, loaded: Mutex::default() })
+    }
+
+    /// Loads the closest match for `family` with the given weight and style,
+    /// looking it up only the first time it is asked for.
+    pub fn load(&self, family: &str, properties: &Properties) -> Result<LoadedFont, FontLookupError> {
+        let mut loaded = self.loaded.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let key = FontKey::new(family, properties);
+        if let Some(font) = loaded.get(&key) {
+            return Ok(font.clone());
+        }
+
+        let font = self.select(family, properties)
+            .ok_or(FontLookupError::NotFound)?
+            .load()
+            .map_err(|e| FontLookupError::Load(e.into()))?;
+        let font_data = font.copy_font_data()
+            .ok_or(FontLookupError::Load(FontError::NoData))?;
+        let font = LoadedFont::from_data(font_data.to_vec())
+            .ok_or(FontLookupError::Load(FontError::Unsupported))?;
+
+        loaded.insert(key, font.clone());
+        Ok(font)
+    }
+
+    /// The closest match for `family` with the given weight and style, from the
+    /// font directories first.
+    fn select(&self, family: &str, properties: &Properties) -> Option<Handle> {
+        let families = [FamilyName::Title(family.to_string())];
+        self.directory_fonts.select_best_match(&families, properties).ok()
+            .or_else(|| {
+                if self.system_fonts {
+                    SystemSource::new().select_best_match(&families, properties).ok()
+                } else {
+                    None
+                }
+            })
+    }
+}
+
+/// Every font in `directory`, one handle per face of a collection file.
+fn font_handles(directory: &Path) -> Result<Vec<(PathBuf, Handle)>, KitError> {
+    let entries = std::fs::read_dir(directory)
+        .map_err(|source| KitError::Io { path: directory.to_path_buf(), source })?;
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|source| KitError::Io { path: directory.to_path_buf(), source })?.path();
+        let is_font = path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| FONT_EXTENSIONS.contains(&extension.to_lowercase().as_str()));
+        if is_font {
+            paths.push(path);
+        }
+    }
+    // Directory order varies between machines; keep the index stable
+    paths.sort();
+
+    let mut handles = Vec::new();
+    for path in paths {
+        let faces = match Font::analyze_path(&path) {
+            Ok(FileType::Single) => 1,
+            Ok(FileType::Collection(count)) => count,
+            Err(source) => return Err(KitError::FontFile { path, source }),
+        };
+        for index in 0..faces {
+            handles.push((path.clone(), Handle::from_path(path.clone(), index)));
+        }
+    }
+    Ok(handles)
+}
@@ -0,0 +1,252 @@
+use std::path::Path;
+use image::{DynamicImage, RgbaImage};
+use serde::Deserialize;
+use crate::compositing::{self, BlendMode};
+use crate::error::{KitError, Location};
+use crate::fonts::FontRegistry;
+use crate::layer_trait::SourceLayer;
+use crate::layout::{LayerDimensions, Position};
+use crate::source::SourceData;
+use crate::template::resolve_path;
+use crate::text_layer::TextLayer;
+use crate::variables::{self, Variables};
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct LayerInfo {
+    pub(crate) name: String,
+    #[allow(dead_code)]
+    #[serde(flatten)]
+    pub(crate) position: Option<Position>,
+    // Multiplies the alpha of everything the layer draws
+    #[serde(default = "default_opacity")]
+    pub(crate) opacity: f32,
+    #[serde(default = "default_blend_mode")]
+    pub(crate) blend_mode: BlendMode,
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+fn default_blend_mode() -> BlendMode {
+    BlendMode::Normal
+}
+
+impl LayerInfo {
+    pub(crate) fn validate(&self) -> Result<(), KitError> {
+        if !(0.0..=1.0).contains(&self.opacity) {
+            return Err(KitError::Invalid {
+                at: Location::field("opacity"),
+                message: "opacity must be between 0 and 1".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+pub(crate) trait GetDimensions {
+    fn get_dimensions(&self, fonts: &FontRegistry) -> Result<LayerDimensions, KitError>;
+}
+
+impl GetDimensions for Layer {
+    fn get_dimensions(&self, fonts: &FontRegistry) -> Result<LayerDimensions, KitError> {
+        match self {
+            Layer::Text(text_layer) => {
+                let fonts = text_layer.font.load_fonts(fonts)?;
+                Ok(text_layer.measure(&fonts))
+            },
+            Layer::Image(image_layer) => {
+                let img = image_layer.load_image()?;
+                let width = (img.width() as f32 * image_layer.scale) as u32;
+                let height = (img.height() as f32 * image_layer.scale) as u32;
+                Ok(LayerDimensions { width, height })
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct ImageLayer {
+    #[serde(rename = "type")]
+    pub(crate) layer_type: String,
+    #[serde(flatten)]
+    pub(crate) info: LayerInfo,
+    // Falls back to the source layer's pixels when left out
+    #[serde(default)]
+    pub(crate) source: String,
+    pub(crate) scale: f32,
+    #[serde(skip)]
+    pub(crate) pixels: Option<RgbaImage>,
+}
+
+impl ImageLayer {
+    pub(crate) fn validate(&self) -> Result<(), KitError> {
+        if self.layer_type != "image" {
+            return Err(KitError::Invalid {
+                at: Location::field("type"),
+                message: format!("expected layer type 'image', got '{}'", self.layer_type),
+            });
+        }
+        
+        if self.scale <= 0.0 {
+            return Err(KitError::Invalid {
+                at: Location::field("scale"),
+                message: "scale must be positive".to_string(),
+            });
+        }
+        
+        if self.source.is_empty() {
+            if self.pixels.is_none() {
+                return Err(KitError::Missing { at: Location::field("source") });
+            }
+        } else if !std::path::Path::new(&self.source).exists() {
+            return Err(KitError::ImageNotFound { at: Location::field("source"), file: self.source.clone().into() });
+        }
+        
+        Ok(())
+    }
+
+    /// Uses the source layer's pixels when the template gives no image file.
+    pub(crate) fn apply_source(&mut self, source_layer: &dyn SourceLayer) {
+        if self.source.is_empty() {
+            self.pixels = source_layer.pixels().cloned();
+        }
+    }
+
+    pub(crate) fn load_image(&self) -> Result<DynamicImage, KitError> {
+        match &self.pixels {
+            Some(pixels) => Ok(DynamicImage::ImageRgba8(pixels.clone())),
+            None => image::open(&self.source).map_err(|source| KitError::ImageLoad {
+                at: Location::field("source"),
+                file: self.source.clone().into(),
+                source: Box::new(source),
+            }),
+        }
+    }
+
+    pub(crate) fn draw(&self, canvas: &mut RgbaImage, position: &Position) -> Result<(), KitError> {
+        let mut overlay = self.load_image()?;
+        
+        // Apply scaling if needed
+        if self.scale != 1.0 {
+            let new_width = (overlay.width() as f32 * self.scale) as u32;
+            let new_height = (overlay.height() as f32 * self.scale) as u32;
+            overlay = overlay.resize(new_width, new_height, image::imageops::FilterType::Lanczos3);
+        }
+
+        compositing::draw(
+            canvas,
+            &overlay.to_rgba8(),
+            position.x as i32,
+            position.y as i32,
+            self.info.opacity,
+            self.info.blend_mode,
+        );
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub(crate) enum Layer {
+    // Boxed, as text layers carry far more settings than images
+    Text(Box<TextLayer>),
+    Image(ImageLayer),
+}
+
+impl Layer {
+    pub(crate) fn info(&self) -> &LayerInfo {
+        match self {
+            Layer::Text(text) => &text.info,
+            Layer::Image(image) => &image.info,
+        }
+    }
+
+    pub(crate) fn validate(&self, fonts: &FontRegistry) -> Result<(), KitError> {
+        match self {
+            Layer::Text(text) => text.validate(fonts)?,
+            Layer::Image(image) => image.validate()?,
+        }
+        self.info().validate()
+    }
+
+    /// Every string setting of the layer with its path: the text, font, colors
+    /// and files. Placeholders may appear in any of them.
+    pub(crate) fn strings_mut(&mut self) -> Vec<(String, &mut String)> {
+        match self {
+            Layer::Text(text) => text.strings_mut(),
+            Layer::Image(image) => vec![("source".to_string(), &mut image.source)],
+        }
+    }
+
+    /// Fills in unset fields from the source layer of the same name, if any.
+    pub(crate) fn apply_source(&mut self, source: &SourceData) {
+        match self {
+            Layer::Text(text) => {
+                if let Some(source_layer) = source.get_layer_by_name(&text.info.name) {
+                    text.apply_source(source_layer);
+                }
+            }
+            Layer::Image(image) => {
+                if let Some(source_layer) = source.get_layer_by_name(&image.info.name) {
+                    image.apply_source(source_layer);
+                }
+            }
+        }
+    }
+
+    /// The image files the layer reads.
+    fn files_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Layer::Text(text) => text.font.fill.iter_mut().filter_map(|fill| fill.file_mut()).collect(),
+            Layer::Image(image) => vec![&mut image.source],
+        }
+    }
+
+    /// Returns a copy of the layer with unset fields taken from the matching source
+    /// layer, `{{variable}}` placeholders filled in and files taken from `base_dir`.
+    pub(crate) fn resolve(
+        &self,
+        variables: &Variables,
+        source: Option<&SourceData>,
+        fonts: &FontRegistry,
+        base_dir: &Path,
+    ) -> Result<Layer, KitError> {
+        let mut layer = self.clone();
+        if let Some(source) = source {
+            layer.apply_source(source);
+        }
+
+        // After the source, whose text may carry placeholders too
+        for (path, value) in layer.strings_mut() {
+            *value = variables::substitute(value, variables)
+                .map_err(|source| KitError::Variable { at: Location::field(&path), source })?;
+        }
+        // Once the placeholders are filled in, so values can name absolute files
+        for file in layer.files_mut() {
+            *file = resolve_path(base_dir, Path::new(file)).to_string_lossy().into_owned();
+        }
+
+        if let Layer::Text(text) = &mut layer {
+            // Layout can't fail, so bad path and warp data is caught here
+            if let Some(path) = &text.path {
+                path.validate()?;
+            }
+            if let Some(warp) = &text.warp {
+                warp.validate()?;
+            }
+            // Fit last, once the final text is known
+            text.apply_fit(fonts)?;
+        }
+        Ok(layer)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TemplateLayer {
+    Text(Box<TextLayer>),
+    Image(ImageLayer),
+}
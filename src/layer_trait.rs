@@ -1,7 +1,17 @@
+use image::RgbaImage;
+
+/// A named layer of a design file. Bounds and font size are in pixels of the
+/// rendered canvas once the file is loaded as `SourceData`.
 pub trait SourceLayer {
     fn name(&self) -> &str;
     fn content(&self) -> &str;
     fn bounds(&self) -> Option<(f64, f64, f64, f64)>;
     fn font_name(&self) -> Option<&str>;
+    fn font_size(&self) -> Option<f32>;
     fn color(&self) -> Option<(u8, u8, u8)>;
-} 
\ No newline at end of file
+
+    /// Rendered pixels of the layer, for sources that carry raster data.
+    fn pixels(&self) -> Option<&RgbaImage> {
+        None
+    }
+}
@@ -0,0 +1,415 @@
+use std::path::Path;
+use serde::Deserialize;
+use crate::error::{KitError, Location};
+use crate::fonts::FontRegistry;
+use crate::layer::{GetDimensions, Layer, LayerInfo};
+use crate::source::SourceData;
+use crate::variables::Variables;
+
+#[allow(dead_code)]
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum HorizontalAlign {
+    Left,
+    Center,
+    Right,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum VerticalAlign {
+    Top,
+    Middle,
+    Bottom,
+    Below,
+    Above,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RelativeTo {
+    Canvas,
+    Layer(String),
+}
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct Position {
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    #[serde(default = "default_relative_to")]
+    pub(crate) relative_to: RelativeTo,
+}
+
+fn default_relative_to() -> RelativeTo {
+    RelativeTo::Canvas
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LayoutType {
+    Vertical,
+    Horizontal,
+    Grid,
+}
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct GroupPosition {
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+}
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct DistributionConfig {
+    #[serde(default)]
+    pub(crate) bounds: Option<DistributionBounds>,
+}
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct DistributionBounds {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum GroupAlignment {
+    Left,
+    Center,
+    Right,
+    Top,
+    Bottom,
+}
+
+fn default_group_alignment() -> GroupAlignment {
+    GroupAlignment::Left
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum GroupJustification {
+    Start,
+    Center,
+    End,
+    SpaceBetween,
+    SpaceAround,
+    SpaceEvenly,
+}
+
+fn default_group_justification() -> GroupJustification {
+    GroupJustification::Start
+}
+
+fn default_spacing() -> u32 {
+    0
+}
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct GroupLayout {
+    #[serde(rename = "type")]
+    pub(crate) layout_type: LayoutType,
+    pub(crate) position: GroupPosition,
+    #[serde(default = "default_spacing")]
+    pub(crate) spacing: u32,
+    #[serde(default = "default_columns")]
+    pub(crate) columns: u32,
+    #[serde(default)]
+    pub(crate) distribution: Option<DistributionConfig>,
+    #[serde(default = "default_group_alignment")]
+    pub(crate) alignment: GroupAlignment,
+    #[serde(default = "default_group_justification")]
+    pub(crate) justification: GroupJustification,
+}
+
+fn default_columns() -> u32 {
+    1
+}
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct Group {
+    pub(crate) name: String,
+    pub(crate) layout: GroupLayout,
+    pub(crate) layers: Vec<Layer>,
+}
+
+// Helper struct to store layer dimensions
+pub(crate) struct LayerDimensions {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+impl Group {
+    pub(crate) fn calculate_positions(&self, layers_info: &[(LayerDimensions, &LayerInfo)]) -> Vec<Position> {
+        let mut positions = Vec::new();
+        let current_x = self.layout.position.x;
+        let current_y = self.layout.position.y;
+
+        // Calculate total dimensions
+        let (total_width, total_height) = match self.layout.layout_type {
+            LayoutType::Horizontal => {
+                let width = layers_info.iter()
+                    .map(|(dims, _)| dims.width)
+                    .sum::<u32>() + (layers_info.len().saturating_sub(1) as u32 * self.layout.spacing);
+                let height = layers_info.iter()
+                    .map(|(dims, _)| dims.height)
+                    .max()
+                    .unwrap_or(0);
+                (width, height)
+            },
+            LayoutType::Grid => {
+                let columns = self.layout.columns as usize;
+                let rows = layers_info.len().div_ceil(columns);
+                
+                let max_width_per_column: Vec<u32> = (0..columns)
+                    .map(|col| {
+                        layers_info.iter()
+                            .skip(col)
+                            .step_by(columns)
+                            .map(|(dims, _)| dims.width)
+                            .max()
+                            .unwrap_or(0)
+                    })
+                    .collect();
+                
+                let max_height_per_row: Vec<u32> = (0..rows)
+                    .map(|row| {
+                        layers_info.iter()
+                            .skip(row * columns)
+                            .take(columns)
+                            .map(|(dims, _)| dims.height)
+                            .max()
+                            .unwrap_or(0)
+                    })
+                    .collect();
+
+                let width = max_width_per_column.iter().sum::<u32>() + 
+                    (columns.saturating_sub(1) as u32 * self.layout.spacing);
+                let height = max_height_per_row.iter().sum::<u32>() + 
+                    (rows.saturating_sub(1) as u32 * self.layout.spacing);
+                (width, height)
+            },
+            LayoutType::Vertical => {
+                let width = layers_info.iter()
+                    .map(|(dims, _)| dims.width)
+                    .max()
+                    .unwrap_or(0);
+                let height = layers_info.iter()
+                    .map(|(dims, _)| dims.height)
+                    .sum::<u32>() + (layers_info.len().saturating_sub(1) as u32 * self.layout.spacing);
+                (width, height)
+            },
+        };
+
+        // Get container bounds from distribution config or use total dimensions
+        let container_bounds = if let Some(dist_config) = &self.layout.distribution {
+            if let Some(bounds) = &dist_config.bounds {
+                (bounds.width, bounds.height)
+            } else {
+                (total_width, total_height)
+            }
+        } else {
+            (total_width, total_height)
+        };
+
+        // Apply global alignment
+        let (base_x, base_y) = match self.layout.alignment {
+            GroupAlignment::Left => (current_x, current_y),
+            GroupAlignment::Center => (
+                current_x + (container_bounds.0.saturating_sub(total_width)) / 2,
+                current_y + (container_bounds.1.saturating_sub(total_height)) / 2
+            ),
+            GroupAlignment::Right => (
+                current_x + container_bounds.0.saturating_sub(total_width),
+                current_y
+            ),
+            GroupAlignment::Top => (
+                current_x,
+                current_y
+            ),
+            GroupAlignment::Bottom => (
+                current_x,
+                current_y + container_bounds.1.saturating_sub(total_height)
+            ),
+        };
+
+        // Calculate spacing based on justification
+        let (init_spacing, item_spacing) = match self.layout.justification {
+            GroupJustification::Start => (0, self.layout.spacing),
+            GroupJustification::Center => {
+                let total_space = match self.layout.layout_type {
+                    LayoutType::Horizontal => container_bounds.0.saturating_sub(total_width),
+                    LayoutType::Vertical => container_bounds.1.saturating_sub(total_height),
+                    LayoutType::Grid => 0, // Grid handles spacing differently
+                };
+                (total_space / 2, self.layout.spacing)
+            },
+            GroupJustification::End => {
+                let total_space = match self.layout.layout_type {
+                    LayoutType::Horizontal => container_bounds.0.saturating_sub(total_width),
+                    LayoutType::Vertical => container_bounds.1.saturating_sub(total_height),
+                    LayoutType::Grid => 0,
+                };
+                (total_space, self.layout.spacing)
+            },
+            GroupJustification::SpaceBetween => {
+                let count = layers_info.len().saturating_sub(1).max(1);
+                let total_space = match self.layout.layout_type {
+                    LayoutType::Horizontal => container_bounds.0.saturating_sub(total_width),
+                    LayoutType::Vertical => container_bounds.1.saturating_sub(total_height),
+                    LayoutType::Grid => 0,
+                };
+                (0, total_space / count as u32)
+            },
+            GroupJustification::SpaceAround => {
+                let count = layers_info.len() + 1;
+                let total_space = match self.layout.layout_type {
+                    LayoutType::Horizontal => container_bounds.0.saturating_sub(total_width),
+                    LayoutType::Vertical => container_bounds.1.saturating_sub(total_height),
+                    LayoutType::Grid => 0,
+                };
+                let spacing = total_space / count as u32;
+                (spacing, spacing)
+            },
+            GroupJustification::SpaceEvenly => {
+                let count = layers_info.len() + 2;
+                let total_space = match self.layout.layout_type {
+                    LayoutType::Horizontal => container_bounds.0.saturating_sub(total_width),
+                    LayoutType::Vertical => container_bounds.1.saturating_sub(total_height),
+                    LayoutType::Grid => 0,
+                };
+                let spacing = total_space / count as u32;
+                (spacing, spacing)
+            },
+        };
+
+        // Position elements based on layout type and justification
+        match self.layout.layout_type {
+            LayoutType::Grid => {
+                let columns = self.layout.columns as usize;
+                let mut x = base_x;
+                let mut y = base_y;
+                let mut col = 0;
+
+                for (dims, _) in layers_info {
+                    positions.push(Position {
+                        x,
+                        y,
+                        relative_to: RelativeTo::Canvas,
+                    });
+
+                    col += 1;
+                    if col >= columns {
+                        // Move to next row
+                        col = 0;
+                        x = base_x;
+                        y += dims.height + item_spacing;
+                    } else {
+                        x += dims.width + item_spacing;
+                    }
+                }
+            },
+            LayoutType::Vertical => {
+                let mut y = base_y + init_spacing;
+                for (dims, _) in layers_info {
+                    let x = match self.layout.alignment {
+                        GroupAlignment::Left => base_x,
+                        GroupAlignment::Center => base_x + (container_bounds.0.saturating_sub(dims.width)) / 2,
+                        GroupAlignment::Right => base_x + container_bounds.0.saturating_sub(dims.width),
+                        _ => base_x,
+                    };
+
+                    positions.push(Position {
+                        x,
+                        y,
+                        relative_to: RelativeTo::Canvas,
+                    });
+                    y += dims.height + item_spacing;
+                }
+            },
+            LayoutType::Horizontal => {
+                let mut x = base_x + init_spacing;
+                for (dims, _) in layers_info {
+                    let y = match self.layout.alignment {
+                        GroupAlignment::Top => base_y,
+                        GroupAlignment::Center => base_y + (container_bounds.1.saturating_sub(dims.height)) / 2,
+                        GroupAlignment::Bottom => base_y + container_bounds.1.saturating_sub(dims.height),
+                        _ => base_y,
+                    };
+
+                    positions.push(Position {
+                        x,
+                        y,
+                        relative_to: RelativeTo::Canvas,
+                    });
+                    x += dims.width + item_spacing;
+                }
+            },
+        }
+
+        // Handle relative positioning
+        let mut relative_adjustments = Vec::new();
+        for (i, pos) in positions.iter().enumerate() {
+            if let RelativeTo::Layer(ref layer_name) = pos.relative_to
+                && let Some((ref_idx, _)) = layers_info.iter()
+                    .enumerate()
+                    .find(|(_, (_, info))| info.name == *layer_name)
+            {
+                relative_adjustments.push((i, ref_idx));
+            }
+        }
+
+        for (target_idx, ref_idx) in relative_adjustments {
+            let ref_pos = positions[ref_idx].clone();
+            positions[target_idx].x = ref_pos.x;
+            positions[target_idx].y = ref_pos.y;
+        }
+
+        positions
+    }
+}
+
+impl Group {
+    /// Where each of the group's layers sits in the template, given the group's index.
+    pub(crate) fn layer_locations(&self, group_index: usize) -> Vec<Location> {
+        self.layers.iter()
+            .enumerate()
+            .map(|(layer_index, layer)| Location::layer(group_index, &self.name, layer_index, &layer.info().name))
+            .collect()
+    }
+
+    pub(crate) fn resolve_layers(
+        &self,
+        locations: &[Location],
+        variables: &Variables,
+        source: Option<&SourceData>,
+        fonts: &FontRegistry,
+        base_dir: &Path,
+    ) -> Result<Vec<Layer>, KitError> {
+        let bounds_width = self.layout.distribution.as_ref()
+            .and_then(|distribution| distribution.bounds.as_ref())
+            .map(|bounds| bounds.width);
+        self.layers.iter()
+            .zip(locations)
+            .map(|(layer, at)| {
+                let mut layer = layer.resolve(variables, source, fonts, base_dir).map_err(|e| e.within(at))?;
+                if let Layer::Text(text) = &mut layer {
+                    text.group_width = bounds_width;
+                }
+                Ok(layer)
+            })
+            .collect()
+    }
+}
+
+pub(crate) fn measure_layers<'a>(
+    layers: &'a [Layer],
+    locations: &[Location],
+    fonts: &FontRegistry,
+) -> Result<Vec<(LayerDimensions, &'a LayerInfo)>, KitError> {
+    let mut layer_dimensions = Vec::new();
+    for (layer, at) in layers.iter().zip(locations) {
+        let dimensions = layer.get_dimensions(fonts).map_err(|e| e.within(at))?;
+        layer_dimensions.push((dimensions, layer.info()));
+    }
+    Ok(layer_dimensions)
+}
@@ -0,0 +1,51 @@
+//! Renders print templates: JSON layouts of text and image layers, optionally
+//! filled in from an Illustrator or Photoshop source file, to PNG, PDF or SVG.
+
+use image::Rgba;
+use csscolorparser::parse as parse_color;
+
+pub mod ai_handler;
+pub mod batch;
+mod compositing;
+mod effects;
+mod error;
+mod fill;
+mod font_spec;
+mod fonts;
+mod layer;
+pub mod layer_trait;
+mod layout;
+mod pdf_font;
+mod pdf_output;
+mod pdf_text;
+pub mod psd_handler;
+mod report;
+mod shaping;
+mod source;
+mod svg_output;
+mod template;
+mod text_layer;
+mod text_path;
+pub mod variables;
+mod warp;
+pub use ai_handler::{AiData, AiLayer};
+pub use error::{DataError, FontError, KitError, Location, OutputError, SourceError, VariableError, Warning};
+pub use layer_trait::SourceLayer;
+pub use psd_handler::{PsdData, PsdLayer};
+pub use report::{GroupReport, LayerContent, LayerReport, Report, SourceLayerReport};
+pub use template::Template;
+pub use variables::{Placeholder, Variables};
+
+fn parse_rgba(color: &str, path: &str) -> Result<Rgba<u8>, KitError> {
+    let parsed = parse_color(color).map_err(|source| KitError::InvalidColor {
+        at: Location::field(path),
+        value: color.to_string(),
+        source,
+    })?;
+    Ok(Rgba([
+        (parsed.r * 255.0) as u8,
+        (parsed.g * 255.0) as u8,
+        (parsed.b * 255.0) as u8,
+        (parsed.a * 255.0) as u8,
+    ]))
+}
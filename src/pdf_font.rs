@@ -0,0 +1,660 @@
+//! Font programs for PDF embedding, cut down to the glyphs a document draws.
+//! Text is written as glyph ids, so every glyph keeps its id; glyphs that
+//! aren't drawn stay in the font with nothing in them.
+
+use std::collections::BTreeSet;
+use rustybuzz::ttf_parser::{RawFace, Tag};
+
+/// A font program and how the document declares it.
+pub(crate) enum FontProgram {
+    /// TrueType outlines, embedded as FontFile2 under a CIDFontType2 font
+    TrueType(Vec<u8>),
+    /// OpenType with CFF outlines, embedded as a FontFile3 of subtype
+    /// OpenType under a CIDFontType0 font
+    OpenType(Vec<u8>),
+}
+
+impl FontProgram {
+    /// The program of `face`, whose file is `data`, keeping only `glyphs`.
+    /// A font whose tables can't be read for subsetting goes in whole.
+    pub(crate) fn subset(data: &[u8], face: &RawFace, glyphs: &BTreeSet<u16>) -> Self {
+        if face.table(tag(b"CFF ")).is_some() {
+            FontProgram::OpenType(subset_opentype(face, glyphs).unwrap_or_else(|| data.to_vec()))
+        } else {
+            FontProgram::TrueType(subset_truetype(face, glyphs).unwrap_or_else(|| data.to_vec()))
+        }
+    }
+}
+
+/// Whether the face has outlines a PDF can embed: TrueType or CFF. CFF2, the
+/// outlines of variable OpenType fonts, has no font program type in PDF.
+pub(crate) fn is_embeddable(face: &RawFace) -> bool {
+    face.table(tag(b"glyf")).is_some() || face.table(tag(b"CFF ")).is_some()
+}
+
+/// The six capital letters that prefix the name of a subset font, derived
+/// from the glyphs kept so different subsets of a font get different names.
+pub(crate) fn subset_tag(glyphs: &BTreeSet<u16>) -> String {
+    // FNV-1a over the glyph ids
+    let mut hash: u32 = 0x811C_9DC5;
+    for id in glyphs {
+        for byte in id.to_be_bytes() {
+            hash = (hash ^ byte as u32).wrapping_mul(0x0100_0193);
+        }
+    }
+    (0..6)
+        .map(|_| {
+            let letter = (b'A' + (hash % 26) as u8) as char;
+            hash /= 26;
+            letter
+        })
+        .collect()
+}
+
+fn tag(name: &[u8; 4]) -> Tag {
+    Tag::from_bytes(name)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// Flags of a composite glyph's components
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+/// Rebuilds the glyf and loca tables with only `glyphs`, the glyphs they are
+/// composed of and the missing glyph, next to the other tables a PDF reader
+/// needs to draw TrueType glyphs by id.
+fn subset_truetype(face: &RawFace, glyphs: &BTreeSet<u16>) -> Option<Vec<u8>> {
+    let head = face.table(tag(b"head"))?;
+    let glyf = face.table(tag(b"glyf"))?;
+    let loca = face.table(tag(b"loca"))?;
+    let glyph_count = read_u16(face.table(tag(b"maxp"))?, 4)?;
+    let long_offsets = read_u16(head, 50)? == 1;
+    let glyph_data = |id: u16| {
+        let index = id as usize;
+        let (start, end) = if long_offsets {
+            (read_u32(loca, index * 4)? as usize, read_u32(loca, index * 4 + 4)? as usize)
+        } else {
+            (read_u16(loca, index * 2)? as usize * 2, read_u16(loca, index * 2 + 2)? as usize * 2)
+        };
+        glyf.get(start..end)
+    };
+
+    let mut kept: BTreeSet<u16> = glyphs.iter().copied().filter(|&id| id < glyph_count).collect();
+    kept.insert(0);
+    let mut pending: Vec<u16> = kept.iter().copied().collect();
+    while let Some(id) = pending.pop() {
+        for component in components(glyph_data(id)?)? {
+            if component < glyph_count && kept.insert(component) {
+                pending.push(component);
+            }
+        }
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((glyph_count as usize + 1) * 4);
+    for id in 0..glyph_count {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        if kept.contains(&id) {
+            new_glyf.extend_from_slice(glyph_data(id)?);
+            new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+        }
+    }
+    new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+
+    // The new loca always has long offsets
+    let mut head = head.to_vec();
+    head.get_mut(50..52)?.copy_from_slice(&1u16.to_be_bytes());
+
+    let mut tables = vec![(*b"head", head), (*b"loca", new_loca), (*b"glyf", new_glyf)];
+    for name in [b"hhea", b"hmtx", b"maxp", b"cvt ", b"fpgm", b"prep"] {
+        if let Some(table) = face.table(tag(name)) {
+            tables.push((*name, table.to_vec()));
+        }
+    }
+    Some(sfnt(0x0001_0000, tables))
+}
+
+/// The glyph ids a composite glyph is built from; none for a simple glyph.
+fn components(glyph: &[u8]) -> Option<Vec<u16>> {
+    let mut ids = Vec::new();
+    // Simple glyphs have a non-negative contour count
+    if glyph.is_empty() || read_u16(glyph, 0)? < 0x8000 {
+        return Some(ids);
+    }
+
+    let mut offset = 10;
+    loop {
+        let flags = read_u16(glyph, offset)?;
+        ids.push(read_u16(glyph, offset + 2)?);
+        offset += if flags & ARG_1_AND_2_ARE_WORDS != 0 { 8 } else { 6 };
+        offset += if flags & WE_HAVE_A_SCALE != 0 {
+            2
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            4
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            8
+        } else {
+            0
+        };
+        if flags & MORE_COMPONENTS == 0 {
+            return Some(ids);
+        }
+    }
+}
+
+/// Subsets the CFF table and wraps it with the tables that describe the
+/// font's metrics and names. Layout tables are left out, as the document's
+/// glyphs are already shaped.
+fn subset_opentype(face: &RawFace, glyphs: &BTreeSet<u16>) -> Option<Vec<u8>> {
+    let mut tables = vec![(*b"CFF ", subset_cff(face.table(tag(b"CFF "))?, glyphs)?)];
+    for name in [b"head", b"hhea", b"hmtx", b"maxp", b"OS/2", b"name", b"cmap", b"post"] {
+        if let Some(table) = face.table(tag(name)) {
+            tables.push((*name, table.to_vec()));
+        }
+    }
+    Some(sfnt(u32::from_be_bytes(*b"OTTO"), tables))
+}
+
+/// Assembles an sfnt font file from its tables, with the table directory and
+/// checksums filled in.
+fn sfnt(version: u32, mut tables: Vec<([u8; 4], Vec<u8>)>) -> Vec<u8> {
+    tables.sort_by_key(|(name, _)| *name);
+    // The whole file's checksum is worked out with head's adjustment at zero
+    for (name, data) in &mut tables {
+        if name == b"head"
+            && let Some(adjustment) = data.get_mut(8..12)
+        {
+            adjustment.fill(0);
+        }
+    }
+
+    let count = tables.len() as u16;
+    let entry_selector = (u16::BITS - 1).saturating_sub(count.leading_zeros()) as u16;
+    let search_range = 16 << entry_selector;
+    let mut font = Vec::new();
+    font.extend_from_slice(&version.to_be_bytes());
+    for value in [count, search_range, entry_selector, (count * 16).saturating_sub(search_range)] {
+        font.extend_from_slice(&value.to_be_bytes());
+    }
+
+    let mut offset = 12 + 16 * tables.len();
+    for (name, data) in &tables {
+        font.extend_from_slice(name);
+        font.extend_from_slice(&checksum(data).to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += data.len().next_multiple_of(4);
+    }
+
+    let mut head_offset = None;
+    for (name, data) in &tables {
+        if name == b"head" {
+            head_offset = Some(font.len());
+        }
+        font.extend_from_slice(data);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+
+    if let Some(head) = head_offset {
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&font));
+        font[head + 8..head + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    font
+}
+
+// The sum of the data as big-endian 32-bit words, zero-padded
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4)
+        .map(|chunk| {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u32::from_be_bytes(word)
+        })
+        .fold(0, u32::wrapping_add)
+}
+
+// Operators of the CFF DICT entries that hold offsets
+const CHARSET: u16 = 15;
+const ENCODING: u16 = 16;
+const CHAR_STRINGS: u16 = 17;
+const PRIVATE: u16 = 18;
+const SUBRS: u16 = 19;
+const FD_ARRAY: u16 = 1236;
+const FD_SELECT: u16 = 1237;
+const ROS: u16 = 1230;
+
+// A charstring that draws nothing
+const ENDCHAR: &[u8] = &[14];
+
+/// Rebuilds a CFF table with the charstrings of glyphs not in `glyphs`
+/// emptied. Subroutines are kept whole, since charstrings call them by index.
+/// The rest of the table is copied in a fixed order, with each offset to it
+/// rewritten.
+fn subset_cff(cff: &[u8], glyphs: &BTreeSet<u16>) -> Option<Vec<u8>> {
+    let header_size = *cff.get(2)? as usize;
+    let (_, names_end) = cff_index(cff, header_size)?;
+    let (top_dicts, top_end) = cff_index(cff, names_end)?;
+    let (_, global_subrs_end) = cff_index(cff, cff_index(cff, top_end)?.1)?;
+    // OpenType fonts hold one font per CFF table
+    let [top_dict] = top_dicts.as_slice() else {
+        return None;
+    };
+    let top = parse_dict(top_dict)?;
+
+    let (char_strings, _) = cff_index(cff, dict_offset(&top, CHAR_STRINGS)?)?;
+    let glyph_count = char_strings.len();
+    let char_strings: Vec<&[u8]> = char_strings.into_iter()
+        .enumerate()
+        .map(|(id, char_string)| if id == 0 || glyphs.contains(&(id as u16)) { char_string } else { ENDCHAR })
+        .collect();
+
+    // Offsets 0 to 2 name a predefined charset and 0 or 1 an encoding. The
+    // charset of a font with CID keys maps CIDs to glyphs, and text is
+    // written as glyph ids, so it becomes the identity.
+    let charset = match dict_offset(&top, CHARSET) {
+        _ if top.iter().any(|entry| entry.operator == ROS) => Some(identity_charset(glyph_count)),
+        Some(offset) if offset > 2 => Some(cff.get(offset..offset + charset_size(cff, offset, glyph_count)?)?.to_vec()),
+        _ => None,
+    };
+    let encoding = match dict_offset(&top, ENCODING) {
+        Some(offset) if offset > 1 => Some(cff.get(offset..offset + encoding_size(cff, offset)?)?),
+        _ => None,
+    };
+
+    // Fonts with CID keys keep a private DICT for each font in their FDArray
+    let mut font_dicts = Vec::new();
+    let mut privates = Vec::new();
+    let fd_select = match dict_offset(&top, FD_ARRAY) {
+        Some(fd_array) => {
+            for font_dict in cff_index(cff, fd_array)?.0 {
+                let font_dict = parse_dict(font_dict)?;
+                privates.push(private_dict(cff, &font_dict)?);
+                font_dicts.push(font_dict);
+            }
+            let offset = dict_offset(&top, FD_SELECT)?;
+            Some(cff.get(offset..offset + fd_select_size(cff, offset, glyph_count)?)?)
+        }
+        None => {
+            privates.push(private_dict(cff, &top)?);
+            None
+        }
+    };
+
+    // Offsets are written as five-byte integers, so the sizes of the DICTs
+    // are known before the offsets in them are
+    let placeholder = |operator: u16| match operator {
+        PRIVATE => Some(vec![0, 0]),
+        CHARSET | ENCODING | CHAR_STRINGS | FD_ARRAY | FD_SELECT => Some(vec![0]),
+        _ => None,
+    };
+    let mut end = header_size
+        + (names_end - header_size)
+        + index_bytes(&[&write_dict(&top, placeholder)]).len()
+        + (global_subrs_end - top_end);
+    let mut place = |size: usize| {
+        let offset = end;
+        end += size;
+        offset
+    };
+
+    let charset_at = charset.as_ref().map(|charset| place(charset.len()));
+    let encoding_at = encoding.map(|encoding| place(encoding.len()));
+    let char_strings = index_bytes(&char_strings);
+    let char_strings_at = place(char_strings.len());
+    let fd_select_at = fd_select.map(|fd_select| place(fd_select.len()));
+    let placeholder_font_dicts: Vec<Vec<u8>> = font_dicts.iter().map(|dict| write_dict(dict, placeholder)).collect();
+    let fd_array_at = (!font_dicts.is_empty())
+        .then(|| place(index_bytes(&placeholder_font_dicts.iter().map(Vec::as_slice).collect::<Vec<_>>()).len()));
+    let private_ats: Vec<usize> = privates.iter().map(|(private, _)| place(private.len())).collect();
+
+    let top = write_dict(&top, |operator| match operator {
+        CHARSET => charset_at.map(|offset| vec![offset]),
+        ENCODING => encoding_at.map(|offset| vec![offset]),
+        CHAR_STRINGS => Some(vec![char_strings_at]),
+        FD_SELECT => fd_select_at.map(|offset| vec![offset]),
+        FD_ARRAY => fd_array_at.map(|offset| vec![offset]),
+        PRIVATE if font_dicts.is_empty() => Some(vec![privates[0].1, private_ats[0]]),
+        _ => None,
+    });
+    let font_dicts: Vec<Vec<u8>> = font_dicts.iter()
+        .zip(privates.iter().zip(&private_ats))
+        .map(|(dict, ((_, size), &offset))| write_dict(dict, |operator| (operator == PRIVATE).then(|| vec![*size, offset])))
+        .collect();
+
+    let mut subset = cff.get(..names_end)?.to_vec();
+    subset.extend(index_bytes(&[&top]));
+    subset.extend_from_slice(cff.get(top_end..global_subrs_end)?);
+    if let Some(charset) = &charset {
+        subset.extend_from_slice(charset);
+    }
+    if let Some(encoding) = encoding {
+        subset.extend_from_slice(encoding);
+    }
+    subset.extend(char_strings);
+    if let Some(fd_select) = fd_select {
+        subset.extend_from_slice(fd_select);
+        subset.extend(index_bytes(&font_dicts.iter().map(Vec::as_slice).collect::<Vec<_>>()));
+    }
+    for (private, _) in privates {
+        subset.extend(private);
+    }
+    Some(subset)
+}
+
+/// The private DICT a top or font DICT points to, rewritten to be followed
+/// directly by its local subroutines, along with the DICT's own size.
+fn private_dict(cff: &[u8], dict: &[DictEntry]) -> Option<(Vec<u8>, usize)> {
+    let entry = dict.iter().find(|entry| entry.operator == PRIVATE)?;
+    let [size, offset] = entry.operands.as_slice() else {
+        return None;
+    };
+    let (size, offset) = (usize::try_from(dict_int(size)?).ok()?, usize::try_from(dict_int(offset)?).ok()?);
+    let private = parse_dict(cff.get(offset..offset + size)?)?;
+
+    let subrs = match dict_offset(&private, SUBRS) {
+        Some(relative) => {
+            let start = offset + relative;
+            Some(cff.get(start..cff_index(cff, start)?.1)?)
+        }
+        None => None,
+    };
+    let size = write_dict(&private, |operator| (operator == SUBRS).then(|| vec![0])).len();
+    let mut bytes = write_dict(&private, |operator| (operator == SUBRS).then(|| vec![size]));
+    bytes.extend_from_slice(subrs.unwrap_or_default());
+    Some((bytes, size))
+}
+
+/// The items of the INDEX at `start` and the offset just past it.
+fn cff_index(data: &[u8], start: usize) -> Option<(Vec<&[u8]>, usize)> {
+    let count = read_u16(data, start)? as usize;
+    if count == 0 {
+        return Some((Vec::new(), start + 2));
+    }
+    let offset_size = *data.get(start + 2)? as usize;
+    if !(1..=4).contains(&offset_size) {
+        return None;
+    }
+
+    let offsets_start = start + 3;
+    let offset = |index: usize| {
+        let bytes = data.get(offsets_start + index * offset_size..offsets_start + (index + 1) * offset_size)?;
+        Some(bytes.iter().fold(0, |value, &byte| value << 8 | byte as usize))
+    };
+    // Offsets count from one byte before the data
+    let data_start = offsets_start + (count + 1) * offset_size - 1;
+    let mut items = Vec::with_capacity(count);
+    for index in 0..count {
+        items.push(data.get(data_start + offset(index)?..data_start + offset(index + 1)?)?);
+    }
+    Some((items, data_start + offset(count)?))
+}
+
+fn index_bytes(items: &[&[u8]]) -> Vec<u8> {
+    let mut bytes = (items.len() as u16).to_be_bytes().to_vec();
+    if items.is_empty() {
+        return bytes;
+    }
+
+    let last_offset = items.iter().map(|item| item.len()).sum::<usize>() + 1;
+    let offset_size = match last_offset {
+        0..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        0x1_0000..=0xFF_FFFF => 3,
+        _ => 4,
+    };
+    bytes.push(offset_size as u8);
+    let mut offset = 1;
+    for length in std::iter::once(0).chain(items.iter().map(|item| item.len())) {
+        offset += length;
+        bytes.extend_from_slice(&(offset as u32).to_be_bytes()[4 - offset_size..]);
+    }
+    for item in items {
+        bytes.extend_from_slice(item);
+    }
+    bytes
+}
+
+// An entry of a CFF DICT: an operator, two-byte ones as 1200 plus their second
+// byte, and its operands as written
+struct DictEntry<'a> {
+    operator: u16,
+    operands: Vec<&'a [u8]>,
+}
+
+fn parse_dict(data: &[u8]) -> Option<Vec<DictEntry<'_>>> {
+    let mut entries = Vec::new();
+    let mut operands = Vec::new();
+    let mut offset = 0;
+    while let Some(&first) = data.get(offset) {
+        let size = match first {
+            12 => {
+                let operator = 1200 + *data.get(offset + 1)? as u16;
+                entries.push(DictEntry { operator, operands: std::mem::take(&mut operands) });
+                offset += 2;
+                continue;
+            }
+            0..=21 => {
+                entries.push(DictEntry { operator: first as u16, operands: std::mem::take(&mut operands) });
+                offset += 1;
+                continue;
+            }
+            28 => 3,
+            29 => 5,
+            // A real number's nibbles run up to one that is 0xF
+            30 => data.get(offset + 1..)?.iter().position(|byte| byte >> 4 == 0xF || byte & 0xF == 0xF)? + 2,
+            32..=246 => 1,
+            247..=254 => 2,
+            _ => return None,
+        };
+        operands.push(data.get(offset..offset + size)?);
+        offset += size;
+    }
+    Some(entries)
+}
+
+fn dict_int(operand: &[u8]) -> Option<i32> {
+    match *operand {
+        [first @ 32..=246] => Some(first as i32 - 139),
+        [first @ 247..=250, second] => Some((first as i32 - 247) * 256 + second as i32 + 108),
+        [first @ 251..=254, second] => Some(-(first as i32 - 251) * 256 - second as i32 - 108),
+        [28, high, low] => Some(i16::from_be_bytes([high, low]) as i32),
+        [29, a, b, c, d] => Some(i32::from_be_bytes([a, b, c, d])),
+        _ => None,
+    }
+}
+
+// The offset an entry holds in its last operand
+fn dict_offset(dict: &[DictEntry], operator: u16) -> Option<usize> {
+    let entry = dict.iter().find(|entry| entry.operator == operator)?;
+    usize::try_from(dict_int(entry.operands.last()?)?).ok()
+}
+
+/// Writes the DICT back out, with the operands of each operator `replace`
+/// gives values for written as five-byte integers.
+fn write_dict(dict: &[DictEntry], replace: impl Fn(u16) -> Option<Vec<usize>>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for entry in dict {
+        match replace(entry.operator) {
+            Some(values) => {
+                for value in values {
+                    bytes.push(29);
+                    bytes.extend_from_slice(&(value as i32).to_be_bytes());
+                }
+            }
+            None => {
+                for operand in &entry.operands {
+                    bytes.extend_from_slice(operand);
+                }
+            }
+        }
+        if entry.operator >= 1200 {
+            bytes.extend_from_slice(&[12, (entry.operator - 1200) as u8]);
+        } else {
+            bytes.push(entry.operator as u8);
+        }
+    }
+    bytes
+}
+
+// Sizes of the structures the top DICT points to, which CFF doesn't record
+
+fn charset_size(cff: &[u8], offset: usize, glyph_count: usize) -> Option<usize> {
+    let format = *cff.get(offset)?;
+    // The missing glyph has no entry
+    let named = glyph_count.saturating_sub(1);
+    match format {
+        0 => Some(1 + 2 * named),
+        1 | 2 => {
+            let range_size = if format == 1 { 3 } else { 4 };
+            let mut covered = 0;
+            let mut size = 1;
+            while covered < named {
+                let left = if format == 1 {
+                    *cff.get(offset + size + 2)? as usize
+                } else {
+                    read_u16(cff, offset + size + 2)? as usize
+                };
+                covered += left + 1;
+                size += range_size;
+            }
+            Some(size)
+        }
+        _ => None,
+    }
+}
+
+// A charset naming each glyph by its id, as one range after the missing glyph
+fn identity_charset(glyph_count: usize) -> Vec<u8> {
+    let mut charset = vec![2];
+    if glyph_count > 1 {
+        charset.extend_from_slice(&1u16.to_be_bytes());
+        charset.extend_from_slice(&((glyph_count - 2) as u16).to_be_bytes());
+    }
+    charset
+}
+
+fn encoding_size(cff: &[u8], offset: usize) -> Option<usize> {
+    let format = *cff.get(offset)?;
+    let count = *cff.get(offset + 1)? as usize;
+    let mut size = match format & 0x7F {
+        0 => 2 + count,
+        1 => 2 + 2 * count,
+        _ => return None,
+    };
+    // The high bit adds supplementary codes after the main table
+    if format & 0x80 != 0 {
+        size += 1 + 3 * *cff.get(offset + size)? as usize;
+    }
+    Some(size)
+}
+
+fn fd_select_size(cff: &[u8], offset: usize, glyph_count: usize) -> Option<usize> {
+    match *cff.get(offset)? {
+        0 => Some(1 + glyph_count),
+        3 => Some(1 + 2 + 3 * read_u16(cff, offset + 1)? as usize + 2),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustybuzz::ttf_parser::{cff, Face, GlyphId};
+
+    #[test]
+    fn truetype_subset_keeps_used_glyphs_and_their_components() {
+        let data = std::fs::read("fonts/arial.ttf").unwrap();
+        let face = Face::parse(&data, 0).unwrap();
+        let e_acute = face.glyph_index('é').unwrap();
+        let b = face.glyph_index('B').unwrap();
+        // The accented letter is a composite of these
+        let e = face.glyph_index('e').unwrap();
+
+        let FontProgram::TrueType(subset) = FontProgram::subset(&data, face.raw_face(), &BTreeSet::from([e_acute.0])) else {
+            panic!("expected a TrueType program");
+        };
+        assert!(subset.len() < data.len() / 4);
+        assert_eq!(checksum(&subset), 0xB1B0_AFBA);
+
+        let subset_face = Face::parse(&subset, 0).unwrap();
+        assert_eq!(subset_face.number_of_glyphs(), face.number_of_glyphs());
+        assert_eq!(subset_face.glyph_bounding_box(e_acute), face.glyph_bounding_box(e_acute));
+        assert_eq!(subset_face.glyph_bounding_box(e), face.glyph_bounding_box(e));
+        assert_eq!(subset_face.glyph_bounding_box(b), None);
+    }
+
+    // A CFF table whose glyph 1 is a square and glyph 2 a square drawn
+    // partly by a local subroutine
+    fn test_cff() -> Vec<u8> {
+        let char_strings = index_bytes(&[
+            &[14],
+            &[139, 139, 21, 239, 139, 5, 139, 239, 5, 14],
+            &[139, 139, 21, 32, 10, 139, 239, 5, 14],
+        ]);
+        let subrs = index_bytes(&[&[239, 139, 5, 11]]);
+        let private = [29, 0, 0, 0, 6, 19];
+
+        let header = [1, 0, 4, 1];
+        let names = index_bytes(&[b"T"]);
+        let top_size = index_bytes(&[&[0; 17]]).len();
+        let char_strings_at = header.len() + names.len() + top_size + 4;
+        let private_at = char_strings_at + char_strings.len();
+        let mut top = vec![29];
+        top.extend_from_slice(&(char_strings_at as i32).to_be_bytes());
+        top.extend_from_slice(&[17, 29, 0, 0, 0, private.len() as u8, 29]);
+        top.extend_from_slice(&(private_at as i32).to_be_bytes());
+        top.push(18);
+
+        let mut cff = header.to_vec();
+        cff.extend(names);
+        cff.extend(index_bytes(&[&top]));
+        cff.extend([0, 0, 0, 0]);
+        cff.extend(char_strings);
+        cff.extend(private);
+        cff.extend(subrs);
+        cff
+    }
+
+    #[test]
+    fn cff_subset_empties_unused_charstrings() {
+        struct Ignore;
+        impl rustybuzz::ttf_parser::OutlineBuilder for Ignore {
+            fn move_to(&mut self, _: f32, _: f32) {}
+            fn line_to(&mut self, _: f32, _: f32) {}
+            fn quad_to(&mut self, _: f32, _: f32, _: f32, _: f32) {}
+            fn curve_to(&mut self, _: f32, _: f32, _: f32, _: f32, _: f32, _: f32) {}
+            fn close(&mut self) {}
+        }
+
+        let original = test_cff();
+        let original_table = cff::Table::parse(&original).unwrap();
+        assert!(original_table.outline(GlyphId(1), &mut Ignore).is_ok());
+
+        let subset = subset_cff(&original, &BTreeSet::from([2])).unwrap();
+        let table = cff::Table::parse(&subset).unwrap();
+        assert_eq!(table.number_of_glyphs(), 3);
+        assert!(table.outline(GlyphId(1), &mut Ignore).is_err());
+        assert_eq!(
+            table.outline(GlyphId(2), &mut Ignore).ok(),
+            original_table.outline(GlyphId(2), &mut Ignore).ok(),
+        );
+    }
+
+    #[test]
+    fn subset_tags_are_six_capitals() {
+        let tag = subset_tag(&BTreeSet::from([1, 2, 3]));
+        assert_eq!(tag.len(), 6);
+        assert!(tag.chars().all(|letter| letter.is_ascii_uppercase()));
+        assert_ne!(tag, subset_tag(&BTreeSet::from([1, 2])));
+    }
+}
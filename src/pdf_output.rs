@@ -0,0 +1,750 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use image::{DynamicImage, Rgba};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream, StringFormat};
+use rusttype::{GlyphId, Scale};
+use crate::compositing::BlendMode;
+use crate::effects::{StrokeJoin, TextStroke, MITER_LIMIT};
+use crate::error::{KitError, OutputError};
+use crate::fill::{self, FillBox, Paint};
+use crate::variables::Variables;
+use crate::fonts::FontRegistry;
+use crate::shaping::LoadedFont;
+use crate::font_spec::FontSpec;
+use crate::pdf_font::{self, FontProgram};
+use crate::layer::{ImageLayer, Layer};
+use crate::layout::Position;
+use crate::template::{PlacedLayer, Template};
+use crate::text_layer::{PlacedGlyph, TextLayer, TextLayout};
+
+/// A font embedded in the document as a Type0/Identity-H font, so text is
+/// written as glyph ids and stays selectable through its ToUnicode map. Only
+/// the glyphs drawn are embedded.
+struct EmbeddedFont {
+    resource_name: String,
+    base_name: String,
+    font: LoadedFont,
+    // Glyph id to the text it was drawn for, empty for marks and other glyphs
+    // that share their cluster's text
+    used_glyphs: BTreeMap<u16, String>,
+}
+
+/// Builds the page content stream and the resources it refers to.
+struct PageWriter {
+    // PDF points per template pixel
+    scale: f32,
+    page_height: f32,
+    operations: Vec<Operation>,
+    fonts: Vec<(String, EmbeddedFont)>,
+    images: Vec<(String, DynamicImage)>,
+    // Transparency groups, drawn as form XObjects
+    forms: Vec<(String, Vec<Operation>)>,
+    // Graphics states by alpha and blend mode
+    states: BTreeMap<(u8, BlendMode), String>,
+    // Gradient and image fills, set as the fill color through the Pattern
+    // color space
+    patterns: Vec<(String, PagePattern)>,
+}
+
+enum PagePattern {
+    // A shading pattern's dictionary
+    Shading(Dictionary),
+    // A tiling pattern's dictionary and the operations drawing one tile
+    Tiling(Dictionary, Vec<Operation>),
+}
+
+fn real(value: f32) -> Object {
+    Object::Real(value)
+}
+
+fn rgb(color: Rgba<u8>) -> Vec<Object> {
+    color.0[..3].iter().map(|&channel| real(channel as f32 / 255.0)).collect()
+}
+
+// A function running through the gradient's stops over 0 to 1: one linear
+// blend per pair of stops, stitched together when there are more than two.
+// PDF shadings carry no alpha, so the stops' opacity is dropped.
+fn gradient_function(stops: &[(f32, Rgba<u8>)]) -> Dictionary {
+    let mut padded = stops.to_vec();
+    if let Some(&(offset, color)) = padded.first()
+        && offset > 0.0
+    {
+        padded.insert(0, (0.0, color));
+    }
+    if let Some(&(offset, color)) = padded.last()
+        && offset < 1.0
+    {
+        padded.push((1.0, color));
+    }
+
+    let mut blends: Vec<Dictionary> = padded.windows(2)
+        .map(|pair| dictionary! {
+            "FunctionType" => 2,
+            "Domain" => vec![real(0.0), real(1.0)],
+            "C0" => rgb(pair[0].1),
+            "C1" => rgb(pair[1].1),
+            "N" => 1,
+        })
+        .collect();
+    if blends.len() == 1 {
+        return blends.remove(0);
+    }
+    let bounds: Vec<Object> = padded[1..padded.len() - 1].iter().map(|&(offset, _)| real(offset)).collect();
+    let encode: Vec<Object> = blends.iter().flat_map(|_| [real(0.0), real(1.0)]).collect();
+    dictionary! {
+        "FunctionType" => 3,
+        "Domain" => vec![real(0.0), real(1.0)],
+        "Functions" => blends.into_iter().map(Object::Dictionary).collect::<Vec<_>>(),
+        "Bounds" => bounds,
+        "Encode" => encode,
+    }
+}
+
+fn pdf_name(name: &str) -> String {
+    name.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').collect()
+}
+
+impl PageWriter {
+    fn op(&mut self, operator: &str, operands: Vec<Object>) {
+        self.operations.push(Operation::new(operator, operands));
+    }
+
+    // Selects the shared graphics state for an alpha and blend mode. The alpha
+    // sets both fill and stroke opacity.
+    fn set_state(&mut self, alpha: u8, mode: BlendMode) {
+        if alpha < 255 || mode != BlendMode::Normal {
+            let count = self.states.len();
+            let name = self.states.entry((alpha, mode)).or_insert_with(|| format!("GS{}", count + 1)).clone();
+            self.op("gs", vec![Object::Name(name.into_bytes())]);
+        }
+    }
+
+    fn set_opacity(&mut self, alpha: u8) {
+        self.set_state(alpha, BlendMode::Normal);
+    }
+
+    /// Starts collecting operations for a transparency group, returning the
+    /// operations written so far to hand back to `end_group`.
+    fn begin_group(&mut self) -> Vec<Operation> {
+        std::mem::take(&mut self.operations)
+    }
+
+    /// Wraps everything written since `begin_group` in a form XObject and
+    /// draws it at `opacity` with `mode`, so the group is blended as a whole
+    /// and its overlapping parts don't show through each other.
+    fn end_group(&mut self, outer: Vec<Operation>, opacity: f32, mode: BlendMode) {
+        let group = std::mem::replace(&mut self.operations, outer);
+        let name = format!("Fm{}", self.forms.len() + 1);
+        self.forms.push((name.clone(), group));
+
+        self.op("q", vec![]);
+        self.set_state((opacity * 255.0).round() as u8, mode);
+        self.op("Do", vec![Object::Name(name.into_bytes())]);
+        self.op("Q", vec![]);
+    }
+
+    fn set_fill(&mut self, color: Rgba<u8>) {
+        let [r, g, b, a] = color.0;
+        self.set_opacity(a);
+        self.op("rg", vec![real(r as f32 / 255.0), real(g as f32 / 255.0), real(b as f32 / 255.0)]);
+    }
+
+    // Sets the fill to `paint` laid over `area`, in template pixels. Gradients
+    // and images become patterns in page space, so the glyphs and decorations
+    // are filled as usual.
+    fn set_paint(&mut self, paint: &Paint, area: FillBox) {
+        let pattern = match paint {
+            Paint::Solid(color) => return self.set_fill(*color),
+            Paint::Linear { angle, stops } => {
+                let ((x0, y0), (x1, y1)) = fill::linear_line(*angle, area);
+                PagePattern::Shading(dictionary! {
+                    "Type" => "Pattern",
+                    "PatternType" => 2,
+                    "Shading" => dictionary! {
+                        "ShadingType" => 2,
+                        "ColorSpace" => "DeviceRGB",
+                        "Coords" => vec![
+                            real(x0 * self.scale), real(self.page_height - y0 * self.scale),
+                            real(x1 * self.scale), real(self.page_height - y1 * self.scale),
+                        ],
+                        "Function" => gradient_function(stops),
+                        "Extend" => vec![true.into(), true.into()],
+                    },
+                })
+            }
+            Paint::Radial { center, radius, stops } => {
+                // A unit circle, stretched into the gradient's ellipse by the pattern matrix
+                let ((cx, cy), (rx, ry)) = fill::radial_ellipse(*center, *radius, area);
+                PagePattern::Shading(dictionary! {
+                    "Type" => "Pattern",
+                    "PatternType" => 2,
+                    "Matrix" => vec![
+                        real(rx * self.scale), real(0.0), real(0.0), real(ry * self.scale),
+                        real(cx * self.scale), real(self.page_height - cy * self.scale),
+                    ],
+                    "Shading" => dictionary! {
+                        "ShadingType" => 3,
+                        "ColorSpace" => "DeviceRGB",
+                        "Coords" => vec![real(0.0), real(0.0), real(0.0), real(0.0), real(0.0), real(1.0)],
+                        "Function" => gradient_function(stops),
+                        "Extend" => vec![true.into(), true.into()],
+                    },
+                })
+            }
+            Paint::Pattern { image, scale } => {
+                let image_name = format!("Im{}", self.images.len() + 1);
+                self.images.push((image_name.clone(), DynamicImage::ImageRgba8(image.clone())));
+
+                // One tile is the image at its scaled size, tiled from the area's top-left
+                let width = image.width() as f32 * scale * self.scale;
+                let height = image.height() as f32 * scale * self.scale;
+                let tile = vec![
+                    Operation::new("cm", vec![real(width), real(0.0), real(0.0), real(height), real(0.0), real(0.0)]),
+                    Operation::new("Do", vec![Object::Name(image_name.into_bytes())]),
+                ];
+                PagePattern::Tiling(dictionary! {
+                    "Type" => "Pattern",
+                    "PatternType" => 1,
+                    "PaintType" => 1,
+                    "TilingType" => 1,
+                    "BBox" => vec![real(0.0), real(0.0), real(width), real(height)],
+                    "XStep" => real(width),
+                    "YStep" => real(height),
+                    "Matrix" => vec![
+                        real(1.0), real(0.0), real(0.0), real(1.0),
+                        real(area.0 * self.scale), real(self.page_height - area.1 * self.scale - height),
+                    ],
+                }, tile)
+            }
+        };
+
+        let name = format!("P{}", self.patterns.len() + 1);
+        self.patterns.push((name.clone(), pattern));
+        self.op("cs", vec![Object::Name(b"Pattern".to_vec())]);
+        self.op("scn", vec![Object::Name(name.into_bytes())]);
+    }
+
+    fn set_stroke(&mut self, color: Rgba<u8>, stroke: &TextStroke) {
+        let [r, g, b, a] = color.0;
+        self.set_opacity(a);
+        self.op("RG", vec![real(r as f32 / 255.0), real(g as f32 / 255.0), real(b as f32 / 255.0)]);
+        // PDF strokes are centered on the outline, so half lands under the fill
+        self.op("w", vec![real(stroke.width * 2.0 * self.scale)]);
+        let join: i64 = match stroke.join {
+            StrokeJoin::Miter => 0,
+            StrokeJoin::Round => 1,
+            StrokeJoin::Bevel => 2,
+        };
+        self.op("j", vec![join.into()]);
+        self.op("M", vec![real(MITER_LIMIT)]);
+    }
+
+    // Paints a rectangle given in template pixels from its top-left corner with
+    // `paint`, the fill and/or stroke operator
+    fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, paint: &str) {
+        self.op("re", vec![
+            real(x * self.scale),
+            real(self.page_height - (y + height) * self.scale),
+            real(width * self.scale),
+            real(height * self.scale),
+        ]);
+        self.op(paint, vec![]);
+    }
+
+    // Indices into `fonts` of each family in the spec's fallback chain
+    fn font_indices(&mut self, spec: &FontSpec, fonts: &FontRegistry) -> Result<Vec<usize>, KitError> {
+        let mut indices = Vec::new();
+        for (family_index, family) in spec.family.iter().enumerate() {
+            let key = format!("{}|{}|{:?}", family, spec.weight.to_font_kit_weight().0, spec.style.to_font_kit_style());
+            if let Some(index) = self.fonts.iter().position(|(k, _)| *k == key) {
+                indices.push(index);
+                continue;
+            }
+
+            let font = spec.load_family(family_index, fonts)?;
+            if !pdf_font::is_embeddable(font.face().raw_face()) {
+                return Err(KitError::Invalid {
+                    at: spec.family_location(family_index),
+                    message: format!("font '{}' has no TrueType or CFF outlines to embed in a PDF", family),
+                });
+            }
+            let resource_name = format!("F{}", self.fonts.len() + 1);
+            let base_name = format!("{}-{}", pdf_name(family), resource_name);
+            self.fonts.push((key, EmbeddedFont { resource_name, base_name, font, used_glyphs: BTreeMap::new() }));
+            indices.push(self.fonts.len() - 1);
+        }
+        Ok(indices)
+    }
+
+    /// Writes a text layer in up to three passes, bottom to top: the shadow,
+    /// the stroke and the fill. The shadow is drawn unblurred, since PDF has no
+    /// blur short of rasterizing it.
+    fn write_text(&mut self, text: &TextLayer, position: &Position, fonts: &FontRegistry) -> Result<(), KitError> {
+        if let Some(warp) = &text.warp {
+            // Bent glyphs can't be set as text, so the warped rendering goes in as an image
+            let image = text.warped_image(&text.font.load_fonts(fonts)?, warp)?;
+            let (width, height) = image.dimensions();
+            self.draw_image(DynamicImage::ImageRgba8(image), position, width, height);
+            return Ok(());
+        }
+
+        let indices = self.font_indices(&text.font, fonts)?;
+        let paint = text.font.paint()?;
+        let fonts: Vec<LoadedFont> = indices.iter().map(|&index| self.fonts[index].1.font.clone()).collect();
+        let (glyphs, layout) = text.place(&fonts, position);
+        let area = text.ink(&fonts, position).bounds
+            .map_or((0.0, 0.0, 0.0, 0.0), |(min_x, min_y, max_x, max_y)| {
+                (min_x as f32, min_y as f32, max_x as f32, max_y as f32)
+            });
+        let layout = layout.as_ref();
+
+        if let Some(shadow) = &text.shadow {
+            self.op("q", vec![]);
+            self.set_fill(shadow.rgba_color()?);
+            // The shadow takes the stroked silhouette's shape
+            let paint = if let Some(stroke) = &text.stroke {
+                self.set_stroke(shadow.rgba_color()?, stroke);
+                self.op("Tr", vec![2.into()]);
+                "B"
+            } else {
+                "f"
+            };
+            self.write_glyphs(text, &indices, &glyphs, shadow.offset.x, shadow.offset.y);
+            self.write_decorations(text, layout, shadow.offset.x, shadow.offset.y, paint);
+            self.op("Q", vec![]);
+        }
+
+        if let Some(stroke) = &text.stroke {
+            self.op("q", vec![]);
+            self.set_stroke(stroke.rgba_color()?, stroke);
+            self.op("Tr", vec![1.into()]);
+            self.write_glyphs(text, &indices, &glyphs, 0.0, 0.0);
+            self.write_decorations(text, layout, 0.0, 0.0, "S");
+            self.op("Q", vec![]);
+        }
+
+        self.op("q", vec![]);
+        self.set_paint(&paint, area);
+        self.write_glyphs(text, &indices, &glyphs, 0.0, 0.0);
+        self.write_decorations(text, layout, 0.0, 0.0, "f");
+        self.op("Q", vec![]);
+
+        Ok(())
+    }
+
+    // Shows every glyph, moved by `(dx, dy)` template pixels
+    fn write_glyphs(&mut self, text: &TextLayer, indices: &[usize], glyphs: &[PlacedGlyph], dx: f32, dy: f32) {
+        self.op("BT", vec![]);
+        let mut current_font = None;
+        for glyph in glyphs {
+            // Switch fonts when the glyph came from elsewhere in the chain
+            // or is sized differently, as small caps are
+            let index = indices[glyph.font];
+            if current_font != Some((index, glyph.size)) {
+                let embedded = &self.fonts[index].1;
+                let em_size = text.font.em_size(embedded.font.font()) * glyph.size;
+                let resource_name = embedded.resource_name.clone();
+                self.op("Tf", vec![Object::Name(resource_name.into_bytes()), real(em_size * self.scale)]);
+                current_font = Some((index, glyph.size));
+            }
+
+            let id = glyph.id.0;
+            self.fonts[index].1.used_glyphs.entry(id).or_insert_with(|| {
+                // The missing-glyph box stands for no text in particular
+                if id == 0 { String::new() } else { glyph.text.clone() }
+            });
+
+            // Clockwise on the canvas is clockwise on the page too, with y flipped
+            let (sin, cos) = glyph.angle.sin_cos();
+            self.op("Tm", vec![
+                real(cos), real(-sin), real(sin), real(cos),
+                real((glyph.x + dx) * self.scale),
+                real(self.page_height - (glyph.y + dy) * self.scale),
+            ]);
+            self.op("Tj", vec![Object::String(id.to_be_bytes().to_vec(), StringFormat::Hexadecimal)]);
+        }
+        self.op("ET", vec![]);
+    }
+
+    fn write_decorations(&mut self, text: &TextLayer, layout: Option<&TextLayout>, dx: f32, dy: f32, paint: &str) {
+        let Some(layout) = layout else {
+            return;
+        };
+        for line in &layout.lines {
+            if let Some((x, width)) = line.extent()
+                && let Some((line_y, thickness)) = text.font.decoration_line(line.top, line.height)
+            {
+                self.rect(x + dx, line_y as f32 + dy, width, thickness as f32, paint);
+            }
+        }
+    }
+
+    fn write_image(&mut self, image: &ImageLayer, placed: &PlacedLayer) -> Result<(), KitError> {
+        let (width, height) = (placed.dimensions.width, placed.dimensions.height);
+        self.draw_image(image.load_image()?, &placed.position, width, height);
+        Ok(())
+    }
+
+    // Draws an image stretched to `width` by `height` template pixels
+    fn draw_image(&mut self, image: DynamicImage, position: &Position, width: u32, height: u32) {
+        let name = format!("Im{}", self.images.len() + 1);
+        self.images.push((name.clone(), image));
+
+        // Images are drawn in a unit square, so scale it to the layer's size
+        let width = width as f32 * self.scale;
+        let height = height as f32 * self.scale;
+        self.op("q", vec![]);
+        self.op("cm", vec![
+            real(width), real(0.0), real(0.0), real(height),
+            real(position.x as f32 * self.scale),
+            real(self.page_height - position.y as f32 * self.scale - height),
+        ]);
+        self.op("Do", vec![Object::Name(name.into_bytes())]);
+        self.op("Q", vec![]);
+    }
+}
+
+fn embed_font(doc: &mut Document, embedded: &EmbeddedFont) -> ObjectId {
+    let font = &embedded.font;
+    let v_metrics = font.font().v_metrics_unscaled();
+    let units_per_em = font.font().units_per_em() as f32;
+    let to_thousandths = |value: f32| value * 1000.0 / units_per_em;
+    // Scaling by the line height leaves glyph metrics in font units
+    let unit_scale = Scale::uniform(v_metrics.ascent - v_metrics.descent);
+
+    let glyphs = embedded.used_glyphs.keys().copied().collect();
+    // Subset fonts are named with a tag telling them apart from the full font
+    let base_name = format!("{}+{}", pdf_font::subset_tag(&glyphs), embedded.base_name);
+    let face = font.face();
+    let (mut font_file, file_key, cid_font_type) = match FontProgram::subset(font.data(), face.raw_face(), &glyphs) {
+        FontProgram::TrueType(data) => {
+            (Stream::new(dictionary! { "Length1" => data.len() as i64 }, data), "FontFile2", "CIDFontType2")
+        }
+        FontProgram::OpenType(data) => {
+            (Stream::new(dictionary! { "Subtype" => "OpenType" }, data), "FontFile3", "CIDFontType0")
+        }
+    };
+    let _ = font_file.compress();
+    let font_file_id = doc.add_object(font_file);
+
+    // Nonsymbolic, as the glyphs are picked by id rather than by an encoding
+    let mut flags = 32;
+    if face.is_monospaced() {
+        flags |= 1;
+    }
+    if face.is_italic() || face.is_oblique() {
+        flags |= 64;
+    }
+
+    let ascent = to_thousandths(v_metrics.ascent);
+    let descent = to_thousandths(v_metrics.descent);
+    let mut descriptor = dictionary! {
+        "Type" => "FontDescriptor",
+        "FontName" => Object::Name(base_name.clone().into_bytes()),
+        "Flags" => flags,
+        "FontBBox" => vec![0.into(), real(descent), 1000.into(), real(ascent)],
+        "ItalicAngle" => real(face.italic_angle()),
+        "Ascent" => real(ascent),
+        "Descent" => real(descent),
+        "CapHeight" => real(ascent),
+        "StemV" => 80,
+    };
+    descriptor.set(file_key, font_file_id);
+    let descriptor_id = doc.add_object(descriptor);
+
+    let mut widths = Vec::new();
+    for &id in embedded.used_glyphs.keys() {
+        let advance = font.font().glyph(GlyphId(id)).scaled(unit_scale).h_metrics().advance_width;
+        widths.push(Object::Integer(id as i64));
+        widths.push(Object::Array(vec![real(to_thousandths(advance))]));
+    }
+
+    let mut cid_font = dictionary! {
+        "Type" => "Font",
+        "Subtype" => cid_font_type,
+        "BaseFont" => Object::Name(base_name.clone().into_bytes()),
+        "CIDSystemInfo" => dictionary! {
+            "Registry" => Object::string_literal("Adobe"),
+            "Ordering" => Object::string_literal("Identity"),
+            "Supplement" => 0,
+        },
+        "FontDescriptor" => descriptor_id,
+        "W" => widths,
+    };
+    // CFF fonts take CIDs as glyph ids, through an identity charset if they
+    // have CID keys
+    if cid_font_type == "CIDFontType2" {
+        cid_font.set("CIDToGIDMap", "Identity");
+    }
+    let cid_font_id = doc.add_object(cid_font);
+
+    let mut cmap = String::from(
+        "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+         /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+         /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+         1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+    );
+    let mappings: Vec<_> = embedded.used_glyphs.iter().filter(|(_, text)| !text.is_empty()).collect();
+    // bfchar sections hold at most 100 entries each
+    for chunk in mappings.chunks(100) {
+        cmap.push_str(&format!("{} beginbfchar\n", chunk.len()));
+        for (id, text) in chunk {
+            let hex: String = text.encode_utf16().map(|u| format!("{:04X}", u)).collect();
+            cmap.push_str(&format!("<{:04X}> <{}>\n", id, hex));
+        }
+        cmap.push_str("endbfchar\n");
+    }
+    cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+    let to_unicode_id = doc.add_object(Stream::new(dictionary! {}, cmap.into_bytes()));
+
+    doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type0",
+        "BaseFont" => Object::Name(base_name.into_bytes()),
+        "Encoding" => "Identity-H",
+        "DescendantFonts" => vec![cid_font_id.into()],
+        "ToUnicode" => to_unicode_id,
+    })
+}
+
+fn embed_image(doc: &mut Document, image: &DynamicImage) -> ObjectId {
+    let rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let rgb: Vec<u8> = rgba.pixels().flat_map(|p| [p[0], p[1], p[2]]).collect();
+
+    let mut dict = dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Image",
+        "Width" => width as i64,
+        "Height" => height as i64,
+        "ColorSpace" => "DeviceRGB",
+        "BitsPerComponent" => 8,
+    };
+
+    // Transparency goes in a separate grayscale soft mask
+    if rgba.pixels().any(|p| p[3] < 255) {
+        let alpha: Vec<u8> = rgba.pixels().map(|p| p[3]).collect();
+        let mut mask = Stream::new(dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => width as i64,
+            "Height" => height as i64,
+            "ColorSpace" => "DeviceGray",
+            "BitsPerComponent" => 8,
+        }, alpha);
+        let _ = mask.compress();
+        dict.set("SMask", doc.add_object(mask));
+    }
+
+    let mut stream = Stream::new(dict, rgb);
+    let _ = stream.compress();
+    doc.add_object(stream)
+}
+
+/// Writes the template as a single-page PDF. The page is the template's pixel
+/// size at its `dpi`; text is set in embedded font subsets and images are
+/// placed as image XObjects at their original resolution.
+pub fn write_pdf(template: &Template, variables: &Variables, path: &Path) -> Result<(), KitError> {
+    let output_error = |source: OutputError| KitError::Output { path: path.to_path_buf(), source };
+
+    let scale = 72.0 / template.dpi;
+    let page_width = template.size.width as f32 * scale;
+    let page_height = template.size.height as f32 * scale;
+
+    let mut writer = PageWriter {
+        scale,
+        page_height,
+        operations: Vec::new(),
+        fonts: Vec::new(),
+        images: Vec::new(),
+        forms: Vec::new(),
+        states: BTreeMap::new(),
+        patterns: Vec::new(),
+    };
+
+    let background = template.background_rgba()?;
+    if background[3] > 0 {
+        writer.op("q", vec![]);
+        writer.set_fill(background);
+        writer.rect(0.0, 0.0, template.size.width as f32, template.size.height as f32, "f");
+        writer.op("Q", vec![]);
+    }
+
+    for placed in template.compose(variables)? {
+        let info = placed.layer.info();
+        let (opacity, mode) = (info.opacity, info.blend_mode);
+        let outer = (opacity < 1.0 || mode != BlendMode::Normal).then(|| writer.begin_group());
+        match &placed.layer {
+            Layer::Text(text) => writer.write_text(text, &placed.position, &template.fonts),
+            Layer::Image(image) => writer.write_image(image, &placed),
+        }
+        .map_err(|e| e.within(&placed.at))?;
+        if let Some(outer) = outer {
+            writer.end_group(outer, opacity, mode);
+        }
+    }
+
+    let mut doc = Document::with_version("1.6");
+    let pages_id = doc.new_object_id();
+
+    let mut fonts = Dictionary::new();
+    for (_, embedded) in &writer.fonts {
+        let font_id = embed_font(&mut doc, embedded);
+        fonts.set(embedded.resource_name.clone(), font_id);
+    }
+
+    // Filled in once the groups, which are XObjects too, have been added
+    let images_id = doc.new_object_id();
+    let mut images = Dictionary::new();
+    for (name, image) in &writer.images {
+        let image_id = embed_image(&mut doc, image);
+        images.set(name.clone(), image_id);
+    }
+
+    let mut states = Dictionary::new();
+    for ((alpha, mode), name) in &writer.states {
+        let opacity = *alpha as f32 / 255.0;
+        states.set(name.clone(), dictionary! {
+            "Type" => "ExtGState",
+            "ca" => real(opacity),
+            "CA" => real(opacity),
+            "BM" => mode.name(),
+        });
+    }
+
+    // The page and its groups share one set of resources
+    let patterns_id = doc.new_object_id();
+    let resources_id = doc.add_object(dictionary! {
+        "Font" => fonts,
+        "XObject" => images_id,
+        "ExtGState" => states,
+        "Pattern" => patterns_id,
+    });
+    let mut patterns = Dictionary::new();
+    for (name, pattern) in writer.patterns {
+        let pattern_id = match pattern {
+            PagePattern::Shading(pattern) => doc.add_object(pattern),
+            PagePattern::Tiling(mut pattern, operations) => {
+                let encoded = Content { operations }.encode().map_err(|e| output_error(e.into()))?;
+                pattern.set("Resources", resources_id);
+                doc.add_object(Stream::new(pattern, encoded))
+            }
+        };
+        patterns.set(name, pattern_id);
+    }
+    doc.objects.insert(patterns_id, Object::Dictionary(patterns));
+    for (name, operations) in writer.forms {
+        let encoded = Content { operations }.encode().map_err(|e| output_error(e.into()))?;
+        let mut form = Stream::new(dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => vec![0.into(), 0.into(), real(page_width), real(page_height)],
+            "Group" => dictionary! { "Type" => "Group", "S" => "Transparency" },
+            "Resources" => resources_id,
+        }, encoded);
+        let _ = form.compress();
+        let form_id = doc.add_object(form);
+        images.set(name, form_id);
+    }
+    doc.objects.insert(images_id, Object::Dictionary(images));
+
+    let content = Content { operations: writer.operations };
+    let encoded = content.encode().map_err(|e| output_error(e.into()))?;
+    let mut content_stream = Stream::new(dictionary! {}, encoded);
+    let _ = content_stream.compress();
+    let content_id = doc.add_object(content_stream);
+
+    let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => vec![0.into(), 0.into(), real(page_width), real(page_height)],
+        "Contents" => content_id,
+        "Resources" => resources_id,
+    });
+    doc.objects.insert(pages_id, Object::Dictionary(dictionary! {
+        "Type" => "Pages",
+        "Kids" => vec![page_id.into()],
+        "Count" => 1,
+    }));
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+
+    doc.save(path).map_err(|e| output_error(e.into()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_text_reads_back() {
+        let dir = std::env::temp_dir().join(format!("kit-pdf-round-trip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let template_path = dir.join("template.json");
+        std::fs::write(&template_path, serde_json::json!({
+            "size": { "width": 200, "height": 100 },
+            "background": "#FFFFFF",
+            "font_dirs": [std::env::current_dir().unwrap().join("fonts")],
+            "system_fonts": false,
+            "groups": [{
+                "name": "main",
+                "layout": { "type": "vertical", "position": { "x": 10, "y": 10 } },
+                "layers": [{
+                    "type": "text",
+                    "name": "greeting",
+                    "text": "Hi",
+                    "font": { "family": "Arial", "size": 40, "color": "#000000" },
+                    "alignment": "left"
+                }]
+            }]
+        }).to_string()).unwrap();
+        let pdf_path = dir.join("out.pdf");
+        let written = Template::from_path(&template_path).and_then(|template| template.write_pdf(&Variables::new(), &pdf_path));
+        let doc = written.map(|_| Document::load(&pdf_path));
+        std::fs::remove_dir_all(&dir).unwrap();
+        let doc = doc.unwrap().unwrap();
+
+        let (_, &page_id) = doc.get_pages().iter().next().unwrap();
+        let fonts = doc.get_page_fonts(page_id);
+        assert_eq!(fonts.len(), 1);
+        let (resource_name, font) = fonts.into_iter().next().unwrap();
+        assert_eq!(font.get(b"Subtype").unwrap().as_name().unwrap(), b"Type0");
+        // Subsets are named with a six letter tag
+        let base_font = font.get(b"BaseFont").unwrap().as_name_str().unwrap();
+        let (tag, name) = base_font.split_once('+').unwrap();
+        assert!(tag.len() == 6 && tag.bytes().all(|b| b.is_ascii_uppercase()));
+        assert!(name.starts_with("Arial"));
+
+        // The subset program sits in the descendant font's descriptor
+        let descendant = font.get(b"DescendantFonts").unwrap().as_array().unwrap()[0].as_reference().unwrap();
+        let descendant = doc.get_dictionary(descendant).unwrap();
+        let descriptor = doc.get_dictionary(descendant.get(b"FontDescriptor").unwrap().as_reference().unwrap()).unwrap();
+        let program = doc.get_object(descriptor.get(b"FontFile2").unwrap().as_reference().unwrap()).unwrap();
+        assert!(!program.as_stream().unwrap().content.is_empty());
+
+        let to_unicode = doc.get_object(font.get(b"ToUnicode").unwrap().as_reference().unwrap()).unwrap();
+        let cmap = String::from_utf8(to_unicode.as_stream().unwrap().content.clone()).unwrap();
+        assert!(cmap.contains("2 beginbfchar"));
+        assert!(cmap.contains("> <0048>\n") && cmap.contains("> <0069>\n"));
+
+        // One text object selecting the font, then placing and showing each glyph
+        let content = Content::decode(&doc.get_page_content(page_id).unwrap()).unwrap();
+        let operators: Vec<&str> = content.operations.iter()
+            .map(|op| op.operator.as_str())
+            .skip_while(|op| *op != "BT")
+            .collect();
+        assert_eq!(operators[..7], ["BT", "Tf", "Tm", "Tj", "Tm", "Tj", "ET"]);
+        let set_font = content.operations.iter().find(|op| op.operator == "Tf").unwrap();
+        assert_eq!(set_font.operands[0].as_name().unwrap(), resource_name.as_slice());
+
+        // And the text extractor used for Illustrator sources reads it back
+        let runs = crate::pdf_text::extract_text_runs(&doc).unwrap();
+        let text: String = runs.iter().map(|run| run.text.as_str()).collect();
+        assert_eq!(text, "Hi");
+    }
+}
@@ -0,0 +1,673 @@
+use std::collections::{BTreeMap, HashMap};
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use crate::error::SourceError;
+
+/// A run of text drawn by a single show-text operator.
+#[derive(Debug, Clone)]
+pub struct TextRun {
+    /// Optional content group (Illustrator layer) the run was drawn in
+    pub layer: Option<String>,
+    /// Index of the BT/ET block the run belongs to
+    pub block: usize,
+    pub text: String,
+    pub font_name: Option<String>,
+    /// Effective font size in points after the text and transformation matrices
+    pub font_size: f32,
+    pub color: (u8, u8, u8),
+    /// Top-left based bounds: x1, y1, x2, y2
+    pub bounds: (f64, f64, f64, f64),
+    /// Baseline y in top-left based coordinates
+    pub baseline: f64,
+}
+
+type Matrix = [f64; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+fn multiply(m1: &Matrix, m2: &Matrix) -> Matrix {
+    [
+        m1[0] * m2[0] + m1[1] * m2[2],
+        m1[0] * m2[1] + m1[1] * m2[3],
+        m1[2] * m2[0] + m1[3] * m2[2],
+        m1[2] * m2[1] + m1[3] * m2[3],
+        m1[4] * m2[0] + m1[5] * m2[2] + m2[4],
+        m1[4] * m2[1] + m1[5] * m2[3] + m2[5],
+    ]
+}
+
+fn translate(tx: f64, ty: f64) -> Matrix {
+    [1.0, 0.0, 0.0, 1.0, tx, ty]
+}
+
+fn number(object: &Object) -> f64 {
+    object.as_float().map(f64::from).unwrap_or(0.0)
+}
+
+fn numbers(operands: &[Object]) -> Vec<f64> {
+    operands.iter().filter_map(|o| o.as_float().ok().map(f64::from)).collect()
+}
+
+/// Decodes a PDF text string, which is either UTF-16BE with a byte order mark or
+/// PDFDocEncoding (treated as Latin-1 here).
+pub fn decode_text_string(bytes: &[u8]) -> String {
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        bytes.iter().map(|&b| b as char).collect()
+    }
+}
+
+/// Converts fill color operands to RGB by component count: gray, RGB or CMYK.
+fn to_rgb(components: &[f64]) -> Option<(u8, u8, u8)> {
+    let channel = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    match components {
+        [gray] => Some((channel(*gray), channel(*gray), channel(*gray))),
+        [r, g, b] => Some((channel(*r), channel(*g), channel(*b))),
+        [c, m, y, k] => Some((
+            channel((1.0 - c) * (1.0 - k)),
+            channel((1.0 - m) * (1.0 - k)),
+            channel((1.0 - y) * (1.0 - k)),
+        )),
+        _ => None,
+    }
+}
+
+/// Strips the six-letter subset tag from a font's BaseFont name.
+fn strip_subset_tag(name: &str) -> &str {
+    match name.split_once('+') {
+        Some((tag, rest)) if tag.len() == 6 && tag.chars().all(|c| c.is_ascii_uppercase()) => rest,
+        _ => name,
+    }
+}
+
+/// Best guess at a font family from the font dictionary: the descriptor's
+/// FontFamily when present, otherwise the PostScript name up to its style suffix
+/// (`Arial-BoldMT` and `ArialMT` both become `Arial`).
+fn font_family(doc: &Document, font: &Dictionary) -> Option<String> {
+    let descriptor = font_descriptor(doc, font);
+    if let Some(family) = descriptor
+        .and_then(|d| d.get(b"FontFamily").ok())
+        .and_then(|f| f.as_str().ok())
+    {
+        return Some(decode_text_string(family));
+    }
+
+    let base_font = font.get(b"BaseFont").and_then(Object::as_name_str).ok()?;
+    Some(family_from_postscript_name(strip_subset_tag(base_font)))
+}
+
+/// Guesses a family from a PostScript font name by dropping its style suffix.
+pub fn family_from_postscript_name(name: &str) -> String {
+    let family = name.split('-').next().unwrap_or(name);
+    let family = family.strip_suffix("PSMT")
+        .or_else(|| family.strip_suffix("MT"))
+        .unwrap_or(family);
+    family.to_string()
+}
+
+fn deref<'a>(doc: &'a Document, object: &'a Object) -> &'a Object {
+    doc.dereference(object).map(|(_, o)| o).unwrap_or(object)
+}
+
+fn font_descriptor<'a>(doc: &'a Document, font: &'a Dictionary) -> Option<&'a Dictionary> {
+    let font = descendant_font(doc, font).unwrap_or(font);
+    font.get(b"FontDescriptor").ok().and_then(|d| deref(doc, d).as_dict().ok())
+}
+
+fn descendant_font<'a>(doc: &'a Document, font: &'a Dictionary) -> Option<&'a Dictionary> {
+    let descendants = deref(doc, font.get(b"DescendantFonts").ok()?).as_array().ok()?;
+    deref(doc, descendants.first()?).as_dict().ok()
+}
+
+/// What the interpreter needs to know about a font resource.
+struct FontInfo {
+    family: Option<String>,
+    /// Two-byte codes (Type0 fonts) rather than one byte per character
+    two_byte: bool,
+    widths: HashMap<u32, f64>,
+    default_width: f64,
+    to_unicode: Option<HashMap<u32, String>>,
+    encoding: String,
+    ascent: f64,
+    descent: f64,
+}
+
+impl FontInfo {
+    fn load(doc: &Document, font: &Dictionary) -> Self {
+        let two_byte = font.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Type0");
+        let mut widths = HashMap::new();
+        let mut default_width = if two_byte { 1000.0 } else { 500.0 };
+
+        if two_byte {
+            if let Some(descendant) = descendant_font(doc, font) {
+                if let Ok(dw) = descendant.get(b"DW") {
+                    default_width = number(dw);
+                }
+                if let Ok(w) = descendant.get(b"W").map(|w| deref(doc, w)).and_then(Object::as_array) {
+                    parse_cid_widths(doc, w, &mut widths);
+                }
+            }
+        } else {
+            let first_char = font.get(b"FirstChar").map(number).unwrap_or(0.0) as u32;
+            if let Ok(w) = font.get(b"Widths").map(|w| deref(doc, w)).and_then(Object::as_array) {
+                for (offset, width) in w.iter().enumerate() {
+                    widths.insert(first_char + offset as u32, number(deref(doc, width)));
+                }
+            }
+        }
+
+        let descriptor = font_descriptor(doc, font);
+        let metric = |key: &[u8], fallback: f64| {
+            descriptor
+                .and_then(|d| d.get(key).ok())
+                .map(number)
+                .filter(|v| *v != 0.0)
+                .unwrap_or(fallback)
+                / 1000.0
+        };
+
+        let encoding = match font.get(b"Encoding").map(|e| deref(doc, e)) {
+            Ok(Object::Name(name)) => String::from_utf8_lossy(name).to_string(),
+            Ok(Object::Dictionary(dict)) => dict.get(b"BaseEncoding")
+                .and_then(Object::as_name_str)
+                .unwrap_or("StandardEncoding")
+                .to_string(),
+            _ => "StandardEncoding".to_string(),
+        };
+
+        let to_unicode = font.get(b"ToUnicode")
+            .ok()
+            .and_then(|t| deref(doc, t).as_stream().ok())
+            .and_then(|stream| stream.decompressed_content().ok().or_else(|| Some(stream.content.clone())))
+            .map(|cmap| parse_to_unicode(&cmap));
+
+        FontInfo {
+            family: font_family(doc, font),
+            two_byte,
+            widths,
+            default_width,
+            to_unicode,
+            encoding,
+            ascent: metric(b"Ascent", 800.0),
+            descent: metric(b"Descent", -200.0),
+        }
+    }
+
+    fn codes(&self, bytes: &[u8]) -> Vec<u32> {
+        if self.two_byte {
+            bytes.chunks(2)
+                .map(|pair| pair.iter().fold(0u32, |acc, &b| (acc << 8) | b as u32))
+                .collect()
+        } else {
+            bytes.iter().map(|&b| b as u32).collect()
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> String {
+        match &self.to_unicode {
+            Some(map) => self.codes(bytes)
+                .iter()
+                .map(|code| map.get(code).cloned().unwrap_or_default())
+                .collect(),
+            None if self.two_byte => String::new(),
+            None => Document::decode_text(Some(&self.encoding), bytes),
+        }
+    }
+
+    fn width(&self, code: u32) -> f64 {
+        self.widths.get(&code).copied().unwrap_or(self.default_width) / 1000.0
+    }
+}
+
+// W arrays mix `c [w1 w2 ...]` and `c_first c_last w` entries
+fn parse_cid_widths(doc: &Document, w: &[Object], widths: &mut HashMap<u32, f64>) {
+    let mut i = 0;
+    while i < w.len() {
+        let first = number(deref(doc, &w[i])) as u32;
+        match w.get(i + 1).map(|o| deref(doc, o)) {
+            Some(Object::Array(list)) => {
+                for (offset, width) in list.iter().enumerate() {
+                    widths.insert(first + offset as u32, number(width));
+                }
+                i += 2;
+            }
+            Some(last) => {
+                let last = number(last) as u32;
+                let width = w.get(i + 2).map(number).unwrap_or(0.0);
+                for code in first..=last {
+                    widths.insert(code, width);
+                }
+                i += 3;
+            }
+            None => break,
+        }
+    }
+}
+
+fn hex_value(token: &str) -> Option<u32> {
+    u32::from_str_radix(token.trim_matches(|c| c == '<' || c == '>'), 16).ok()
+}
+
+fn hex_string(token: &str) -> String {
+    let digits = token.trim_matches(|c| c == '<' || c == '>');
+    let units: Vec<u16> = digits.as_bytes()
+        .chunks(4)
+        .filter_map(|chunk| std::str::from_utf8(chunk).ok())
+        .filter_map(|chunk| u16::from_str_radix(chunk, 16).ok())
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Parses the `bfchar` and `bfrange` sections of a ToUnicode CMap.
+fn parse_to_unicode(cmap: &[u8]) -> HashMap<u32, String> {
+    let text = String::from_utf8_lossy(cmap);
+    let mut map = HashMap::new();
+    let mut section = "";
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.ends_with("beginbfchar") {
+            section = "bfchar";
+            continue;
+        } else if line.ends_with("beginbfrange") {
+            section = "bfrange";
+            continue;
+        } else if line.starts_with("endbf") {
+            section = "";
+            continue;
+        }
+
+        let tokens: Vec<&str> = line
+            .split(|c: char| c.is_whitespace() || c == '[' || c == ']')
+            .filter(|t| !t.is_empty())
+            .collect();
+
+        match section {
+            "bfchar" if tokens.len() >= 2 => {
+                if let Some(code) = hex_value(tokens[0]) {
+                    map.insert(code, hex_string(tokens[1]));
+                }
+            }
+            "bfrange" if tokens.len() >= 3 => {
+                let (Some(start), Some(end)) = (hex_value(tokens[0]), hex_value(tokens[1])) else {
+                    continue;
+                };
+                if line.contains('[') {
+                    // Explicit destination per code
+                    for (code, token) in (start..=end).zip(&tokens[2..]) {
+                        map.insert(code, hex_string(token));
+                    }
+                } else if let Some(base) = hex_value(tokens[2]) {
+                    for (offset, code) in (start..=end).enumerate() {
+                        if let Some(c) = char::from_u32(base + offset as u32) {
+                            map.insert(code, c.to_string());
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    map
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Matrix,
+    fill: (u8, u8, u8),
+}
+
+struct TextState {
+    font: Option<Vec<u8>>,
+    size: f64,
+    char_spacing: f64,
+    word_spacing: f64,
+    horizontal_scale: f64,
+    leading: f64,
+    rise: f64,
+    matrix: Matrix,
+    line_matrix: Matrix,
+}
+
+impl Default for TextState {
+    fn default() -> Self {
+        TextState {
+            font: None,
+            size: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            horizontal_scale: 1.0,
+            leading: 0.0,
+            rise: 0.0,
+            matrix: IDENTITY,
+            line_matrix: IDENTITY,
+        }
+    }
+}
+
+/// Names of the document's optional content groups, in catalog order.
+pub fn optional_content_groups(doc: &Document) -> Vec<String> {
+    let Ok(catalog) = doc.catalog() else {
+        return Vec::new();
+    };
+    let Some(properties) = catalog.get(b"OCProperties").ok().and_then(|p| deref(doc, p).as_dict().ok()) else {
+        return Vec::new();
+    };
+    let Some(groups) = properties.get(b"OCGs").ok().and_then(|g| deref(doc, g).as_array().ok()) else {
+        return Vec::new();
+    };
+
+    groups.iter()
+        .filter_map(|group| deref(doc, group).as_dict().ok())
+        .filter_map(|group| group.get(b"Name").ok().and_then(|n| n.as_str().ok()))
+        .map(decode_text_string)
+        .collect()
+}
+
+// The top-left corner of the page's MediaBox, which needn't sit at the origin
+fn page_corner(doc: &Document, page_id: ObjectId) -> (f64, f64) {
+    let mut node = doc.get_dictionary(page_id).ok();
+    while let Some(dict) = node {
+        if let Ok(media_box) = dict.get(b"MediaBox").map(|m| deref(doc, m)).and_then(Object::as_array) {
+            let values: Vec<f64> = media_box.iter().map(number).collect();
+            if let [x1, y1, x2, y2] = values[..] {
+                return (x1.min(x2), y1.max(y2));
+            }
+        }
+        node = dict.get(b"Parent").and_then(Object::as_reference).and_then(|id| doc.get_dictionary(id)).ok();
+    }
+    (0.0, 0.0)
+}
+
+// Resources may sit on the page itself or be inherited from the page tree
+fn page_properties(doc: &Document, page_id: ObjectId) -> Option<&Dictionary> {
+    let (resources, resource_ids) = doc.get_page_resources(page_id);
+    resources.into_iter()
+        .chain(resource_ids.into_iter().filter_map(|id| doc.get_dictionary(id).ok()))
+        .find_map(|r| r.get(b"Properties").ok().and_then(|p| deref(doc, p).as_dict().ok()))
+}
+
+/// Walks the first page's content stream and returns every text run it draws.
+pub fn extract_text_runs(doc: &Document) -> Result<Vec<TextRun>, SourceError> {
+    let page_id = *doc.get_pages().get(&1).ok_or(SourceError::NoPages)?;
+    let corner = page_corner(doc, page_id);
+    let content = Content::decode(&doc.get_page_content(page_id)?)?;
+
+    let fonts: BTreeMap<Vec<u8>, FontInfo> = doc.get_page_fonts(page_id)
+        .into_iter()
+        .map(|(name, font)| (name, FontInfo::load(doc, font)))
+        .collect();
+    let properties = page_properties(doc, page_id);
+
+    let mut runs = Vec::new();
+    let mut graphics = GraphicsState { ctm: IDENTITY, fill: (0, 0, 0) };
+    let mut graphics_stack = Vec::new();
+    let mut text = TextState::default();
+    // One entry per open marked-content sequence; Some for optional content
+    let mut marked_content: Vec<Option<String>> = Vec::new();
+    let mut block = 0;
+
+    for operation in &content.operations {
+        let operands = &operation.operands;
+        match operation.operator.as_str() {
+            "q" => graphics_stack.push(graphics.clone()),
+            "Q" => {
+                if let Some(saved) = graphics_stack.pop() {
+                    graphics = saved;
+                }
+            }
+            "cm" => {
+                if let [a, b, c, d, e, f] = numbers(operands)[..] {
+                    graphics.ctm = multiply(&[a, b, c, d, e, f], &graphics.ctm);
+                }
+            }
+            "g" | "rg" | "k" | "sc" | "scn" => {
+                if let Some(color) = to_rgb(&numbers(operands)) {
+                    graphics.fill = color;
+                }
+            }
+            "BDC" => {
+                let layer = match (operands.first().and_then(|o| o.as_name().ok()), operands.get(1)) {
+                    (Some(b"OC"), Some(Object::Name(name))) => properties
+                        .and_then(|p| p.get(name).ok())
+                        .and_then(|group| deref(doc, group).as_dict().ok())
+                        .and_then(|group| group.get(b"Name").ok())
+                        .and_then(|n| n.as_str().ok())
+                        .map(decode_text_string),
+                    _ => None,
+                };
+                marked_content.push(layer);
+            }
+            "BMC" => marked_content.push(None),
+            "EMC" => {
+                marked_content.pop();
+            }
+            "BT" => {
+                block += 1;
+                text.matrix = IDENTITY;
+                text.line_matrix = IDENTITY;
+            }
+            "Tf" => {
+                text.font = operands.first().and_then(|o| o.as_name().ok()).map(|n| n.to_vec());
+                text.size = operands.get(1).map(number).unwrap_or(0.0);
+            }
+            "Tc" => text.char_spacing = operands.first().map(number).unwrap_or(0.0),
+            "Tw" => text.word_spacing = operands.first().map(number).unwrap_or(0.0),
+            "Tz" => text.horizontal_scale = operands.first().map(number).unwrap_or(100.0) / 100.0,
+            "TL" => text.leading = operands.first().map(number).unwrap_or(0.0),
+            "Ts" => text.rise = operands.first().map(number).unwrap_or(0.0),
+            "Td" | "TD" => {
+                if let [tx, ty] = numbers(operands)[..] {
+                    if operation.operator == "TD" {
+                        text.leading = -ty;
+                    }
+                    text.line_matrix = multiply(&translate(tx, ty), &text.line_matrix);
+                    text.matrix = text.line_matrix;
+                }
+            }
+            "Tm" => {
+                if let [a, b, c, d, e, f] = numbers(operands)[..] {
+                    text.line_matrix = [a, b, c, d, e, f];
+                    text.matrix = text.line_matrix;
+                }
+            }
+            "T*" => {
+                text.line_matrix = multiply(&translate(0.0, -text.leading), &text.line_matrix);
+                text.matrix = text.line_matrix;
+            }
+            "Tj" | "TJ" | "'" | "\"" => {
+                if operation.operator == "'" || operation.operator == "\"" {
+                    if operation.operator == "\"" {
+                        text.word_spacing = operands.first().map(number).unwrap_or(0.0);
+                        text.char_spacing = operands.get(1).map(number).unwrap_or(0.0);
+                    }
+                    text.line_matrix = multiply(&translate(0.0, -text.leading), &text.line_matrix);
+                    text.matrix = text.line_matrix;
+                }
+
+                let Some(font) = text.font.as_ref().and_then(|name| fonts.get(name)) else {
+                    continue;
+                };
+                let layer = marked_content.iter().rev().find_map(|l| l.clone());
+                let elements: Vec<&Object> = match operation.operator.as_str() {
+                    "TJ" => operands.first()
+                        .and_then(|o| o.as_array().ok())
+                        .map(|a| a.iter().collect())
+                        .unwrap_or_default(),
+                    _ => operands.last().into_iter().collect(),
+                };
+                if let Some(run) = show_text(&mut text, &graphics, font, &elements, layer, block, corner) {
+                    runs.push(run);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(runs)
+}
+
+/// Lays out one show-text operation, advancing the text matrix as it goes.
+fn show_text(
+    text: &mut TextState,
+    graphics: &GraphicsState,
+    font: &FontInfo,
+    elements: &[&Object],
+    layer: Option<String>,
+    block: usize,
+    (left, top): (f64, f64),
+) -> Option<TextRun> {
+    let start = multiply(&text.matrix, &graphics.ctm);
+    let mut content = String::new();
+
+    for element in elements {
+        match element {
+            Object::String(bytes, _) => {
+                content.push_str(&font.decode(bytes));
+                for code in font.codes(bytes) {
+                    let mut advance = font.width(code) * text.size + text.char_spacing;
+                    if !font.two_byte && code == 32 {
+                        advance += text.word_spacing;
+                    }
+                    text.matrix = multiply(&translate(advance * text.horizontal_scale, 0.0), &text.matrix);
+                }
+            }
+            other => {
+                // Kerning adjustment in thousandths of an em; a large gap reads as a space
+                let adjustment = number(other);
+                if adjustment < -250.0 && !content.ends_with(' ') {
+                    content.push(' ');
+                }
+                let advance = -adjustment / 1000.0 * text.size * text.horizontal_scale;
+                text.matrix = multiply(&translate(advance, 0.0), &text.matrix);
+            }
+        }
+    }
+
+    if content.trim().is_empty() {
+        return None;
+    }
+
+    let end = multiply(&text.matrix, &graphics.ctm);
+    let scale = (start[0] * start[3] - start[1] * start[2]).abs().sqrt();
+    let font_size = text.size * scale;
+    let baseline = start[5] + text.rise * scale;
+    let (x1, x2) = (start[4].min(end[4]) - left, start[4].max(end[4]) - left);
+    let (y1, y2) = (top - (baseline + font.ascent * font_size), top - (baseline + font.descent * font_size));
+
+    Some(TextRun {
+        layer,
+        block,
+        text: content,
+        font_name: font.family.clone(),
+        font_size: font_size as f32,
+        color: graphics.fill,
+        bounds: (x1, y1, x2, y2),
+        baseline: top - baseline,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bfchar_and_bfrange_sections() {
+        let cmap = b"/CIDInit /ProcSet findresource begin\n\
+            2 beginbfchar\n<0003> <0020>\n<0024> <00410301>\nendbfchar\n\
+            1 beginbfrange\n<0010> <0012> <0061>\nendbfrange\n\
+            1 beginbfrange\n<0020> <0021> [<0066006C> <D835DC00>]\nendbfrange\n\
+            endcmap\n";
+        let map = parse_to_unicode(cmap);
+        assert_eq!(map[&0x03], " ");
+        // A code may stand for several characters
+        assert_eq!(map[&0x24], "A\u{301}");
+        assert_eq!((map[&0x10].as_str(), map[&0x11].as_str(), map[&0x12].as_str()), ("a", "b", "c"));
+        assert_eq!(map[&0x20], "fl");
+        assert_eq!(map[&0x21], "\u{1D400}");
+        assert_eq!(map.len(), 7);
+    }
+
+    #[test]
+    fn ignores_lines_outside_sections() {
+        let map = parse_to_unicode(b"1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n");
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn parses_both_forms_of_cid_widths() {
+        let w = vec![
+            Object::Integer(1),
+            Object::Array(vec![Object::Integer(500), Object::Real(250.5)]),
+            Object::Integer(10),
+            Object::Integer(12),
+            Object::Integer(600),
+        ];
+        let mut widths = HashMap::new();
+        parse_cid_widths(&Document::new(), &w, &mut widths);
+        assert_eq!(widths[&1], 500.0);
+        assert_eq!(widths[&2], 250.5);
+        assert_eq!((widths[&10], widths[&11], widths[&12]), (600.0, 600.0, 600.0));
+        assert_eq!(widths.len(), 5);
+    }
+
+    #[test]
+    fn names_families_from_postscript_names() {
+        assert_eq!(strip_subset_tag("ABCDEF+Arial-BoldMT"), "Arial-BoldMT");
+        assert_eq!(strip_subset_tag("Abcdef+Arial"), "Abcdef+Arial");
+        assert_eq!(family_from_postscript_name("Arial-BoldMT"), "Arial");
+        assert_eq!(family_from_postscript_name("TimesNewRomanPSMT"), "TimesNewRoman");
+        assert_eq!(family_from_postscript_name("Helvetica"), "Helvetica");
+    }
+
+    #[test]
+    fn measures_runs_from_the_media_box_corner() {
+        use lopdf::content::Operation;
+        use lopdf::{dictionary, Stream};
+
+        let mut doc = Document::with_version("1.5");
+        let font = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        let content = Content {
+            operations: vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 10.into()]),
+                Operation::new("Td", vec![150.into(), 900.into()]),
+                Operation::new("Tj", vec![Object::string_literal("Hi")]),
+                Operation::new("ET", vec![]),
+            ],
+        };
+        let contents = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+        let pages = doc.new_object_id();
+        let page = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages,
+            "Contents" => contents,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font } },
+            "MediaBox" => vec![100.into(), 200.into(), 712.into(), 992.into()],
+        });
+        doc.objects.insert(pages, Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page.into()],
+            "Count" => 1,
+        }));
+        let catalog = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages });
+        doc.trailer.set("Root", catalog);
+
+        let runs = extract_text_runs(&doc).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].text, "Hi");
+        assert_eq!(runs[0].baseline, 92.0);
+        assert_eq!(runs[0].bounds.0, 50.0);
+        assert!(runs[0].bounds.1 < 92.0 && runs[0].bounds.3 > 92.0);
+    }
+}
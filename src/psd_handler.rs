@@ -0,0 +1,525 @@
+use std::fs::File;
+use std::io::Read;
+use std::sync::Arc;
+use image::RgbaImage;
+use psd::Psd;
+use crate::error::{SourceError, Warning};
+use crate::layer_trait::SourceLayer;
+use crate::pdf_text::family_from_postscript_name;
+
+#[derive(Debug, Clone)]
+pub struct PsdLayer {
+    pub name: String,
+    pub content: String,
+    pub bounds: Option<(f64, f64, f64, f64)>, // x1, y1, x2, y2
+    pub font_name: Option<String>,
+    pub font_size: Option<f32>,
+    pub color: Option<(u8, u8, u8)>, // RGB
+    pub pixels: Option<RgbaImage>,
+}
+
+impl SourceLayer for PsdLayer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn content(&self) -> &str {
+        &self.content
+    }
+
+    fn bounds(&self) -> Option<(f64, f64, f64, f64)> {
+        self.bounds
+    }
+
+    fn font_name(&self) -> Option<&str> {
+        self.font_name.as_deref()
+    }
+
+    fn font_size(&self) -> Option<f32> {
+        self.font_size
+    }
+
+    fn color(&self) -> Option<(u8, u8, u8)> {
+        self.color
+    }
+
+    fn pixels(&self) -> Option<&RgbaImage> {
+        self.pixels.as_ref()
+    }
+}
+
+/// Text details pulled from a layer's `TySh` (type tool) block.
+#[derive(Debug, Default)]
+struct TextInfo {
+    content: String,
+    font_name: Option<String>,
+    font_size: Option<f32>,
+    color: Option<(u8, u8, u8)>,
+}
+
+pub struct PsdData {
+    layers: Vec<PsdLayer>,
+    warnings: Vec<Warning>,
+}
+
+impl PsdData {
+    pub fn new(path: &str) -> Result<Self, SourceError> {
+        let mut file = File::open(path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        check_length(&bytes)?;
+        let psd = Psd::from_bytes(&bytes)?;
+        // Text details are a bonus on top of the pixels, so a type tool block
+        // the parser can't follow only costs those
+        let mut warnings = Vec::new();
+        let text_layers = read_text_layers(&bytes).unwrap_or_else(|source| {
+            warnings.push(Warning::SourceTextLayers { path: path.to_string(), source: Arc::new(source) });
+            Vec::new()
+        });
+        // Type sizes are in points, which cover `resolution / 72` pixels
+        let pixels_per_point = read_resolution(&bytes).ok().flatten()
+            .filter(|resolution| *resolution > 0.0)
+            .map_or(1.0, |resolution| resolution / 72.0);
+
+        let layers = psd.layers().iter()
+            .map(|layer| {
+                // The psd crate reports right and bottom as inclusive pixel indices
+                let bounds = (
+                    layer.layer_left() as f64,
+                    layer.layer_top() as f64,
+                    (layer.layer_right() + 1) as f64,
+                    (layer.layer_bottom() + 1) as f64,
+                );
+                let text = text_layers.iter()
+                    .find(|(name, _)| name == layer.name())
+                    .map(|(_, text)| text);
+
+                PsdLayer {
+                    name: layer.name().to_string(),
+                    content: text.map(|t| t.content.clone()).unwrap_or_default(),
+                    bounds: Some(bounds),
+                    font_name: text.and_then(|t| t.font_name.clone()),
+                    font_size: text.and_then(|t| t.font_size).map(|size| size * pixels_per_point),
+                    color: text.and_then(|t| t.color),
+                    pixels: crop_layer(&layer.rgba(), psd.width(), psd.height(), bounds),
+                }
+            })
+            .collect();
+
+        Ok(Self { layers, warnings })
+    }
+
+    pub fn layers(&self) -> &[PsdLayer] {
+        &self.layers
+    }
+
+    /// Problems met while reading the file that still left it usable.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn into_warnings(self) -> Vec<Warning> {
+        self.warnings
+    }
+
+    pub fn get_layer_by_name(&self, name: &str) -> Option<&dyn SourceLayer> {
+        self.layers.iter()
+            .find(|layer| layer.name == name)
+            .map(|layer| layer as &dyn SourceLayer)
+    }
+}
+
+/// Cuts a layer's pixels out of the full-canvas RGBA buffer the psd crate returns.
+fn crop_layer(rgba: &[u8], width: u32, height: u32, bounds: (f64, f64, f64, f64)) -> Option<RgbaImage> {
+    let canvas = RgbaImage::from_raw(width, height, rgba.to_vec())?;
+    let x1 = (bounds.0.max(0.0) as u32).min(width);
+    let y1 = (bounds.1.max(0.0) as u32).min(height);
+    let x2 = (bounds.2.max(0.0) as u32).min(width);
+    let y2 = (bounds.3.max(0.0) as u32).min(height);
+    if x2 <= x1 || y2 <= y1 {
+        return None;
+    }
+    Some(image::imageops::crop_imm(&canvas, x1, y1, x2 - x1, y2 - y1).to_image())
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], SourceError> {
+        let end = self.position.checked_add(count)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(SourceError::Truncated)?;
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SourceError> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.take(N)?);
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, SourceError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SourceError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, SourceError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, SourceError> {
+        Ok(f64::from_be_bytes(self.array()?))
+    }
+
+    fn skip_section(&mut self) -> Result<(), SourceError> {
+        let length = self.u32()? as usize;
+        self.take(length)?;
+        Ok(())
+    }
+}
+
+/// Checks the file holds every section its header and section lengths call
+/// for, since the psd crate slices by them unchecked and panics on a file
+/// that was cut short.
+fn check_length(bytes: &[u8]) -> Result<(), SourceError> {
+    let mut cursor = Cursor { bytes, position: 12 };
+    let channels = cursor.u16()? as usize;
+    let height = cursor.u32()? as usize;
+    let width = cursor.u32()? as usize;
+    let depth = cursor.u16()? as usize;
+    cursor.u16()?; // color mode
+    cursor.skip_section()?; // color mode data
+    cursor.skip_section()?; // image resources
+    cursor.skip_section()?; // layer and mask information
+
+    let rows = channels.saturating_mul(height);
+    match cursor.u16()? {
+        // Raw rows of whole bytes
+        0 => {
+            cursor.take(rows.saturating_mul(width.saturating_mul(depth).div_ceil(8)))?;
+        }
+        // RLE rows, each byte count listed up front
+        1 => {
+            let mut length = 0usize;
+            for _ in 0..rows {
+                length = length.saturating_add(cursor.u16()? as usize);
+            }
+            cursor.take(length)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Reads the horizontal resolution, in pixels per inch, from the image resources.
+fn read_resolution(bytes: &[u8]) -> Result<Option<f32>, SourceError> {
+    let mut cursor = Cursor { bytes, position: 26 };
+    cursor.skip_section()?; // color mode data
+    let length = cursor.u32()? as usize;
+    let end = cursor.position + length;
+    while cursor.position + 12 <= end {
+        cursor.take(4)?; // 8BIM signature
+        let id = cursor.u16()?;
+        // The Pascal name, length byte included, is padded to an even size
+        let name_length = cursor.u8()? as usize;
+        cursor.take(name_length + (name_length + 1) % 2)?;
+        let size = cursor.u32()? as usize;
+        let mut data = Cursor { bytes: cursor.take(size)?, position: 0 };
+        cursor.take(size % 2)?;
+        // ResolutionInfo starts with the horizontal resolution as 16.16 fixed point
+        if id == 0x03ED {
+            return Ok(Some(data.u32()? as f32 / 65536.0));
+        }
+    }
+    Ok(None)
+}
+
+/// Walks the layer records for type tool blocks, since the psd crate skips them.
+/// Returns the layer name and text details of every text layer.
+fn read_text_layers(bytes: &[u8]) -> Result<Vec<(String, TextInfo)>, SourceError> {
+    let mut cursor = Cursor { bytes, position: 26 };
+    cursor.skip_section()?; // color mode data
+    cursor.skip_section()?; // image resources
+    cursor.u32()?; // layer and mask information length
+    if cursor.u32()? == 0 {
+        return Ok(Vec::new());
+    }
+    // A negative count only flags that the first alpha channel is transparency
+    let layer_count = (cursor.u16()? as i16).unsigned_abs();
+
+    let mut text_layers = Vec::new();
+    for _ in 0..layer_count {
+        cursor.take(16)?; // top, left, bottom, right
+        let channels = cursor.u16()? as usize;
+        cursor.take(channels * 6)?;
+        cursor.take(12)?; // blend mode signature and key, opacity, clipping, flags, filler
+        let extra_length = cursor.u32()? as usize;
+        let extra_end = cursor.position + extra_length;
+
+        cursor.skip_section()?; // layer mask data
+        cursor.skip_section()?; // blending ranges
+        let name_length = cursor.u8()? as usize;
+        let mut name = String::from_utf8_lossy(cursor.take(name_length)?).to_string();
+        // The Pascal name is padded to a multiple of four bytes
+        cursor.take((4 - (name_length + 1) % 4) % 4)?;
+
+        let mut text = None;
+        while cursor.position + 12 <= extra_end {
+            cursor.take(4)?; // 8BIM signature
+            let key = cursor.take(4)?;
+            let length = cursor.u32()? as usize;
+            let data = cursor.take(length.min(extra_end - cursor.position))?;
+            match key {
+                b"luni" => name = read_unicode_name(data).unwrap_or(name),
+                b"TySh" => text = read_type_tool(data).ok(),
+                _ => {}
+            }
+        }
+        cursor.position = extra_end;
+
+        if let Some(text) = text {
+            text_layers.push((name, text));
+        }
+    }
+
+    Ok(text_layers)
+}
+
+fn read_unicode_name(data: &[u8]) -> Option<String> {
+    let count = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let units: Vec<u16> = data.get(4..4 + count * 2)?
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    Some(String::from_utf16_lossy(&units).trim_end_matches('\0').to_string())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+// Reads the number following `key` in the engine data, e.g. `/FontSize 120.0`
+fn engine_number(data: &[u8], key: &[u8]) -> Option<f32> {
+    let start = find(data, key)? + key.len();
+    let text: String = data[start..].iter()
+        .map(|&b| b as char)
+        .skip_while(|c| c.is_whitespace())
+        .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == '-')
+        .collect();
+    text.parse().ok()
+}
+
+/// Parses a type tool block: its transform, the `Txt ` descriptor item and the
+/// font, size and fill color from the first style run in the engine data.
+fn read_type_tool(data: &[u8]) -> Result<TextInfo, SourceError> {
+    let mut cursor = Cursor { bytes: data, position: 0 };
+    cursor.u16()?; // version
+    let (xx, xy, yx, yy) = (cursor.f64()?, cursor.f64()?, cursor.f64()?, cursor.f64()?);
+    let scale = (xx * yy - xy * yx).abs().sqrt() as f32;
+
+    let text_start = find(data, b"Txt TEXT").ok_or(SourceError::NoText)? + 8;
+    let mut cursor = Cursor { bytes: data, position: text_start };
+    let count = cursor.u32()? as usize;
+    let units: Vec<u16> = cursor.take(count * 2)?
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    let content = String::from_utf16_lossy(&units)
+        .trim_end_matches(['\0', '\r'])
+        .replace('\r', "\n");
+
+    let font_size = engine_number(data, b"/FontSize ").map(|size| size * scale);
+
+    // Colors are stored as ARGB components in 0..1
+    let color = find(data, b"/FillColor").and_then(|start| {
+        let values = &data[start..];
+        let open = find(values, b"/Values [")? + 9;
+        let close = open + find(&values[open..], b"]")?;
+        let components: Vec<f32> = String::from_utf8_lossy(&values[open..close])
+            .split_whitespace()
+            .filter_map(|v| v.parse().ok())
+            .collect();
+        let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        match components[..] {
+            [_, r, g, b] => Some((channel(r), channel(g), channel(b))),
+            _ => None,
+        }
+    });
+
+    // Font names are UTF-16BE strings with a byte order mark inside parentheses.
+    // The closing parenthesis is a whole code unit; a unit's high byte may be
+    // 0x29 too, as in U+2900 to U+29FF.
+    let font_name = find(data, b"/FontSet").and_then(|start| {
+        let names = &data[start..];
+        let open = find(names, b"/Name (\xFE\xFF")? + 9;
+        let units: Vec<u16> = names[open..]
+            .chunks_exact(2)
+            .take_while(|pair| *pair != b"\0)")
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        Some(family_from_postscript_name(&String::from_utf16_lossy(&units)))
+    });
+
+    Ok(TextInfo { content, font_name, font_size, color })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    // A type tool block with an identity transform, the text and the engine
+    // data's first style run and font
+    fn type_tool(text: &str, font_name: &str) -> Vec<u8> {
+        let mut data = 1u16.to_be_bytes().to_vec();
+        for value in [1.0f64, 0.0, 0.0, 1.0, 0.0, 0.0] {
+            data.extend(value.to_be_bytes());
+        }
+        data.extend(b"Txt TEXT");
+        data.extend((text.encode_utf16().count() as u32 + 1).to_be_bytes());
+        data.extend(utf16(text));
+        data.extend([0, 0]);
+        data.extend(b"/EngineDict << /StyleRun << /RunArray [ << /StyleSheet << /StyleSheetData << \
+            /FontSize 24.0 /FillColor << /Type 1 /Values [ 1.0 1.0 0.0 0.0 ] >> >> >> >> ] >> >> \
+            /ResourceDict << /FontSet [ << /Name (\xFE\xFF");
+        data.extend(utf16(font_name));
+        data.extend(b") >> ] >>");
+        if data.len() % 2 == 1 {
+            data.push(0);
+        }
+        data
+    }
+
+    // A 4 by 2 RGB document at 144 pixels per inch with one layer covering it
+    fn psd_file(layer_name: &str, type_tool: &[u8]) -> Vec<u8> {
+        let (width, height) = (4u32, 2u32);
+        let plane = (width * height) as usize;
+
+        let mut bytes = b"8BPS".to_vec();
+        bytes.extend(1u16.to_be_bytes());
+        bytes.extend([0; 6]);
+        bytes.extend(3u16.to_be_bytes());
+        bytes.extend(height.to_be_bytes());
+        bytes.extend(width.to_be_bytes());
+        bytes.extend(8u16.to_be_bytes());
+        bytes.extend(3u16.to_be_bytes());
+        bytes.extend(0u32.to_be_bytes()); // color mode data
+
+        let mut resolution = b"8BIM".to_vec();
+        resolution.extend(0x03EDu16.to_be_bytes());
+        resolution.extend([0, 0]);
+        resolution.extend(16u32.to_be_bytes());
+        resolution.extend((144u32 << 16).to_be_bytes());
+        resolution.extend([0, 1, 0, 1, 0, 0x90, 0, 0, 0, 1, 0, 1]);
+        bytes.extend((resolution.len() as u32).to_be_bytes());
+        bytes.extend(resolution);
+
+        let mut extra = 0u32.to_be_bytes().to_vec(); // layer mask data
+        extra.extend(0u32.to_be_bytes()); // blending ranges
+        let mut name = vec![layer_name.len() as u8];
+        name.extend(layer_name.as_bytes());
+        name.resize(name.len().div_ceil(4) * 4, 0);
+        extra.extend(name);
+        extra.extend(b"8BIMTySh");
+        extra.extend((type_tool.len() as u32).to_be_bytes());
+        extra.extend(type_tool);
+
+        let mut layers = 1u16.to_be_bytes().to_vec();
+        for edge in [0, 0, height, width] {
+            layers.extend(edge.to_be_bytes());
+        }
+        layers.extend(4u16.to_be_bytes());
+        for channel in [-1i16, 0, 1, 2] {
+            layers.extend(channel.to_be_bytes());
+            layers.extend((2 + plane as u32).to_be_bytes());
+        }
+        layers.extend(b"8BIMnorm");
+        layers.extend([255, 0, 0, 0]);
+        layers.extend((extra.len() as u32).to_be_bytes());
+        layers.extend(extra);
+        for value in [255, 255, 0, 0] {
+            layers.extend(0u16.to_be_bytes()); // raw
+            layers.extend(vec![value; plane]);
+        }
+        if layers.len() % 2 == 1 {
+            layers.push(0);
+        }
+
+        let mut layer_and_mask = (layers.len() as u32).to_be_bytes().to_vec();
+        layer_and_mask.extend(layers);
+        layer_and_mask.extend(0u32.to_be_bytes()); // global layer mask
+        bytes.extend((layer_and_mask.len() as u32).to_be_bytes());
+        bytes.extend(layer_and_mask);
+
+        bytes.extend(0u16.to_be_bytes()); // raw image data
+        for value in [255, 0, 0] {
+            bytes.extend(vec![value; plane]);
+        }
+        bytes
+    }
+
+    fn load(name: &str, bytes: &[u8]) -> Result<PsdData, SourceError> {
+        let path = std::env::temp_dir().join(format!("kit-{}-{}.psd", name, std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        let data = PsdData::new(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        data
+    }
+
+    #[test]
+    fn reads_a_text_layer() {
+        let psd = load("text-layer", &psd_file("Title", &type_tool("Hello\rthere", "Sans\u{2901}Pro-Bold"))).unwrap();
+        assert!(psd.warnings().is_empty());
+        let layer = psd.get_layer_by_name("Title").unwrap();
+        assert_eq!(layer.content(), "Hello\nthere");
+        // A unit whose high byte is a parenthesis doesn't end the name
+        assert_eq!(layer.font_name(), Some("Sans\u{2901}Pro"));
+        // 24 points at 144 pixels per inch
+        assert_eq!(layer.font_size(), Some(48.0));
+        assert_eq!(layer.color(), Some((255, 0, 0)));
+        assert_eq!(layer.bounds(), Some((0.0, 0.0, 4.0, 2.0)));
+        let pixels = layer.pixels().unwrap();
+        assert_eq!(pixels.dimensions(), (4, 2));
+        assert_eq!(pixels.get_pixel(0, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn truncated_files_fail_to_load() {
+        let bytes = psd_file("Title", &type_tool("Hello", "Arial"));
+        for length in [20, bytes.len() / 2, bytes.len() - 1] {
+            assert!(matches!(load("truncated", &bytes[..length]), Err(SourceError::Truncated)), "{}", length);
+        }
+        assert!(load("whole", &bytes).is_ok());
+    }
+
+    #[test]
+    fn reads_the_resolution_resource() {
+        let mut resource = b"8BIM".to_vec();
+        resource.extend(0x03EDu16.to_be_bytes());
+        resource.extend([0, 0]); // empty name, padded
+        resource.extend(16u32.to_be_bytes());
+        resource.extend((300u32 << 16).to_be_bytes());
+        resource.extend([0; 12]);
+
+        let mut bytes = vec![0; 26];
+        bytes.extend(0u32.to_be_bytes()); // color mode data
+        bytes.extend((resource.len() as u32).to_be_bytes());
+        bytes.extend(resource);
+        assert_eq!(read_resolution(&bytes).unwrap(), Some(300.0));
+
+        bytes.truncate(40);
+        assert!(matches!(read_resolution(&bytes), Err(SourceError::Truncated)));
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt;
+use crate::error::Warning;
+use crate::layer_trait::SourceLayer;
+use crate::variables::Placeholder;
+
+/// What `Template::inspect` finds: the canvas, the variables the template
+/// refers to, the layers of its source file and where each layer lands.
+/// Displays as the report the `inspect` command prints.
+pub struct Report {
+    pub width: u32,
+    pub height: u32,
+    pub background: String,
+    pub variables: Vec<Placeholder>,
+    /// Layers of the source file, when the template has one
+    pub source_layers: Option<Vec<SourceLayerReport>>,
+    pub groups: Vec<GroupReport>,
+    /// Problems met along the way that don't stop the template from rendering
+    pub warnings: Vec<Warning>,
+}
+
+/// A layer of the template's source file, with whatever it carries.
+pub struct SourceLayerReport {
+    pub name: String,
+    pub content: String,
+    pub bounds: Option<(f64, f64, f64, f64)>,
+    pub font_name: Option<String>,
+    pub font_size: Option<f32>,
+    pub color: Option<(u8, u8, u8)>,
+    /// Width and height of the layer's pixels, for sources with raster data
+    pub pixel_size: Option<(u32, u32)>,
+}
+
+pub struct GroupReport {
+    pub name: String,
+    pub layers: Vec<LayerReport>,
+}
+
+/// A layer as laid out: what it draws, its size and its canvas position.
+pub struct LayerReport {
+    pub name: String,
+    pub content: LayerContent,
+    pub width: u32,
+    pub height: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+pub enum LayerContent {
+    /// The layer's final text
+    Text(String),
+    /// The image file drawn
+    Image(String),
+    /// An image taken from the source layer's pixels
+    SourceImage,
+}
+
+impl SourceLayerReport {
+    pub(crate) fn new(layer: &dyn SourceLayer) -> Self {
+        SourceLayerReport {
+            name: layer.name().to_string(),
+            content: layer.content().to_string(),
+            bounds: layer.bounds(),
+            font_name: layer.font_name().map(str::to_string),
+            font_size: layer.font_size(),
+            color: layer.color(),
+            pixel_size: layer.pixels().map(|pixels| pixels.dimensions()),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Canvas: {}x{}, background {}", self.width, self.height, self.background)?;
+
+        if !self.variables.is_empty() {
+            let described: Vec<String> = self.variables.iter()
+                .map(|p| match &p.default {
+                    Some(default) => format!("{} (default \"{}\")", p.name, default),
+                    None => p.name.clone(),
+                })
+                .collect();
+            writeln!(f, "Variables: {}", described.join(", "))?;
+        }
+
+        if let Some(source_layers) = &self.source_layers {
+            writeln!(f, "Source layers:")?;
+            for source_layer in source_layers {
+                writeln!(f, "  {}", source_layer)?;
+            }
+        }
+
+        for group in &self.groups {
+            writeln!(f, "Group '{}'", group.name)?;
+            for layer in &group.layers {
+                writeln!(f, "  {}", layer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SourceLayerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut details = Vec::new();
+        if !self.content.is_empty() {
+            details.push(format!("\"{}\"", self.content));
+        }
+        if let Some((x1, y1, x2, y2)) = self.bounds {
+            details.push(format!("bounds ({}, {}, {}, {})", x1, y1, x2, y2));
+        }
+        if let Some(font_name) = &self.font_name {
+            details.push(format!("font {}", font_name));
+        }
+        if let Some(font_size) = self.font_size {
+            details.push(format!("size {}", font_size));
+        }
+        if let Some((r, g, b)) = self.color {
+            details.push(format!("color #{:02X}{:02X}{:02X}", r, g, b));
+        }
+        if let Some((width, height)) = self.pixel_size {
+            details.push(format!("{}x{} pixels", width, height));
+        }
+        if details.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{} {}", self.name, details.join(", "))
+        }
+    }
+}
+
+impl fmt::Display for LayerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match &self.content {
+            LayerContent::Text(text) => format!("text \"{}\"", text),
+            LayerContent::Image(source) => format!("image {}", source),
+            LayerContent::SourceImage => "image from source layer".to_string(),
+        };
+        write!(f, "{} ({}): {}x{} at ({}, {})", self.name, description, self.width, self.height, self.x, self.y)
+    }
+}
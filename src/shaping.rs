@@ -0,0 +1,452 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use rusttype::{Font, GlyphId, Scale};
+use serde::Deserialize;
+use unicode_bidi::{BidiClass, BidiInfo, Level};
+
+/// A font ready for both shaping and drawing. rustybuzz shapes from the raw
+/// font data while rusttype rasterizes and measures the glyphs. Clones share
+/// the font.
+#[derive(Clone)]
+pub struct LoadedFont {
+    // Both borrow from `data`, so they are declared first to be dropped first
+    face: rustybuzz::Face<'static>,
+    font: Font<'static>,
+    data: Arc<Vec<u8>>,
+}
+
+impl LoadedFont {
+    pub fn from_data(data: Vec<u8>) -> Option<Self> {
+        let data = Arc::new(data);
+        // SAFETY: the bytes live on the heap behind the `Arc`, which is never
+        // mutated and which this font and each of its clones keep alive for
+        // as long as their face and font. Moving the `Arc` doesn't move the
+        // bytes, and `face()` and `Deref` only lend the parsed font out for as
+        // long as `self` is borrowed.
+        let bytes: &'static [u8] = unsafe { std::slice::from_raw_parts(data.as_ptr(), data.len()) };
+        let face = rustybuzz::Face::from_slice(bytes, 0)?;
+        let font = Font::try_from_bytes(bytes)?;
+        Some(LoadedFont { face, font, data })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The font parsed for shaping, parsed once when the font is loaded.
+    pub fn face(&self) -> &rustybuzz::Face<'_> {
+        &self.face
+    }
+
+    /// The font parsed for rasterizing and measuring glyphs.
+    pub fn font(&self) -> &Font<'_> {
+        &self.font
+    }
+
+    fn covers(&self, character: char) -> bool {
+        self.font.glyph(character).id().0 != 0
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TextDirection {
+    // Taken from the first strong character of each paragraph
+    Auto,
+    Ltr,
+    Rtl,
+}
+
+/// A glyph placed along a run of text, `x` pixels from the start of the run
+/// and `y` pixels below the baseline.
+#[derive(Clone)]
+pub struct ShapedGlyph {
+    // Index of the font in the fallback chain the glyph is drawn from
+    pub font: usize,
+    pub id: GlyphId,
+    // The characters this glyph stands for; empty for the extra glyphs of a
+    // cluster, such as combining marks
+    pub text: String,
+    pub x: f32,
+    pub y: f32,
+    // Size relative to the run's font size; smaller for emulated small caps
+    pub size: f32,
+}
+
+/// A run of text laid out by the shaper. Measuring and drawing both go through
+/// this so they always agree.
+#[derive(Clone, Default)]
+pub struct ShapedText {
+    pub glyphs: Vec<ShapedGlyph>,
+    // Distance from the start of the run to the pen position after it
+    pub width: f32,
+    // Height of the tallest glyph's ink
+    pub height: u32,
+}
+
+/// Size of the capitals that stand in for lowercase letters in emulated small
+/// caps, relative to the font size
+pub const SMALL_CAPS_SIZE: f32 = 0.7;
+
+/// How a run of text is set beyond its font and size.
+#[derive(Clone, Copy, Default)]
+pub struct ShapeStyle<'a> {
+    pub rtl: bool,
+    // BCP 47 tag passed to the shaper for language-specific forms
+    pub language: Option<&'a str>,
+    // Draws lowercase letters as smaller capitals
+    pub small_caps: bool,
+    // Extra pixels between clusters
+    pub letter_spacing: f32,
+}
+
+/// Shapes a single-direction run of text with OpenType features, so ligatures,
+/// contextual forms and mark positioning come out as the font intends. Each
+/// character is shaped with the first font in `fonts` that has a glyph for it.
+/// Glyphs are returned in visual order, left to right, with the letter spacing
+/// between clusters but not after the last.
+pub fn shape(fonts: &[LoadedFont], scale: Scale, text: &str, style: ShapeStyle) -> ShapedText {
+    let mut shaped = ShapedText::default();
+
+    let mut runs = font_runs(fonts, text);
+    if style.rtl {
+        runs.reverse();
+    }
+    for (font_index, run) in runs {
+        if !style.small_caps {
+            shape_run(&mut shaped, fonts, font_index, scale, run, style);
+            continue;
+        }
+        let mut cases = case_runs(run);
+        if style.rtl {
+            cases.reverse();
+        }
+        for (lowercase, part) in cases {
+            if !lowercase {
+                shape_run(&mut shaped, fonts, font_index, scale, part, style);
+                continue;
+            }
+            let start = shaped.glyphs.len();
+            let small_scale = Scale { x: scale.x * SMALL_CAPS_SIZE, y: scale.y * SMALL_CAPS_SIZE };
+            shape_run(&mut shaped, fonts, font_index, small_scale, &part.to_uppercase(), style);
+            for glyph in &mut shaped.glyphs[start..] {
+                glyph.size = SMALL_CAPS_SIZE;
+            }
+        }
+    }
+
+    if style.letter_spacing != 0.0 {
+        space_letters(&mut shaped, style.letter_spacing);
+    }
+    shaped
+}
+
+// Splits `text` into runs of lowercase letters and runs of everything else.
+// Combining marks stay with the letter they sit on.
+fn case_runs(text: &str) -> Vec<(bool, &str)> {
+    let mut runs = Vec::new();
+    let mut current: Option<(bool, usize)> = None;
+
+    for (offset, character) in text.char_indices() {
+        let lowercase = character.is_lowercase();
+        match current {
+            Some(_) if unicode_bidi::bidi_class(character) == BidiClass::NSM => {}
+            Some((case, start)) if case != lowercase => {
+                runs.push((case, &text[start..offset]));
+                current = Some((lowercase, offset));
+            }
+            None => current = Some((lowercase, offset)),
+            _ => {}
+        }
+    }
+    if let Some((case, start)) = current {
+        runs.push((case, &text[start..]));
+    }
+
+    runs
+}
+
+/// Moves each cluster `spacing` pixels further than the one before it. Marks
+/// and the other extra glyphs of a cluster move with its first glyph.
+pub fn space_letters(shaped: &mut ShapedText, spacing: f32) {
+    let mut shift = 0.0;
+    for (index, glyph) in shaped.glyphs.iter_mut().enumerate() {
+        if index > 0 && !glyph.text.is_empty() {
+            shift += spacing;
+        }
+        glyph.x += shift;
+    }
+    shaped.width += shift;
+}
+
+/// Splits `text` into runs that each come from one font of the chain. Characters
+/// no font covers stay in the run around them, so joiners and variation
+/// selectors don't break up a sequence.
+fn font_runs<'a>(fonts: &[LoadedFont], text: &'a str) -> Vec<(usize, &'a str)> {
+    let mut runs = Vec::new();
+    let mut current: Option<(usize, usize)> = None;
+
+    for (offset, character) in text.char_indices() {
+        let covering = fonts.iter().position(|font| font.covers(character));
+        match (current, covering) {
+            (None, font_index) => current = Some((font_index.unwrap_or(0), offset)),
+            (Some((font_index, start)), Some(covering)) if covering != font_index => {
+                runs.push((font_index, &text[start..offset]));
+                current = Some((covering, offset));
+            }
+            _ => {}
+        }
+    }
+    if let Some((font_index, start)) = current {
+        runs.push((font_index, &text[start..]));
+    }
+
+    runs
+}
+
+fn shape_run(shaped: &mut ShapedText, fonts: &[LoadedFont], font_index: usize, scale: Scale, text: &str, style: ShapeStyle) {
+    let font = &fonts[font_index];
+
+    let mut buffer = rustybuzz::UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.set_direction(if style.rtl { rustybuzz::Direction::RightToLeft } else { rustybuzz::Direction::LeftToRight });
+    if let Some(language) = style.language.and_then(|language| rustybuzz::Language::from_str(language).ok()) {
+        buffer.set_language(language);
+    }
+    let output = rustybuzz::shape(font.face(), &[], buffer);
+
+    // Font units to pixels, matching rusttype's scaling by ascent to descent
+    let v_metrics = font.font().v_metrics_unscaled();
+    let x_scale = scale.x / (v_metrics.ascent - v_metrics.descent);
+    let y_scale = scale.y / (v_metrics.ascent - v_metrics.descent);
+
+    // Each cluster covers the text up to the next cluster's start
+    let mut cluster_starts: Vec<usize> = output.glyph_infos().iter().map(|info| info.cluster as usize).collect();
+    cluster_starts.sort_unstable();
+    cluster_starts.dedup();
+    let cluster_text = |start: usize| {
+        let end = cluster_starts.iter().find(|&&s| s > start).copied().unwrap_or(text.len());
+        text.get(start..end).unwrap_or_default().to_string()
+    };
+
+    let mut seen_clusters = Vec::new();
+    for (info, position) in output.glyph_infos().iter().zip(output.glyph_positions()) {
+        let id = GlyphId(info.glyph_id as u16);
+        let cluster = info.cluster as usize;
+        let glyph_text = if seen_clusters.contains(&cluster) {
+            String::new()
+        } else {
+            seen_clusters.push(cluster);
+            cluster_text(cluster)
+        };
+
+        let x = shaped.width + position.x_offset as f32 * x_scale;
+        // Shaper offsets point up; canvas y points down
+        let y = -(position.y_offset as f32) * y_scale;
+        let glyph = font.font().glyph(id).scaled(scale).positioned(rusttype::point(0.0, y));
+        if let Some(bounding_box) = glyph.pixel_bounding_box() {
+            shaped.height = shaped.height.max(bounding_box.height() as u32);
+        }
+
+        shaped.glyphs.push(ShapedGlyph { font: font_index, id, text: glyph_text, x, y, size: 1.0 });
+        shaped.width += position.x_advance as f32 * x_scale;
+    }
+}
+
+/// Characters of `text` that no font in the chain has a glyph for.
+pub fn uncovered_chars(fonts: &[LoadedFont], text: &str) -> Vec<char> {
+    let mut uncovered = Vec::new();
+    for character in text.chars() {
+        // Joiners and variation selectors have no glyph of their own
+        let invisible = matches!(character, '\u{200C}' | '\u{200D}' | '\u{FE00}'..='\u{FE0F}');
+        if !character.is_whitespace()
+            && !character.is_control()
+            && !invisible
+            && !uncovered.contains(&character)
+            && !fonts.iter().any(|font| font.covers(character))
+        {
+            uncovered.push(character);
+        }
+    }
+    uncovered
+}
+
+/// Advance of the font's space glyph, the gap drawn between words.
+pub fn space_width(font: &Font, scale: Scale) -> f32 {
+    font.glyph(' ').scaled(scale).h_metrics().advance_width
+}
+
+/// A piece of a word in a single bidi embedding level
+pub type LevelRun<'a> = (&'a str, Level);
+
+/// Resolves the bidi embedding levels of a paragraph's words, taken to be
+/// separated by single spaces. Each word is split into runs wherever its level
+/// changes, so every run can be shaped in one direction. Also returns the
+/// level of the space after each word but the last.
+pub fn word_runs<'a>(words: &[&'a str], direction: TextDirection) -> (Vec<Vec<LevelRun<'a>>>, Vec<Level>) {
+    let paragraph = words.join(" ");
+    let base_level = match direction {
+        TextDirection::Auto => None,
+        TextDirection::Ltr => Some(Level::ltr()),
+        TextDirection::Rtl => Some(Level::rtl()),
+    };
+    let bidi = BidiInfo::new(&paragraph, base_level);
+    let level_at = |offset: usize| bidi.levels.get(offset).copied().unwrap_or_else(Level::ltr);
+
+    let mut runs = Vec::new();
+    let mut spaces = Vec::new();
+    let mut word_start = 0;
+    for (index, word) in words.iter().enumerate() {
+        let mut word_runs = Vec::new();
+        let mut run_start = 0;
+        for (offset, _) in word.char_indices().skip(1) {
+            if level_at(word_start + offset) != level_at(word_start + run_start) {
+                word_runs.push((&word[run_start..offset], level_at(word_start + run_start)));
+                run_start = offset;
+            }
+        }
+        if !word.is_empty() {
+            word_runs.push((&word[run_start..], level_at(word_start + run_start)));
+        }
+        runs.push(word_runs);
+
+        word_start += word.len();
+        if index + 1 < words.len() {
+            spaces.push(level_at(word_start));
+        }
+        word_start += 1;
+    }
+
+    (runs, spaces)
+}
+
+/// The order to draw a line's items in, left to right, given their embedding
+/// levels: every run at or above each level is reversed, from the highest
+/// level down to the lowest odd one (rule L2 of the bidi algorithm).
+pub fn visual_order(levels: &[Level]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    let Some(highest) = levels.iter().map(|level| level.number()).max() else {
+        return order;
+    };
+    let Some(lowest_odd) = levels.iter().map(|level| level.number()).filter(|number| number % 2 == 1).min() else {
+        return order;
+    };
+
+    for level in (lowest_odd..=highest).rev() {
+        let mut index = 0;
+        while index < order.len() {
+            if levels[order[index]].number() >= level {
+                let start = index;
+                while index < order.len() && levels[order[index]].number() >= level {
+                    index += 1;
+                }
+                order[start..index].reverse();
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(numbers: &[u8]) -> Vec<Level> {
+        numbers.iter().map(|&number| Level::new(number).unwrap()).collect()
+    }
+
+    #[test]
+    fn visual_order_reverses_right_to_left_runs() {
+        assert_eq!(visual_order(&levels(&[0, 0, 0])), vec![0, 1, 2]);
+        assert_eq!(visual_order(&levels(&[0, 1, 1, 0])), vec![0, 2, 1, 3]);
+        // Numbers inside right-to-left text keep their own order
+        assert_eq!(visual_order(&levels(&[1, 2, 2, 1])), vec![3, 1, 2, 0]);
+        assert!(visual_order(&[]).is_empty());
+    }
+
+    #[test]
+    fn word_runs_split_words_where_the_direction_changes() {
+        let (runs, spaces) = word_runs(&["abcשלום", "def"], TextDirection::Ltr);
+        assert_eq!(runs, vec![
+            vec![("abc", Level::ltr()), ("שלום", Level::rtl())],
+            vec![("def", Level::ltr())],
+        ]);
+        assert_eq!(spaces, vec![Level::ltr()]);
+
+        let (runs, spaces) = word_runs(&["שלום(123)עולם", "x"], TextDirection::Rtl);
+        assert_eq!(runs[0], vec![("שלום(", Level::rtl()), ("123", Level::new(2).unwrap()), (")עולם", Level::rtl())]);
+        assert_eq!(runs[1], vec![("x", Level::new(2).unwrap())]);
+        assert_eq!(spaces, vec![Level::rtl()]);
+    }
+
+    #[test]
+    fn word_runs_take_the_paragraph_direction_from_its_text() {
+        let (runs, _) = word_runs(&["שלום", "abc"], TextDirection::Auto);
+        assert_eq!(runs[0], vec![("שלום", Level::rtl())]);
+        assert_eq!(runs[1], vec![("abc", Level::new(2).unwrap())]);
+    }
+
+    #[test]
+    fn shapes_with_the_loaded_face() {
+        let font = LoadedFont::from_data(std::fs::read("fonts/arial.ttf").unwrap()).unwrap();
+        let scale = Scale::uniform(40.0);
+        let shaped = shape(std::slice::from_ref(&font), scale, "Hi", ShapeStyle::default());
+
+        let texts: Vec<&str> = shaped.glyphs.iter().map(|glyph| glyph.text.as_str()).collect();
+        assert_eq!(texts, vec!["H", "i"]);
+        assert_eq!(shaped.glyphs[0].id, font.font().glyph('H').id());
+        // Shaping and rusttype agree on advances
+        let advance = |c| font.font().glyph(c).scaled(scale).h_metrics().advance_width;
+        assert!((shaped.width - advance('H') - advance('i')).abs() < 0.01);
+        assert!((shaped.glyphs[1].x - advance('H')).abs() < 0.01);
+    }
+
+    #[test]
+    fn case_runs_keep_marks_with_their_letter() {
+        assert_eq!(case_runs("HeLLo"), vec![(false, "H"), (true, "e"), (false, "LL"), (true, "o")]);
+        assert_eq!(case_runs("e\u{301}A"), vec![(true, "e\u{301}"), (false, "A")]);
+        assert_eq!(case_runs("A\u{301}b"), vec![(false, "A\u{301}"), (true, "b")]);
+        assert!(case_runs("").is_empty());
+    }
+
+    fn glyph(x: f32, text: &str) -> ShapedGlyph {
+        ShapedGlyph { font: 0, id: GlyphId(0), text: text.to_string(), x, y: 0.0, size: 1.0 }
+    }
+
+    #[test]
+    fn letter_spacing_moves_clusters_with_their_marks() {
+        let mut shaped = ShapedText {
+            glyphs: vec![glyph(0.0, "a"), glyph(10.0, "e\u{301}"), glyph(12.0, ""), glyph(20.0, "b")],
+            width: 30.0,
+            height: 10,
+        };
+        space_letters(&mut shaped, 2.0);
+        let xs: Vec<f32> = shaped.glyphs.iter().map(|glyph| glyph.x).collect();
+        assert_eq!(xs, vec![0.0, 12.0, 14.0, 24.0]);
+        // No spacing after the last cluster
+        assert_eq!(shaped.width, 34.0);
+    }
+
+    #[test]
+    fn small_caps_draw_lowercase_as_smaller_capitals() {
+        let font = LoadedFont::from_data(std::fs::read("fonts/arial.ttf").unwrap()).unwrap();
+        let style = ShapeStyle { small_caps: true, ..ShapeStyle::default() };
+        let shaped = shape(std::slice::from_ref(&font), Scale::uniform(40.0), "Ab", style);
+
+        assert_eq!(shaped.glyphs[0].size, 1.0);
+        assert_eq!(shaped.glyphs[1].size, SMALL_CAPS_SIZE);
+        assert_eq!(shaped.glyphs[1].id, font.font().glyph('B').id());
+    }
+
+    #[test]
+    fn right_to_left_runs_come_out_in_visual_order() {
+        let font = LoadedFont::from_data(std::fs::read("fonts/arial.ttf").unwrap()).unwrap();
+        let style = ShapeStyle { rtl: true, ..ShapeStyle::default() };
+        let shaped = shape(std::slice::from_ref(&font), Scale::uniform(40.0), "שלום", style);
+        let texts: Vec<&str> = shaped.glyphs.iter().map(|glyph| glyph.text.as_str()).collect();
+        assert_eq!(texts, vec!["ם", "ו", "ל", "ש"]);
+    }
+}
@@ -0,0 +1,62 @@
+use serde::Deserialize;
+use crate::ai_handler::AiData;
+use crate::error::{KitError, Warning};
+use crate::layer_trait::SourceLayer;
+use crate::psd_handler::PsdData;
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SourceType {
+    AI,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+pub(crate) struct SourceFile {
+    pub(crate) path: String,
+    #[serde(rename = "type")]
+    pub(crate) file_type: SourceType,
+}
+
+pub(crate) enum SourceData {
+    Ai(AiData),
+    Psd(PsdData),
+}
+
+impl SourceData {
+    /// Loads an Illustrator or Photoshop file, telling them apart by extension.
+    /// Illustrator measures in points, which become pixels at the template's `dpi`.
+    pub(crate) fn load(path: &str, dpi: f32) -> Result<Self, KitError> {
+        let data = if path.ends_with(".ai") {
+            AiData::new(path).map(|ai| SourceData::Ai(ai.scaled(dpi / 72.0)))
+        } else if path.ends_with(".psd") {
+            PsdData::new(path).map(SourceData::Psd)
+        } else {
+            return Err(KitError::UnsupportedSource(path.to_string()));
+        };
+        data.map_err(|source| KitError::SourceLoad { path: path.to_string(), source })
+    }
+
+    pub(crate) fn get_layer_by_name(&self, name: &str) -> Option<&dyn SourceLayer> {
+        match self {
+            SourceData::Ai(ai) => ai.get_layer_by_name(name),
+            SourceData::Psd(psd) => psd.get_layer_by_name(name),
+        }
+    }
+
+    pub(crate) fn layers(&self) -> Vec<&dyn SourceLayer> {
+        match self {
+            SourceData::Ai(ai) => ai.layers().iter().map(|layer| layer as &dyn SourceLayer).collect(),
+            SourceData::Psd(psd) => psd.layers().iter().map(|layer| layer as &dyn SourceLayer).collect(),
+        }
+    }
+
+    /// Problems met while reading the file that still left it usable.
+    pub(crate) fn warnings(&self) -> &[Warning] {
+        match self {
+            SourceData::Ai(_) => &[],
+            SourceData::Psd(psd) => psd.warnings(),
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use std::io::Cursor;
+use std::path::Path;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use image::{DynamicImage, ImageOutputFormat, Rgba};
+use crate::compositing::BlendMode;
+use crate::effects::{StrokeJoin, TextShadow, TextStroke, MITER_LIMIT};
+use crate::error::{KitError, Location};
+use crate::fill::{self, FillBox, Paint};
+use crate::fonts::FontRegistry;
+use crate::variables::Variables;
+use crate::font_spec::{FontDecoration, FontSpec, FontStyle, FontVariant, FontWeight};
+use crate::layer::{ImageLayer, Layer, LayerInfo};
+use crate::layout::{LayerDimensions, Position};
+use crate::template::{PlacedLayer, Template};
+use crate::text_layer::TextLayer;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// SVG colors are written as hex plus a separate opacity, which every viewer understands
+fn color_attributes(name: &str, color: Rgba<u8>) -> String {
+    let [r, g, b, a] = color.0;
+    let mut attributes = format!(r##"{}="#{:02X}{:02X}{:02X}""##, name, r, g, b);
+    if a < 255 {
+        attributes.push_str(&format!(r#" {}-opacity="{:.3}""#, name, a as f32 / 255.0));
+    }
+    attributes
+}
+
+fn css_weight(weight: &FontWeight) -> &'static str {
+    match weight {
+        FontWeight::Normal => "normal",
+        FontWeight::Bold => "bold",
+        FontWeight::Weight100 => "100",
+        FontWeight::Weight200 => "200",
+        FontWeight::Weight300 => "300",
+        FontWeight::Weight400 => "400",
+        FontWeight::Weight500 => "500",
+        FontWeight::Weight600 => "600",
+        FontWeight::Weight700 => "700",
+        FontWeight::Weight800 => "800",
+        FontWeight::Weight900 => "900",
+    }
+}
+
+fn css_style(style: &FontStyle) -> &'static str {
+    match style {
+        FontStyle::Normal => "normal",
+        FontStyle::Italic => "italic",
+        FontStyle::Oblique => "oblique",
+    }
+}
+
+fn css_decoration(decoration: &FontDecoration) -> &'static str {
+    match decoration {
+        FontDecoration::None => "none",
+        FontDecoration::Underline => "underline",
+        FontDecoration::LineThrough => "line-through",
+        FontDecoration::Overline => "overline",
+    }
+}
+
+fn stroke_attributes(stroke: &TextStroke) -> Result<String, KitError> {
+    let join = match stroke.join {
+        StrokeJoin::Miter => "miter",
+        StrokeJoin::Round => "round",
+        StrokeJoin::Bevel => "bevel",
+    };
+    // SVG strokes are centered on the outline; painting them first leaves the
+    // outer half showing around the fill
+    Ok(format!(
+        r#" {} stroke-width="{:.2}" stroke-linejoin="{}" stroke-miterlimit="{}" paint-order="stroke""#,
+        color_attributes("stroke", stroke.rgba_color()?),
+        stroke.width * 2.0,
+        join,
+        MITER_LIMIT,
+    ))
+}
+
+// A drop shadow filter covering the whole canvas, so large offsets aren't clipped
+fn write_shadow_filter(svg: &mut String, id: &str, shadow: &TextShadow, width: u32, height: u32) -> Result<(), KitError> {
+    let [r, g, b, a] = shadow.rgba_color()?.0;
+    svg.push_str(&format!(
+        r##"  <filter id="{}" filterUnits="userSpaceOnUse" x="0" y="0" width="{}" height="{}"><feDropShadow dx="{:.2}" dy="{:.2}" stdDeviation="{:.2}" flood-color="#{:02X}{:02X}{:02X}" flood-opacity="{:.3}"/></filter>"##,
+        id, width, height, shadow.offset.x, shadow.offset.y, shadow.blur_sigma(), r, g, b, a as f32 / 255.0,
+    ));
+    svg.push('\n');
+    Ok(())
+}
+
+fn stop_elements(stops: &[(f32, Rgba<u8>)]) -> String {
+    stops.iter()
+        .map(|&(offset, color)| {
+            let [r, g, b, a] = color.0;
+            format!(
+                r##"<stop offset="{:.3}" stop-color="#{:02X}{:02X}{:02X}" stop-opacity="{:.3}"/>"##,
+                offset, r, g, b, a as f32 / 255.0,
+            )
+        })
+        .collect()
+}
+
+// The text's fill attribute. Gradients and patterns are written as a paint
+// server just before the element, in canvas coordinates over `area` so they
+// line up with the raster output.
+fn write_fill(svg: &mut String, id: &str, paint: &Paint, area: FillBox) -> Result<String, KitError> {
+    match paint {
+        Paint::Solid(color) => return Ok(color_attributes("fill", *color)),
+        Paint::Linear { angle, stops } => {
+            let ((x1, y1), (x2, y2)) = fill::linear_line(*angle, area);
+            svg.push_str(&format!(
+                r#"  <linearGradient id="{}" gradientUnits="userSpaceOnUse" x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}">{}</linearGradient>"#,
+                id, x1, y1, x2, y2, stop_elements(stops),
+            ));
+        }
+        Paint::Radial { center, radius, stops } => {
+            // A unit circle stretched into the gradient's ellipse
+            let ((cx, cy), (rx, ry)) = fill::radial_ellipse(*center, *radius, area);
+            svg.push_str(&format!(
+                r#"  <radialGradient id="{}" gradientUnits="userSpaceOnUse" cx="0" cy="0" r="1" gradientTransform="matrix({:.2} 0 0 {:.2} {:.2} {:.2})">{}</radialGradient>"#,
+                id, rx, ry, cx, cy, stop_elements(stops),
+            ));
+        }
+        Paint::Pattern { image, scale } => {
+            let png = png_base64(&DynamicImage::ImageRgba8(image.clone())).map_err(|source| KitError::Invalid {
+                at: Location::field("font.fill.source"),
+                message: format!("failed to encode pattern: {}", source),
+            })?;
+            let (width, height) = (image.width() as f32 * scale, image.height() as f32 * scale);
+            svg.push_str(&format!(
+                r#"  <pattern id="{}" patternUnits="userSpaceOnUse" x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}"><image width="{:.2}" height="{:.2}" preserveAspectRatio="none" href="data:image/png;base64,{}"/></pattern>"#,
+                id, area.0, area.1, width, height, width, height, png,
+            ));
+        }
+    }
+    svg.push('\n');
+    Ok(format!(r#"fill="url(#{})""#, id))
+}
+
+// Opacity and blend mode apply to the layer's element as a whole
+fn layer_attributes(info: &LayerInfo) -> String {
+    let mut attributes = String::new();
+    if info.opacity < 1.0 {
+        attributes.push_str(&format!(r#" opacity="{:.3}""#, info.opacity));
+    }
+    if info.blend_mode != BlendMode::Normal {
+        attributes.push_str(&format!(r#" style="mix-blend-mode: {}""#, info.blend_mode.name().to_lowercase()));
+    }
+    attributes
+}
+
+fn font_attributes(font: &FontSpec, em_size: f32) -> String {
+    let mut attributes = format!(
+        r#"font-family="{}" font-size="{:.2}" font-weight="{}" font-style="{}" text-decoration="{}""#,
+        // Viewers fall back through the list themselves
+        escape(&font.family.join(", ")),
+        em_size,
+        css_weight(&font.weight),
+        css_style(&font.style),
+        css_decoration(&font.decoration),
+    );
+    if font.variant == FontVariant::SmallCaps {
+        attributes.push_str(r#" font-variant="small-caps""#);
+    }
+    attributes
+}
+
+/// One `<text>` element per layer, with a `<tspan>` per word so word positions
+/// match the raster output exactly, or per glyph cluster, turned to follow it,
+/// for text on a path. A shadow becomes a filter written just before the
+/// element, named after the layer's place in the document. Warped text is
+/// embedded as the raster output's image instead.
+fn write_text(
+    svg: &mut String,
+    text: &TextLayer,
+    placed: &PlacedLayer,
+    index: usize,
+    canvas_width: u32,
+    canvas_height: u32,
+    fonts: &FontRegistry,
+) -> Result<(), KitError> {
+    let fonts = text.font.load_fonts(fonts)?;
+    if let Some(warp) = &text.warp {
+        // Bent glyphs can't be set as text, so the warped rendering goes in as an image
+        let image = DynamicImage::ImageRgba8(text.warped_image(&fonts, warp)?);
+        let png = png_base64(&image).map_err(|source| KitError::Invalid {
+            at: Location::field("warp"),
+            message: format!("failed to encode warped text: {}", source),
+        })?;
+        write_image_element(svg, &text.info, &placed.position, &placed.dimensions, &png);
+        return Ok(());
+    }
+    let (glyphs, layout) = text.place(&fonts, &placed.position);
+    let area = text.ink(&fonts, &placed.position).bounds
+        .map_or((0.0, 0.0, 0.0, 0.0), |(min_x, min_y, max_x, max_y)| {
+            (min_x as f32, min_y as f32, max_x as f32, max_y as f32)
+        });
+    let fill = write_fill(svg, &format!("fill-{}", index + 1), &text.font.paint()?, area)?;
+
+    let mut attributes = String::new();
+    let letter_spacing = text.letter_spacing.pixels(text.font.size);
+    if letter_spacing != 0.0 {
+        attributes.push_str(&format!(r#" letter-spacing="{:.2}""#, letter_spacing));
+    }
+    if let Some(stroke) = &text.stroke {
+        attributes.push_str(&stroke_attributes(stroke)?);
+    }
+    if let Some(shadow) = &text.shadow {
+        let id = format!("shadow-{}", index + 1);
+        write_shadow_filter(svg, &id, shadow, canvas_width, canvas_height)?;
+        attributes.push_str(&format!(r#" filter="url(#{})""#, id));
+    }
+    attributes.push_str(&layer_attributes(&text.info));
+
+    let em_size = text.font.em_size(fonts[0].font());
+    svg.push_str(&format!(
+        r#"  <text id="{}" {} {}{}>"#,
+        escape(&text.info.name),
+        font_attributes(&text.font, em_size),
+        fill,
+        attributes,
+    ));
+    match layout {
+        Some(layout) => {
+            for (line, word) in layout.words() {
+                // A justified single word spreads its letters instead
+                let spread = if word.letter_spread > 0.0 {
+                    format!(r#" letter-spacing="{:.2}""#, letter_spacing + word.letter_spread)
+                } else {
+                    String::new()
+                };
+                // Viewers shape and order the characters of each word themselves
+                svg.push_str(&format!(
+                    r#"<tspan x="{:.2}" y="{:.2}"{}>{}</tspan>"#,
+                    word.x,
+                    line.baseline,
+                    spread,
+                    escape(&word.text),
+                ));
+            }
+        }
+        None => {
+            // Marks and other extra glyphs of a cluster come with its first glyph
+            for glyph in glyphs.iter().filter(|glyph| !glyph.text.is_empty()) {
+                // Small caps are already capitals here, so they get their own size
+                let size = if glyph.size != 1.0 {
+                    format!(r#" font-size="{:.2}" font-variant="normal""#, em_size * glyph.size)
+                } else {
+                    String::new()
+                };
+                svg.push_str(&format!(
+                    r#"<tspan x="{:.2}" y="{:.2}" rotate="{:.2}"{}>{}</tspan>"#,
+                    glyph.x,
+                    glyph.y,
+                    glyph.angle.to_degrees(),
+                    size,
+                    escape(&glyph.text),
+                ));
+            }
+        }
+    }
+    svg.push_str("</text>\n");
+
+    Ok(())
+}
+
+fn png_base64(image: &DynamicImage) -> Result<String, image::ImageError> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    Ok(BASE64.encode(&png))
+}
+
+// An `<image>` element stretched over `dimensions` at `position`
+fn write_image_element(svg: &mut String, info: &LayerInfo, position: &Position, dimensions: &LayerDimensions, png: &str) {
+    svg.push_str(&format!(
+        r#"  <image id="{}" x="{}" y="{}" width="{}" height="{}"{} preserveAspectRatio="none" href="data:image/png;base64,{}"/>"#,
+        escape(&info.name),
+        position.x,
+        position.y,
+        dimensions.width,
+        dimensions.height,
+        layer_attributes(info),
+        png,
+    ));
+    svg.push('\n');
+}
+
+fn write_image(svg: &mut String, image: &ImageLayer, placed: &PlacedLayer) -> Result<(), KitError> {
+    let png = png_base64(&image.load_image()?).map_err(|source| KitError::ImageLoad {
+        at: Location::field("source"),
+        file: image.source.clone().into(),
+        source: Box::new(source),
+    })?;
+    write_image_element(svg, &image.info, &placed.position, &placed.dimensions, &png);
+    Ok(())
+}
+
+/// Writes the template as an SVG document in template pixels. Layers are laid
+/// out by `Template::compose`, the same as raster output; images are embedded
+/// as PNG data URLs so the file stands alone.
+pub fn write_svg(template: &Template, variables: &Variables, path: &Path) -> Result<(), KitError> {
+    let (width, height) = (template.size.width, template.size.height);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n",
+        width, height,
+    );
+
+    let background = template.background_rgba()?;
+    if background[3] > 0 {
+        svg.push_str(&format!(
+            "  <rect width=\"{}\" height=\"{}\" {}/>\n",
+            width, height, color_attributes("fill", background),
+        ));
+    }
+
+    for (index, placed) in template.compose(variables)?.iter().enumerate() {
+        match &placed.layer {
+            Layer::Text(text) => write_text(&mut svg, text, placed, index, width, height, &template.fonts),
+            Layer::Image(image) => write_image(&mut svg, image, placed),
+        }
+        .map_err(|e| e.within(&placed.at))?;
+    }
+
+    svg.push_str("</svg>\n");
+    std::fs::write(path, svg).map_err(|source| KitError::Io { path: path.to_path_buf(), source })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes a template with the given layers and returns the SVG
+    fn render(name: &str, layers: serde_json::Value) -> String {
+        let dir = std::env::temp_dir().join(format!("kit-svg-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let template_path = dir.join("template.json");
+        std::fs::write(&template_path, serde_json::json!({
+            "size": { "width": 400, "height": 200 },
+            "background": "#FFFFFF",
+            "font_dirs": [std::env::current_dir().unwrap().join("fonts")],
+            "system_fonts": false,
+            "groups": [{
+                "name": "main",
+                "layout": { "type": "vertical", "position": { "x": 10, "y": 10 } },
+                "layers": layers
+            }]
+        }).to_string()).unwrap();
+        let svg_path = dir.join("out.svg");
+        let written = Template::from_path(&template_path).and_then(|template| template.write_svg(&Variables::new(), &svg_path));
+        let svg = written.map(|_| std::fs::read_to_string(&svg_path).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+        svg.unwrap()
+    }
+
+    fn text(name: &str, text: &str, extra: serde_json::Value) -> serde_json::Value {
+        let mut layer = serde_json::json!({
+            "type": "text",
+            "name": name,
+            "text": text,
+            "font": { "family": "Arial", "size": 20, "color": "#000000" },
+            "alignment": "left"
+        });
+        for (key, value) in extra.as_object().unwrap() {
+            match (layer.get_mut(key), value) {
+                (Some(serde_json::Value::Object(existing)), serde_json::Value::Object(more)) => existing.extend(more.clone()),
+                _ => { layer[key] = value.clone(); }
+            }
+        }
+        layer
+    }
+
+    #[test]
+    fn escapes_layer_names_and_text() {
+        let svg = render("escape", serde_json::json!([text(r#"a<b & "c""#, r#"1<2 & "x">0"#, serde_json::json!({}))]));
+        assert!(svg.contains(r#"<text id="a&lt;b &amp; &quot;c&quot;" "#));
+        assert!(svg.contains(">1&lt;2</tspan>"));
+        assert!(svg.contains(">&amp;</tspan>"));
+        assert!(svg.contains(">&quot;x&quot;&gt;0</tspan>"));
+        assert!(!svg.contains("<b") && !svg.contains("<2"));
+    }
+
+    #[test]
+    fn gradients_are_defined_before_their_text() {
+        let stops = serde_json::json!([{ "color": "#FF0000" }, { "color": "#0000FF80" }]);
+        let svg = render("gradients", serde_json::json!([
+            text("linear", "One", serde_json::json!({ "font": { "fill": { "type": "linear_gradient", "angle": 90, "stops": stops } } })),
+            text("radial", "Two", serde_json::json!({ "font": { "fill": { "type": "radial_gradient", "stops": stops } } })),
+        ]));
+        let linear = svg.find(r#"<linearGradient id="fill-1" gradientUnits="userSpaceOnUse""#).unwrap();
+        let radial = svg.find(r#"<radialGradient id="fill-2" gradientUnits="userSpaceOnUse""#).unwrap();
+        assert!(linear < svg.find(r#"<text id="linear""#).unwrap());
+        assert!(radial < svg.find(r#"<text id="radial""#).unwrap());
+        assert!(svg.contains(r#"fill="url(#fill-1)""#) && svg.contains(r#"fill="url(#fill-2)""#));
+        assert!(svg.contains(r##"<stop offset="0.000" stop-color="#FF0000" stop-opacity="1.000"/><stop offset="1.000" stop-color="#0000FF" stop-opacity="0.502"/>"##));
+    }
+
+    #[test]
+    fn each_shadow_gets_its_own_filter() {
+        let shadow = serde_json::json!({ "shadow": { "color": "#000000", "offset": { "x": 2, "y": 3 }, "blur": 6 } });
+        let svg = render("shadows", serde_json::json!([
+            text("first", "One", shadow.clone()),
+            text("plain", "Two", serde_json::json!({})),
+            text("third", "Three", shadow),
+        ]));
+        assert!(svg.contains(r#"<filter id="shadow-1" "#) && svg.contains(r#"<filter id="shadow-3" "#));
+        assert!(!svg.contains("shadow-2"));
+        assert!(svg.contains(r##"<feDropShadow dx="2.00" dy="3.00" stdDeviation="2.00" flood-color="#000000""##));
+        let filters: Vec<&str> = svg.lines()
+            .filter(|line| line.contains("<text "))
+            .map(|line| line.split("filter=\"").nth(1).map_or("", |rest| &rest[..rest.find('"').unwrap()]))
+            .collect();
+        assert_eq!(filters, ["url(#shadow-1)", "", "url(#shadow-3)"]);
+    }
+
+    #[test]
+    fn warped_text_falls_back_to_an_image() {
+        let svg = render("warp", serde_json::json!([text("bent", "Bent", serde_json::json!({ "warp": { "style": "arch" } }))]));
+        assert!(!svg.contains("<text"));
+        let image = svg.lines().find(|line| line.contains(r#"<image id="bent""#)).unwrap();
+        assert!(image.contains(r#"href="data:image/png;base64,"#));
+    }
+}
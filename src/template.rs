@@ -0,0 +1,494 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+use crate::error::{KitError, Location, Warning};
+use crate::fonts::FontRegistry;
+use crate::layer::Layer;
+use crate::layout::{measure_layers, Group, LayerDimensions, Position};
+use crate::report::{GroupReport, LayerContent, LayerReport, Report, SourceLayerReport};
+use crate::source::SourceData;
+use crate::variables::{self, Variables};
+use crate::{parse_rgba, pdf_output, svg_output};
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct Size {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+/// A print template: a canvas size and background plus groups of text and
+/// image layers, laid out per group.
+#[derive(Deserialize, Clone)]
+pub struct Template {
+    pub(crate) size: Size,
+    // Resolution the pixel size is meant for; sets the page size of vector output
+    #[serde(default = "default_dpi")]
+    pub(crate) dpi: f32,
+    pub(crate) background: String,
+    pub(crate) source: Option<String>,
+    // Directories of font files to use before, or instead of, system fonts
+    #[serde(default)]
+    pub(crate) font_dirs: Vec<PathBuf>,
+    // Whether fonts missing from `font_dirs` may come from the system; by
+    // default only when there are no font directories
+    #[serde(default)]
+    pub(crate) system_fonts: Option<bool>,
+    pub(crate) groups: Vec<Group>,
+    #[serde(skip)]
+    pub(crate) fonts: Arc<FontRegistry>,
+    // Directory of the template file, which the paths in it are relative to
+    #[serde(skip)]
+    pub(crate) base_dir: PathBuf,
+    // The source file, read once with the template and shared by its clones
+    #[serde(skip)]
+    pub(crate) source_data: Option<Arc<SourceData>>,
+}
+
+fn default_dpi() -> f32 {
+    300.0
+}
+
+/// Takes a path written in a template from the template's directory rather
+/// than from wherever the program runs. Absolute paths are kept, and so are
+/// empty ones, which leave the setting out.
+pub(crate) fn resolve_path(base_dir: &Path, path: &Path) -> PathBuf {
+    if path.as_os_str().is_empty() {
+        return PathBuf::new();
+    }
+    base_dir.join(path)
+}
+
+// A resolved layer with its final size and canvas position
+pub(crate) struct PlacedLayer {
+    pub(crate) layer: Layer,
+    // Where the layer came from, for errors raised while drawing it
+    pub(crate) at: Location,
+    pub(crate) dimensions: LayerDimensions,
+    pub(crate) position: Position,
+}
+
+impl Template {
+    pub(crate) fn background_rgba(&self) -> Result<Rgba<u8>, KitError> {
+        parse_rgba(&self.background, "background")
+    }
+
+    /// Loads a template from a JSON file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, KitError> {
+        let path = path.as_ref();
+        let io_error = |source| KitError::Io { path: path.to_path_buf(), source };
+        let mut template_file = File::open(path).map_err(io_error)?;
+        let mut template_contents = String::new();
+        template_file.read_to_string(&mut template_contents).map_err(io_error)?;
+        let mut template: Template = serde_json::from_str(&template_contents)
+            .map_err(|source| KitError::Parse { path: path.to_path_buf(), source })?;
+        template.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        for font_dir in &mut template.font_dirs {
+            *font_dir = resolve_path(&template.base_dir, font_dir);
+        }
+        if let Some(source) = &mut template.source {
+            *source = resolve_path(&template.base_dir, Path::new(source)).to_string_lossy().into_owned();
+        }
+        template.index_fonts()?;
+        template.source_data = template.source.as_deref().map(|source| SourceData::load(source, template.dpi)).transpose()?.map(Arc::new);
+        Ok(template)
+    }
+
+    /// Adds font directories to the template's own. `system_fonts` also lets
+    /// fonts missing from every directory come from the system.
+    pub fn add_font_dirs(&mut self, directories: &[PathBuf], system_fonts: bool) -> Result<(), KitError> {
+        if directories.is_empty() && !system_fonts {
+            return Ok(());
+        }
+        self.font_dirs.extend_from_slice(directories);
+        if system_fonts {
+            self.system_fonts = Some(true);
+        }
+        self.index_fonts()
+    }
+
+    fn index_fonts(&mut self) -> Result<(), KitError> {
+        let system_fonts = self.system_fonts.unwrap_or(self.font_dirs.is_empty());
+        self.fonts = Arc::new(FontRegistry::new(&self.font_dirs, system_fonts)?);
+        Ok(())
+    }
+
+    pub fn has_text_layer(&self, name: &str) -> bool {
+        self.groups.iter()
+            .flat_map(|group| &group.layers)
+            .any(|layer| matches!(layer, Layer::Text(text) if text.info.name == name))
+    }
+
+    /// Replaces the text of each named text layer.
+    pub fn apply_overrides(&mut self, overrides: &[(String, String)]) -> Result<(), KitError> {
+        for (name, text) in overrides {
+            let layer = self.groups.iter_mut()
+                .flat_map(|group| group.layers.iter_mut())
+                .find_map(|layer| match layer {
+                    Layer::Text(text_layer) if text_layer.info.name == *name => Some(text_layer),
+                    _ => None,
+                })
+                .ok_or_else(|| KitError::UnknownOverride(name.clone()))?;
+            layer.text = text.clone();
+        }
+        Ok(())
+    }
+
+    fn source_data(&self) -> Option<&SourceData> {
+        self.source_data.as_deref()
+    }
+
+    fn check_source_layers(&self, source: &SourceData) -> Result<(), KitError> {
+        // Check each text layer can be found in the source
+        for (group_index, group) in self.groups.iter().enumerate() {
+            for (layer_index, layer) in group.layers.iter().enumerate() {
+                if let Layer::Text(text) = layer
+                    && source.get_layer_by_name(&text.info.name).is_none()
+                {
+                    return Err(KitError::SourceLayerNotFound {
+                        at: Location::layer(group_index, &group.name, layer_index, &text.info.name),
+                        name: text.info.name.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists every distinct `{{variable}}` referenced by the template's layers,
+    /// including text the layers take from the source file.
+    pub fn placeholders(&self) -> Result<Vec<variables::Placeholder>, KitError> {
+        self.placeholders_with(self.source_data())
+    }
+
+    fn placeholders_with(&self, source_data: Option<&SourceData>) -> Result<Vec<variables::Placeholder>, KitError> {
+        let mut placeholders: Vec<variables::Placeholder> = Vec::new();
+        for (group_index, group) in self.groups.iter().enumerate() {
+            for (layer, at) in group.layers.iter().zip(group.layer_locations(group_index)) {
+                let mut layer = layer.clone();
+                if let Some(source) = source_data {
+                    layer.apply_source(source);
+                }
+                for (path, value) in layer.strings_mut() {
+                    let found = variables::placeholders(value)
+                        .map_err(|source| KitError::Variable { at: Location::field(&path), source }.within(&at))?;
+                    for placeholder in found {
+                        if !placeholders.iter().any(|p| p.name == placeholder.name) {
+                            placeholders.push(placeholder);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(placeholders)
+    }
+
+    /// Checks colors, fonts, images and the source file without rendering.
+    /// Unbound variables and characters no font can draw are returned as
+    /// warnings rather than failing the check.
+    pub fn validate(&self, variables: &Variables) -> Result<Vec<Warning>, KitError> {
+        self.background_rgba()?;
+
+        // Unbound variables are only a warning here; stand in their names so the
+        // rest of each layer can still be checked
+        let source_data = self.source_data();
+        let mut warnings = Vec::new();
+        let mut variables = variables.clone();
+        for placeholder in self.placeholders_with(source_data)? {
+            if placeholder.default.is_none() && !variables.contains_key(&placeholder.name) {
+                warnings.push(Warning::UnboundVariable { name: placeholder.name.clone() });
+                variables.insert(placeholder.name.clone(), placeholder.name);
+            }
+        }
+
+        if let Some(source) = source_data {
+            self.check_source_layers(source)?;
+        }
+
+        for (group_index, group) in self.groups.iter().enumerate() {
+            let locations = group.layer_locations(group_index);
+            for (layer, at) in group.resolve_layers(&locations, &variables, source_data, &self.fonts, &self.base_dir)?.iter().zip(&locations) {
+                layer.validate(&self.fonts).map_err(|e| e.within(at))?;
+
+                // Missing glyphs draw as nothing, so warn rather than fail
+                warnings.extend(self.uncovered_chars(layer, at)?);
+            }
+        }
+
+        if let Some(source) = source_data {
+            warnings.extend_from_slice(source.warnings());
+        }
+        Ok(warnings)
+    }
+
+    /// Lists the characters of each text layer that no font in its chain can
+    /// draw, with `variables` bound. Rendering goes ahead without them, so
+    /// callers rendering untrusted text can check this first.
+    pub fn missing_glyphs(&self, variables: &Variables) -> Result<Vec<Warning>, KitError> {
+        let source_data = self.source_data();
+        let mut warnings = Vec::new();
+        for (group_index, group) in self.groups.iter().enumerate() {
+            let locations = group.layer_locations(group_index);
+            for (layer, at) in group.resolve_layers(&locations, variables, source_data, &self.fonts, &self.base_dir)?.iter().zip(&locations) {
+                warnings.extend(self.uncovered_chars(layer, at)?);
+            }
+        }
+        Ok(warnings)
+    }
+
+    fn uncovered_chars(&self, layer: &Layer, at: &Location) -> Result<Option<Warning>, KitError> {
+        let Layer::Text(text) = layer else {
+            return Ok(None);
+        };
+        let chars = text.uncovered_chars(&self.fonts).map_err(|e| e.within(at))?;
+        Ok((!chars.is_empty()).then(|| Warning::UncoveredChars { at: at.clone(), chars }))
+    }
+
+    /// Renders the template to an image with `variables` bound to its placeholders.
+    pub fn render(&self, variables: &Variables) -> Result<RgbaImage, KitError> {
+        // Create a new image with the specified size and background color
+        let mut canvas = RgbaImage::new(self.size.width, self.size.height);
+        let bg_rgba = self.background_rgba()?;
+
+        // Fill background
+        for pixel in canvas.pixels_mut() {
+            *pixel = bg_rgba;
+        }
+
+        // Draw each layer
+        for placed in self.compose(variables)? {
+            match &placed.layer {
+                Layer::Text(text) => text.draw(&mut canvas, &placed.position, &self.fonts),
+                Layer::Image(image) => image.draw(&mut canvas, &placed.position),
+            }
+            .map_err(|e| e.within(&placed.at))?;
+        }
+
+        Ok(canvas)
+    }
+
+    /// Resolves and lays out every layer, in drawing order. This is the part of
+    /// rendering shared by the raster and vector backends.
+    pub(crate) fn compose(&self, variables: &Variables) -> Result<Vec<PlacedLayer>, KitError> {
+        let source_data = self.source_data();
+
+        // If we have a source file, validate that all required layers exist
+        if let Some(source) = source_data {
+            self.check_source_layers(source)?;
+        }
+
+        let mut placed = Vec::new();
+
+        // Process each group
+        for (group_index, group) in self.groups.iter().enumerate() {
+            // Fill in source defaults and variables before measuring so layout
+            // sees the final text
+            let locations = group.layer_locations(group_index);
+            let layers = group.resolve_layers(&locations, variables, source_data, &self.fonts, &self.base_dir)?;
+
+            // Calculate dimensions and positions for all layers in the group
+            let (dimensions, positions) = {
+                let layer_dimensions = measure_layers(&layers, &locations, &self.fonts)?;
+                let positions = group.calculate_positions(&layer_dimensions);
+                let dimensions: Vec<LayerDimensions> = layer_dimensions.into_iter().map(|(dims, _)| dims).collect();
+                (dimensions, positions)
+            };
+
+            for (((layer, at), dimensions), position) in layers.into_iter().zip(locations).zip(dimensions).zip(positions) {
+                placed.push(PlacedLayer { layer, at, dimensions, position });
+            }
+        }
+
+        Ok(placed)
+    }
+
+    /// Writes the template as a single-page PDF with embedded fonts.
+    pub fn write_pdf<P: AsRef<Path>>(&self, variables: &Variables, path: P) -> Result<(), KitError> {
+        pdf_output::write_pdf(self, variables, path.as_ref())
+    }
+
+    /// Writes the template as an SVG document.
+    pub fn write_svg<P: AsRef<Path>>(&self, variables: &Variables, path: P) -> Result<(), KitError> {
+        svg_output::write_svg(self, variables, path.as_ref())
+    }
+
+    /// Reports the template's variables, source layers and computed layout.
+    pub fn inspect(&self, variables: &Variables) -> Result<Report, KitError> {
+        let source_data = self.source_data();
+        let placeholders = self.placeholders_with(source_data)?;
+        let mut groups = Vec::new();
+        for (group_index, group) in self.groups.iter().enumerate() {
+            let locations = group.layer_locations(group_index);
+            let layers = group.resolve_layers(&locations, variables, source_data, &self.fonts, &self.base_dir)?;
+            let layer_dimensions = measure_layers(&layers, &locations, &self.fonts)?;
+            let positions = group.calculate_positions(&layer_dimensions);
+            let layers = layers.iter().zip(&layer_dimensions).zip(&positions)
+                .map(|((layer, (dims, _)), position)| LayerReport {
+                    name: layer.info().name.clone(),
+                    content: match layer {
+                        Layer::Text(text) => LayerContent::Text(text.text.clone()),
+                        Layer::Image(image) if image.source.is_empty() => LayerContent::SourceImage,
+                        Layer::Image(image) => LayerContent::Image(image.source.clone()),
+                    },
+                    width: dims.width,
+                    height: dims.height,
+                    x: position.x,
+                    y: position.y,
+                })
+                .collect();
+            groups.push(GroupReport { name: group.name.clone(), layers });
+        }
+
+        let (source_layers, warnings) = match source_data {
+            Some(source) => (
+                Some(source.layers().into_iter().map(SourceLayerReport::new).collect()),
+                source.warnings().to_vec(),
+            ),
+            None => (None, Vec::new()),
+        };
+        Ok(Report {
+            width: self.size.width,
+            height: self.size.height,
+            background: self.background.clone(),
+            variables: placeholders,
+            source_layers,
+            groups,
+            warnings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_relative_to_the_template() {
+        let template_dir = std::env::temp_dir().join(format!("kit-template-paths-{}", std::process::id()));
+        std::fs::create_dir_all(template_dir.join("type")).unwrap();
+        std::fs::create_dir_all(template_dir.join("art")).unwrap();
+        std::fs::copy("fonts/arial.ttf", template_dir.join("type/arial.ttf")).unwrap();
+        RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255])).save(template_dir.join("art/dot.png")).unwrap();
+        std::fs::write(template_dir.join("source.ai"), r#"{
+            "design_metafields": [{ "namespace": "layer", "value": "GREETING" }],
+            "layers": [{ "name": "GREETING", "content": "Hello" }]
+        }"#).unwrap();
+        let template_path = template_dir.join("template.json");
+        std::fs::write(&template_path, r##"{
+            "size": { "width": 400, "height": 100 },
+            "background": "#FFFFFF",
+            "source": "source.ai",
+            "font_dirs": ["type"],
+            "system_fonts": false,
+            "groups": [{
+                "name": "main",
+                "layout": { "type": "vertical", "position": { "x": 0, "y": 0 } },
+                "layers": [
+                    {
+                        "type": "text",
+                        "name": "GREETING",
+                        "font": {
+                            "family": "Arial", "size": 20, "color": "#000000",
+                            "fill": { "type": "pattern", "source": "art/dot.png" }
+                        },
+                        "alignment": "left"
+                    },
+                    { "type": "image", "name": "dot", "source": "art/{{image}}", "scale": 1 }
+                ]
+            }]
+        }"##).unwrap();
+
+        let template = Template::from_path(&template_path);
+        let variables: Variables = [("image".to_string(), "dot.png".to_string())].into_iter().collect();
+        let checked = template.as_ref().ok().map(|template| (template.validate(&variables).is_ok(), template.inspect(&variables)));
+        std::fs::remove_dir_all(&template_dir).unwrap();
+
+        let template = template.unwrap();
+        assert_eq!(template.font_dirs, vec![template_dir.join("type")]);
+        assert_eq!(template.source, Some(template_dir.join("source.ai").to_string_lossy().into_owned()));
+        let (valid, report) = checked.unwrap();
+        assert!(valid);
+        let image = report.unwrap().groups.remove(0).layers.remove(1).content;
+        assert!(matches!(image, LayerContent::Image(file) if Path::new(&file) == template_dir.join("art/dot.png")));
+    }
+
+    #[test]
+    fn placeholders_include_source_layer_text() {
+        let template_dir = std::env::temp_dir().join(format!("kit-source-placeholders-{}", std::process::id()));
+        std::fs::create_dir_all(&template_dir).unwrap();
+        std::fs::write(template_dir.join("source.ai"), r#"{
+            "design_metafields": [{ "namespace": "layer", "value": "GREETING" }],
+            "layers": [{ "name": "GREETING", "content": "Hello {{name}}" }]
+        }"#).unwrap();
+        let template_path = template_dir.join("template.json");
+        std::fs::write(&template_path, serde_json::json!({
+            "size": { "width": 400, "height": 100 },
+            "background": "#FFFFFF",
+            "source": "source.ai",
+            "font_dirs": [std::env::current_dir().unwrap().join("fonts")],
+            "groups": [{
+                "name": "main",
+                "layout": { "type": "vertical", "position": { "x": 0, "y": 0 } },
+                "layers": [{
+                    "type": "text",
+                    "name": "GREETING",
+                    "font": { "family": "Arial", "size": 20, "color": "#000000" },
+                    "alignment": "left"
+                }]
+            }]
+        }).to_string()).unwrap();
+
+        let template = Template::from_path(&template_path).unwrap();
+        let placeholders = template.placeholders();
+        let warnings = template.validate(&Variables::new());
+        std::fs::remove_dir_all(&template_dir).unwrap();
+
+        let names: Vec<String> = placeholders.unwrap().into_iter().map(|placeholder| placeholder.name).collect();
+        assert_eq!(names, vec!["name".to_string()]);
+        assert!(warnings.unwrap().iter().any(|warning| matches!(warning, Warning::UnboundVariable { name } if name == "name")));
+    }
+
+    #[test]
+    fn source_sizes_are_converted_to_pixels() {
+        let template_dir = std::env::temp_dir().join(format!("kit-source-pixels-{}", std::process::id()));
+        std::fs::create_dir_all(&template_dir).unwrap();
+        std::fs::write(template_dir.join("source.ai"), r#"{
+            "design_metafields": [{ "namespace": "layer", "value": "GREETING" }],
+            "layers": [{ "name": "GREETING", "content": "Hello", "bounds": [36, 36, 180, 72], "font_size": 12 }]
+        }"#).unwrap();
+        let template_path = template_dir.join("template.json");
+        std::fs::write(&template_path, serde_json::json!({
+            "size": { "width": 400, "height": 100 },
+            "dpi": 144,
+            "background": "#FFFFFF",
+            "source": "source.ai",
+            "font_dirs": [std::env::current_dir().unwrap().join("fonts")],
+            "groups": [{
+                "name": "main",
+                "layout": { "type": "vertical", "position": { "x": 0, "y": 0 } },
+                "layers": [{
+                    "type": "text",
+                    "name": "GREETING",
+                    "font": { "family": "Arial", "size": 0, "color": "#000000" },
+                    "alignment": "left",
+                    "source_box": true
+                }]
+            }]
+        }).to_string()).unwrap();
+
+        let template = Template::from_path(&template_path);
+        std::fs::remove_dir_all(&template_dir).unwrap();
+
+        let template = template.unwrap();
+        let layer = template.groups[0].layers[0]
+            .resolve(&Variables::new(), template.source_data(), &template.fonts, &template.base_dir)
+            .unwrap();
+        let Layer::Text(text) = layer else { panic!("expected a text layer") };
+        // 12pt and a 2in by 0.5in frame at 144 dpi
+        assert_eq!(text.font.size, 24.0);
+        assert_eq!((text.max_width, text.max_height), (Some(288), Some(72)));
+    }
+}
@@ -0,0 +1,1129 @@
+use image::RgbaImage;
+use rusttype::Scale;
+use serde::Deserialize;
+use crate::compositing::{self, BlendMode};
+use crate::effects::{self, EffectPadding, Mask, Outline, Stroker, TextShadow, TextStroke};
+use crate::error::{KitError, Location};
+use crate::font_spec::{FontSpec, FontVariant};
+use crate::fonts::FontRegistry;
+use crate::layer::LayerInfo;
+use crate::layer_trait::SourceLayer;
+use crate::layout::{LayerDimensions, Position, RelativeTo};
+use crate::shaping::{self, LoadedFont, ShapeStyle, ShapedText, TextDirection};
+use crate::text_path::TextPath;
+use crate::warp::TextWarp;
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TextTransform {
+    None,
+    Uppercase,
+    Lowercase,
+    // Uppercases the first letter of each word
+    Capitalize,
+}
+
+impl TextTransform {
+    fn apply(self, text: &str) -> String {
+        match self {
+            TextTransform::None => text.to_string(),
+            TextTransform::Uppercase => text.to_uppercase(),
+            TextTransform::Lowercase => text.to_lowercase(),
+            TextTransform::Capitalize => {
+                let mut capitalized = String::with_capacity(text.len());
+                let mut word_start = true;
+                for character in text.chars() {
+                    if word_start && character.is_alphabetic() {
+                        capitalized.extend(character.to_uppercase());
+                        word_start = false;
+                    } else {
+                        capitalized.push(character);
+                    }
+                    if character.is_whitespace() {
+                        word_start = true;
+                    }
+                }
+                capitalized
+            }
+        }
+    }
+}
+
+/// Extra space in pixels, written as a number or as `"2px"`, or relative to
+/// the font size, written as `"0.1em"`.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(try_from = "SpacingValue")]
+pub(crate) struct Spacing {
+    pub(crate) value: f32,
+    pub(crate) em: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum SpacingValue {
+    Pixels(f32),
+    Text(String),
+}
+
+impl TryFrom<SpacingValue> for Spacing {
+    type Error = String;
+
+    fn try_from(value: SpacingValue) -> Result<Self, String> {
+        let text = match value {
+            SpacingValue::Pixels(value) => return Ok(Spacing { value, em: false }),
+            SpacingValue::Text(text) => text,
+        };
+        let (number, em) = match (text.strip_suffix("em"), text.strip_suffix("px")) {
+            (Some(number), _) => (number, true),
+            (None, Some(number)) => (number, false),
+            (None, None) => (text.as_str(), false),
+        };
+        match number.trim().parse() {
+            Ok(value) => Ok(Spacing { value, em }),
+            Err(_) => Err(format!("invalid spacing '{}', expected pixels or a length like '0.1em'", text)),
+        }
+    }
+}
+
+impl Spacing {
+    pub(crate) fn pixels(self, font_size: f32) -> f32 {
+        if self.em { self.value * font_size } else { self.value }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TextAlignment {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TextFit {
+    None,
+    // Reduce the font size until the text fits its box
+    Shrink,
+    // Increase the font size as far as the box allows
+    Grow,
+    // Shrink down to `min_size`, then wrap at `max_width`
+    ShrinkThenWrap,
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TextJustification {
+    Left,
+    Center,
+    Right,
+    Justify,
+}
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct TextLayer {
+    #[serde(rename = "type")]
+    pub(crate) layer_type: String,
+    #[serde(flatten)]
+    pub(crate) info: LayerInfo,
+    // Falls back to the source layer's content when left out
+    #[serde(default)]
+    pub(crate) text: String,
+    pub(crate) font: FontSpec,
+    pub(crate) alignment: TextAlignment,
+    #[serde(default = "default_text_justification")]
+    pub(crate) justification: TextJustification,
+    // Justifies the last line of each paragraph too, including a paragraph's
+    // only line, instead of aligning it
+    #[serde(default)]
+    pub(crate) justify_last_line: bool,
+    // Words past this width wrap onto the next line
+    #[serde(default)]
+    pub(crate) max_width: Option<u32>,
+    // Takes `max_width` and `max_height` from the source layer's bounds, for
+    // text set in an area text frame
+    #[serde(default)]
+    pub(crate) source_box: bool,
+    // Distance between baselines as a multiple of the font size
+    #[serde(default = "default_line_height")]
+    pub(crate) line_height: f32,
+    // Fits the font size to the box given by `max_width` and `max_height`
+    #[serde(default = "default_text_fit")]
+    pub(crate) fit: TextFit,
+    #[serde(default)]
+    pub(crate) max_height: Option<u32>,
+    #[serde(default)]
+    pub(crate) min_size: Option<f32>,
+    #[serde(default)]
+    pub(crate) max_size: Option<f32>,
+    // Base direction for bidi reordering
+    #[serde(default = "default_text_direction")]
+    pub(crate) direction: TextDirection,
+    // BCP 47 tag passed to the shaper for language-specific forms
+    #[serde(default)]
+    pub(crate) language: Option<String>,
+    #[serde(default)]
+    pub(crate) stroke: Option<TextStroke>,
+    #[serde(default)]
+    pub(crate) shadow: Option<TextShadow>,
+    // Bends the baseline along an arc, circle, wave or SVG path
+    #[serde(default)]
+    pub(crate) path: Option<TextPath>,
+    // Bends the finished text through an envelope such as an arch or a flag
+    #[serde(default)]
+    pub(crate) warp: Option<TextWarp>,
+    // Added between letters, and on top of the space between words
+    #[serde(default)]
+    pub(crate) letter_spacing: Spacing,
+    #[serde(default)]
+    pub(crate) word_spacing: Spacing,
+    #[serde(default = "default_text_transform")]
+    pub(crate) text_transform: TextTransform,
+    // Width of the group's distribution bounds, which justified text fills
+    // when it has no `max_width` of its own
+    #[serde(skip)]
+    pub(crate) group_width: Option<u32>,
+}
+
+fn default_text_justification() -> TextJustification {
+    TextJustification::Left
+}
+
+fn default_line_height() -> f32 {
+    1.2
+}
+
+fn default_text_fit() -> TextFit {
+    TextFit::None
+}
+
+fn default_text_direction() -> TextDirection {
+    TextDirection::Auto
+}
+
+fn default_text_transform() -> TextTransform {
+    TextTransform::None
+}
+
+// Smallest size the fit modes shrink to when no `min_size` is given
+const DEFAULT_MIN_FIT_SIZE: f32 = 1.0;
+
+// Largest size `grow` tries when no `max_size` is given
+const DEFAULT_MAX_FIT_SIZE: f32 = 10000.0;
+
+/// Finds the largest size in `min..=max` for which `fits` holds, assuming larger
+/// sizes never fit better. Returns `None` when even `min` does not fit.
+fn largest_fitting_size(min: f32, max: f32, fits: impl Fn(f32) -> bool) -> Option<f32> {
+    if !fits(min) {
+        return None;
+    }
+    if fits(max) {
+        return Some(max);
+    }
+
+    let (mut low, mut high) = (min, max);
+    // A tenth of a pixel is finer than any visible difference
+    while high - low > 0.1 {
+        let middle = (low + high) / 2.0;
+        if fits(middle) {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    Some(low)
+}
+
+impl TextLayer {
+    /// Fills in anything the template left unset from the matching source layer.
+    pub(crate) fn apply_source(&mut self, source_layer: &dyn SourceLayer) {
+        if self.text.is_empty() {
+            self.text = source_layer.content().to_string();
+        }
+        if self.font.family.is_empty()
+            && let Some(font_name) = source_layer.font_name()
+        {
+            self.font.family = vec![font_name.to_string()];
+        }
+        if self.font.size <= 0.0
+            && let Some(font_size) = source_layer.font_size()
+        {
+            self.font.size = font_size;
+        }
+        if self.font.color.is_empty()
+            && let Some((r, g, b)) = source_layer.color()
+        {
+            self.font.color = format!("#{:02X}{:02X}{:02X}", r, g, b);
+        }
+        if self.source_box
+            && let Some((x1, y1, x2, y2)) = source_layer.bounds()
+        {
+            let (width, height) = ((x2 - x1).abs().round() as u32, (y2 - y1).abs().round() as u32);
+            if self.max_width.is_none() && width > 0 {
+                self.max_width = Some(width);
+            }
+            if self.max_height.is_none() && height > 0 {
+                self.max_height = Some(height);
+            }
+        }
+    }
+
+    /// Every string setting of the layer with its path, for variable
+    /// substitution. The layer's name and type are identifiers rather than
+    /// settings, so they're left out.
+    pub(crate) fn strings_mut(&mut self) -> Vec<(String, &mut String)> {
+        let mut strings = vec![("text".to_string(), &mut self.text)];
+        strings.extend(self.font.strings_mut().into_iter().map(|(path, value)| (format!("font.{}", path), value)));
+        if let Some(language) = &mut self.language {
+            strings.push(("language".to_string(), language));
+        }
+        if let Some(stroke) = &mut self.stroke {
+            strings.push(("stroke.color".to_string(), &mut stroke.color));
+        }
+        if let Some(shadow) = &mut self.shadow {
+            strings.push(("shadow.color".to_string(), &mut shadow.color));
+        }
+        if let Some(path) = &mut self.path {
+            strings.extend(path.strings_mut().into_iter().map(|(field, value)| (format!("path.{}", field), value)));
+        }
+        strings
+    }
+
+    pub(crate) fn validate(&self, fonts: &FontRegistry) -> Result<(), KitError> {
+        if self.layer_type != "text" {
+            return Err(KitError::Invalid {
+                at: Location::field("type"),
+                message: format!("expected layer type 'text', got '{}'", self.layer_type),
+            });
+        }
+        
+        if self.text.is_empty() {
+            return Err(KitError::Missing { at: Location::field("text") });
+        }
+        
+        self.font.validate(fonts)?;
+        if let Some(stroke) = &self.stroke {
+            stroke.validate()?;
+        }
+        if let Some(shadow) = &self.shadow {
+            shadow.validate()?;
+        }
+        if let Some(path) = &self.path {
+            path.validate()?;
+        }
+        if let Some(warp) = &self.warp {
+            warp.validate()?;
+        }
+        
+        Ok(())
+    }
+
+    /// Breaks the text into lines at explicit `\n`s and, when `max_width` is
+    /// set, wherever the next word would run past it. Words stay in logical
+    /// order, each split into runs shaped in the direction bidi resolves for
+    /// them.
+    fn wrap_lines(&self, fonts: &[LoadedFont], scale: Scale) -> Vec<TextLine> {
+        let space_width = self.word_gap(fonts, scale);
+        let mut lines = Vec::new();
+        let style = ShapeStyle {
+            rtl: false,
+            language: self.language.as_deref(),
+            small_caps: self.font.variant == FontVariant::SmallCaps,
+            letter_spacing: self.letter_spacing.pixels(self.font.size),
+        };
+
+        for paragraph in self.text_transform.apply(&self.text).split('\n') {
+            let words: Vec<&str> = paragraph.split_whitespace().collect();
+            let (word_runs, space_levels) = shaping::word_runs(&words, self.direction);
+
+            let mut line = TextLine::default();
+            for (index, runs) in word_runs.into_iter().enumerate() {
+                let runs: Vec<TextRun> = runs.into_iter()
+                    .map(|(text, level)| TextRun {
+                        text: text.to_string(),
+                        shaped: shaping::shape(fonts, scale, text, ShapeStyle { rtl: level.is_rtl(), ..style }),
+                        level,
+                    })
+                    .collect();
+                // Runs of a word are letter spaced where they meet
+                let width = runs.iter().map(|run| run.shaped.width).sum::<f32>()
+                    + runs.len().saturating_sub(1) as f32 * style.letter_spacing;
+
+                let gap = if line.words.is_empty() { 0.0 } else { space_width };
+                if let Some(max_width) = self.max_width
+                    && !line.words.is_empty()
+                    && line.width + gap + width > max_width as f32
+                {
+                    lines.push(std::mem::take(&mut line));
+                }
+
+                if !line.words.is_empty() {
+                    line.width += space_width;
+                    line.spaces.push(space_levels[index - 1]);
+                }
+                line.width += width;
+                line.height = runs.iter().map(|run| run.shaped.height).fold(line.height, u32::max);
+                line.words.push(runs);
+            }
+            line.ends_paragraph = true;
+            lines.push(line);
+        }
+
+        if self.justification == TextJustification::Justify {
+            self.justify(&mut lines);
+        }
+        lines
+    }
+
+    /// Spreads lines to the width of the text box: `max_width`, else the
+    /// group's distribution bounds, else the widest line. The last line of
+    /// each paragraph, which is the only line of a short one, keeps its
+    /// alignment unless `justify_last_line` is set.
+    /// The space goes between the words, or between the letters of a line
+    /// with a single word.
+    fn justify(&self, lines: &mut [TextLine]) {
+        let widest = lines.iter().map(|line| line.width).fold(0.0, f32::max);
+        let box_width = self.max_width.or(self.group_width).map_or(widest, |width| width as f32);
+
+        for line in lines.iter_mut().filter(|line| self.justify_last_line || !line.ends_paragraph) {
+            let extra = box_width - line.width;
+            if extra <= 0.0 {
+                continue;
+            }
+            match line.words.as_mut_slice() {
+                [] => continue,
+                [runs] => {
+                    let clusters: usize = runs.iter()
+                        .map(|run| run.shaped.glyphs.iter().filter(|glyph| !glyph.text.is_empty()).count())
+                        .sum();
+                    if clusters < 2 {
+                        continue;
+                    }
+                    // Placement spreads the runs apart by the same amount
+                    line.letter_spread = extra / (clusters - 1) as f32;
+                    for run in runs {
+                        shaping::space_letters(&mut run.shaped, line.letter_spread);
+                    }
+                }
+                words => line.extra_gap = extra / (words.len() - 1) as f32,
+            }
+            line.width = box_width;
+        }
+    }
+
+    /// Characters of the text that no font in the chain can draw.
+    pub(crate) fn uncovered_chars(&self, fonts: &FontRegistry) -> Result<Vec<char>, KitError> {
+        Ok(shaping::uncovered_chars(&self.font.load_fonts(fonts)?, &self.text_transform.apply(&self.text)))
+    }
+
+    // The space between words: the first font's space, letter spaced like
+    // any other character, plus the word spacing
+    fn word_gap(&self, fonts: &[LoadedFont], scale: Scale) -> f32 {
+        shaping::space_width(fonts[0].font(), scale)
+            + self.letter_spacing.pixels(self.font.size)
+            + self.word_spacing.pixels(self.font.size)
+    }
+
+    fn line_advance(&self) -> f32 {
+        self.font.size * self.line_height
+    }
+
+    /// Sets the font size chosen by the `fit` mode. Shrink and grow keep the
+    /// text on its explicit lines; shrink-then-wrap only wraps once the text no
+    /// longer fits at `min_size`.
+    pub(crate) fn apply_fit(&mut self, fonts: &FontRegistry) -> Result<(), KitError> {
+        if self.fit == TextFit::None || self.font.family.is_empty() || self.font.size <= 0.0 {
+            return Ok(());
+        }
+        if self.max_width.is_none() && self.max_height.is_none() {
+            return Err(KitError::Invalid {
+                at: Location::field("fit"),
+                message: "fitting text needs max_width or max_height".to_string(),
+            });
+        }
+
+        let fonts = self.font.load_fonts(fonts)?;
+        let fits = |size: f32, max_width: Option<u32>| {
+            let mut candidate = self.clone();
+            candidate.font.size = size;
+            candidate.max_width = max_width;
+            let dimensions = candidate.measure(&fonts);
+            self.max_width.is_none_or(|width| dimensions.width <= width)
+                && self.max_height.is_none_or(|height| dimensions.height <= height)
+        };
+
+        let min_size = self.min_size.unwrap_or(DEFAULT_MIN_FIT_SIZE).min(self.font.size);
+        let (size, max_width) = match self.fit {
+            TextFit::None => return Ok(()),
+            TextFit::Shrink => {
+                let size = largest_fitting_size(min_size, self.font.size, |size| fits(size, None));
+                (size.unwrap_or(min_size), None)
+            }
+            TextFit::Grow => {
+                let max_size = self.max_size.unwrap_or(DEFAULT_MAX_FIT_SIZE).max(self.font.size);
+                let size = largest_fitting_size(self.font.size, max_size, |size| fits(size, None));
+                (size.unwrap_or(self.font.size), None)
+            }
+            TextFit::ShrinkThenWrap => {
+                match largest_fitting_size(min_size, self.font.size, |size| fits(size, None)) {
+                    Some(size) => (size, None),
+                    None => {
+                        let size = largest_fitting_size(min_size, self.font.size, |size| fits(size, self.max_width));
+                        (size.unwrap_or(min_size), self.max_width)
+                    }
+                }
+            }
+        };
+
+        self.font.size = size;
+        self.max_width = max_width;
+        Ok(())
+    }
+
+    fn effect_padding(&self) -> EffectPadding {
+        EffectPadding::new(self.stroke.as_ref(), self.shadow.as_ref())
+    }
+
+    /// The size of the laid-out text block: the widest line by the span from
+    /// the first line's top to the bottom of the last, or the box around the
+    /// ink of text on a path, plus room for the stroke and shadow. Warped text
+    /// measures the box around its bent ink instead.
+    pub(crate) fn measure(&self, fonts: &[LoadedFont]) -> LayerDimensions {
+        match &self.warp {
+            Some(warp) => {
+                let flat = self.warp_box(fonts).3;
+                let (width, height) = warp.warped_size(flat.width, flat.height);
+                LayerDimensions { width, height }
+            }
+            None => self.measure_flat(fonts),
+        }
+    }
+
+    // The size of the text before any warp
+    fn measure_flat(&self, fonts: &[LoadedFont]) -> LayerDimensions {
+        if let Some(path) = &self.path {
+            return self.path_glyphs(fonts, path, &Position { x: 0, y: 0, relative_to: RelativeTo::Canvas }).1;
+        }
+        let lines = self.wrap_lines(fonts, Scale::uniform(self.font.size));
+        let width = lines.iter().map(|line| line.width).fold(0.0, f32::max).ceil() as u32;
+        let height = match lines.last() {
+            Some(last) => ((lines.len() - 1) as f32 * self.line_advance()) as u32 + last.height,
+            None => 0,
+        };
+        let padding = self.effect_padding();
+        LayerDimensions {
+            width: width + padding.left + padding.right,
+            height: height + padding.top + padding.bottom,
+        }
+    }
+
+    /// Places each word of the text for drawing at `position`, reordering
+    /// mixed-direction lines for display. Shared by every output backend so
+    /// they agree on where text lands.
+    fn layout(&self, fonts: &[LoadedFont], position: &Position) -> TextLayout {
+        let scale = Scale::uniform(self.font.size);
+        // Lines are spaced by the first font of the chain
+        let v_metrics = fonts[0].font().v_metrics(scale);
+        let space_width = self.word_gap(fonts, scale);
+        let letter_spacing = self.letter_spacing.pixels(self.font.size);
+        let mut lines = Vec::new();
+
+        // Keep the stroke and shadow inside the measured box
+        let padding = self.effect_padding();
+        let anchor_x = match self.alignment {
+            TextAlignment::Center => position.x as f32 + (padding.left as f32 - padding.right as f32) / 2.0,
+            TextAlignment::Right => position.x as f32 - padding.right as f32,
+            TextAlignment::Left => position.x as f32 + padding.left as f32,
+        };
+
+        for (index, line) in self.wrap_lines(fonts, scale).into_iter().enumerate() {
+            let top = position.y + padding.top + (index as f32 * self.line_advance()) as u32;
+
+            // Each line is aligned on its own
+            let x_position = match self.alignment {
+                TextAlignment::Center => (anchor_x - line.width / 2.0).max(0.0),
+                TextAlignment::Right => (anchor_x - line.width).max(0.0),
+                TextAlignment::Left => anchor_x,
+            };
+
+            let (height, letter_spread) = (line.height, line.letter_spread);
+            let placed = line.into_visual_runs(space_width, letter_spacing).into_iter()
+                .map(|(run, x)| PlacedWord {
+                    text: run.text,
+                    x: x_position + x,
+                    shaped: run.shaped,
+                    letter_spread,
+                })
+                .collect();
+
+            lines.push(PlacedLine {
+                words: placed,
+                baseline: top as f32 + v_metrics.ascent,
+                top,
+                height,
+            });
+        }
+
+        TextLayout { lines }
+    }
+
+    /// Places every glyph of the text on the canvas, along the path if there
+    /// is one. Straight text also returns its layout, which decorations and
+    /// the SVG backend work from.
+    pub(crate) fn place(&self, fonts: &[LoadedFont], position: &Position) -> (Vec<PlacedGlyph>, Option<TextLayout>) {
+        match &self.path {
+            Some(path) => (self.path_glyphs(fonts, path, position).0, None),
+            None => {
+                let layout = self.layout(fonts, position);
+                (layout.glyphs(), Some(layout))
+            }
+        }
+    }
+
+    /// Places each glyph along the path, turned to follow it, then moves the
+    /// whole so the ink's top-left corner, less the effect padding, sits at
+    /// `position`. Lines after the first follow the path further below it.
+    /// Returns the glyphs with the size of the bent text.
+    fn path_glyphs(&self, fonts: &[LoadedFont], path: &TextPath, position: &Position) -> (Vec<PlacedGlyph>, LayerDimensions) {
+        let scale = Scale::uniform(self.font.size);
+        let space_width = self.word_gap(fonts, scale);
+        let letter_spacing = self.letter_spacing.pixels(self.font.size);
+        let lines = self.wrap_lines(fonts, scale);
+        let curve = path.curve(lines.iter().map(|line| line.width).fold(0.0, f32::max));
+
+        let mut glyphs = Vec::new();
+        for (index, line) in lines.into_iter().enumerate() {
+            let offset = index as f32 * self.line_advance();
+            let count = line.words.iter().flatten().map(|run| run.shaped.glyphs.len()).sum();
+            let (start, gap) = path.line_start(&curve, line.width, count, &self.alignment, offset);
+
+            let mut spread = 0.0;
+            for (run, run_x) in line.into_visual_runs(space_width, letter_spacing) {
+                let run_start = start + run_x;
+                for glyph in run.shaped.glyphs {
+                    // Each glyph is turned to the path's direction at its middle
+                    let glyph_scale = Scale::uniform(self.font.size * glyph.size);
+                    let advance = fonts[glyph.font].font().glyph(glyph.id).scaled(glyph_scale).h_metrics().advance_width;
+                    let middle = run_start + glyph.x + spread + advance / 2.0;
+                    let (on_path, heading) = curve.at(middle, offset + glyph.y);
+                    glyphs.push(PlacedGlyph {
+                        font: glyph.font,
+                        id: glyph.id,
+                        text: glyph.text,
+                        x: on_path.x - heading.x * advance / 2.0,
+                        y: on_path.y - heading.y * advance / 2.0,
+                        angle: heading.y.atan2(heading.x),
+                        size: glyph.size,
+                    });
+                    spread += gap;
+                }
+            }
+        }
+
+        let bounds = glyphs.iter()
+            .filter_map(|glyph| glyph.outline(fonts, scale).bounds())
+            .reduce(|(x0, y0, x1, y1), (min_x, min_y, max_x, max_y)| {
+                (x0.min(min_x), y0.min(min_y), x1.max(max_x), y1.max(max_y))
+            });
+        let padding = self.effect_padding();
+        let (shift_x, shift_y, width, height) = match bounds {
+            Some((min_x, min_y, max_x, max_y)) => (
+                (position.x + padding.left) as f32 - min_x.floor(),
+                (position.y + padding.top) as f32 - min_y.floor(),
+                (max_x.ceil() - min_x.floor()) as u32,
+                (max_y.ceil() - min_y.floor()) as u32,
+            ),
+            None => (0.0, 0.0, 0, 0),
+        };
+        for glyph in &mut glyphs {
+            glyph.x += shift_x;
+            glyph.y += shift_y;
+        }
+
+        let dimensions = LayerDimensions {
+            width: width + padding.left + padding.right,
+            height: height + padding.top + padding.bottom,
+        };
+        (glyphs, dimensions)
+    }
+
+    /// Draws the shadow, then the stroke, then the text and its decorations.
+    /// Warped text is drawn flat first and bent as a whole, with the top-left
+    /// corner of the result at `position`.
+    pub(crate) fn draw(&self, canvas: &mut RgbaImage, position: &Position, fonts: &FontRegistry) -> Result<(), KitError> {
+        let fonts = self.font.load_fonts(fonts)?;
+        let (image, x, y) = match &self.warp {
+            Some(warp) => (self.warped_image(&fonts, warp)?, position.x as i32, position.y as i32),
+            None => match self.render(&fonts, position)? {
+                Some(rendered) => rendered,
+                None => return Ok(()),
+            },
+        };
+        compositing::draw(canvas, &image, x, y, self.info.opacity, self.info.blend_mode);
+        Ok(())
+    }
+
+    /// The box a warp bends: the ink of the text laid out flat, grown by the
+    /// effect padding, as its top-left corner and size. Where layout anchors
+    /// the text doesn't matter, as long as each alignment keeps its lines in
+    /// line with each other.
+    fn warp_box(&self, fonts: &[LoadedFont]) -> (Position, i32, i32, LayerDimensions) {
+        let width = self.measure_flat(fonts).width;
+        let anchor_x = match (&self.path, &self.alignment) {
+            (Some(_), _) | (None, TextAlignment::Left) => 0,
+            (None, TextAlignment::Center) => width / 2,
+            (None, TextAlignment::Right) => width,
+        };
+        let position = Position { x: anchor_x, y: 0, relative_to: RelativeTo::Canvas };
+        let padding = self.effect_padding();
+        let (min_x, min_y, max_x, max_y) = self.ink(fonts, &position).bounds.unwrap_or((0, 0, 0, 0));
+        let dimensions = LayerDimensions {
+            width: (max_x - min_x) as u32 + padding.left + padding.right,
+            height: (max_y - min_y) as u32 + padding.top + padding.bottom,
+        };
+        (position, min_x - padding.left as i32, min_y - padding.top as i32, dimensions)
+    }
+
+    /// Renders the text flat into a transparent box around its ink and
+    /// remaps the box through the warp. Vector backends embed the result as
+    /// an image, since bent glyphs are no longer text.
+    pub(crate) fn warped_image(&self, fonts: &[LoadedFont], warp: &TextWarp) -> Result<RgbaImage, KitError> {
+        let (position, box_x, box_y, dimensions) = self.warp_box(fonts);
+        let mut image = RgbaImage::new(dimensions.width, dimensions.height);
+        if let Some((layer, x, y)) = self.render(fonts, &position)? {
+            compositing::draw(&mut image, &layer, x - box_x, y - box_y, 1.0, BlendMode::Normal);
+        }
+        Ok(warp.apply(&image))
+    }
+
+    /// The outlines and decoration rectangles of the text placed at
+    /// `position`, with the box around all of them.
+    pub(crate) fn ink(&self, fonts: &[LoadedFont], position: &Position) -> TextInk {
+        let scale = Scale::uniform(self.font.size);
+        let (glyphs, layout) = self.place(fonts, position);
+        let outlines: Vec<Outline> = glyphs.iter().map(|glyph| glyph.outline(fonts, scale)).collect();
+
+        // Decorations run the length of each line, spaces included. Text on a
+        // path has none.
+        let lines = layout.as_ref().map_or(&[][..], |layout| &layout.lines);
+        let decorations: Vec<_> = lines.iter()
+            .filter_map(|line| {
+                let (x, width) = line.extent()?;
+                let (line_y, thickness) = self.font.decoration_line(line.top, line.height)?;
+                Some((x as u32, line_y, width.ceil() as u32, thickness))
+            })
+            .collect();
+
+        let mut bounds: Option<(i32, i32, i32, i32)> = None;
+        let boxes = outlines.iter()
+            .filter_map(|outline| outline.bounds())
+            .map(|(min_x, min_y, max_x, max_y)| {
+                (min_x.floor() as i32, min_y.floor() as i32, max_x.ceil() as i32, max_y.ceil() as i32)
+            })
+            .chain(decorations.iter().map(|&(x, y, width, height)| {
+                (x as i32, y as i32, (x + width) as i32, (y + height) as i32)
+            }));
+        for (min_x, min_y, max_x, max_y) in boxes {
+            bounds = Some(match bounds {
+                Some((x0, y0, x1, y1)) => (x0.min(min_x), y0.min(min_y), x1.max(max_x), y1.max(max_y)),
+                None => (min_x, min_y, max_x, max_y),
+            });
+        }
+
+        TextInk { outlines, decorations, bounds }
+    }
+
+    /// Rasterizes the text with its effects, returning the image and where
+    /// its top-left pixel goes, or `None` when there is no ink.
+    fn render(&self, fonts: &[LoadedFont], position: &Position) -> Result<Option<(RgbaImage, i32, i32)>, KitError> {
+        let paint = self.font.paint()?;
+        let TextInk { outlines, decorations, bounds } = self.ink(fonts, position);
+
+        // The region everything is drawn in: the ink, grown to hold the effects
+        let Some((min_x, min_y, max_x, max_y)) = bounds else {
+            return Ok(None);
+        };
+        let padding = self.effect_padding();
+        let margin = [padding.left, padding.top, padding.right, padding.bottom].into_iter().max().unwrap_or(0)
+            + self.stroke.as_ref().map_or(0, effects::stroke_reach) + 1;
+        let mut fill = Mask::new(
+            min_x - margin as i32,
+            min_y - margin as i32,
+            (max_x - min_x) as u32 + 2 * margin,
+            (max_y - min_y) as u32 + 2 * margin,
+        );
+
+        for outline in &outlines {
+            fill.fill(outline);
+        }
+        for &(x, y, width, height) in &decorations {
+            fill.add_rect(x, y, width, height);
+        }
+
+        let stroke = match &self.stroke {
+            Some(stroke) => {
+                let mut stroker = Stroker::new(&fill, stroke);
+                for outline in &outlines {
+                    stroker.add_outline(outline);
+                }
+                for &(x, y, width, height) in &decorations {
+                    stroker.add_rect(x, y, width, height);
+                }
+                Some((stroker.finish(&fill), stroke.rgba_color()?))
+            }
+            None => None,
+        };
+
+        // The shadow falls from everything drawn, stroke included
+        let shadow = match &self.shadow {
+            Some(shadow) => {
+                let silhouette = stroke.as_ref().map_or(&fill, |(mask, _)| mask);
+                let mask = silhouette.shadow(shadow.offset.x, shadow.offset.y, shadow.blur_sigma());
+                Some((mask, shadow.rgba_color()?))
+            }
+            None => None,
+        };
+
+        // Gradients and patterns span the ink
+        let area = (min_x as f32, min_y as f32, max_x as f32, max_y as f32);
+        Ok(Some(effects::composite(
+            &fill,
+            |x, y| paint.color_at(x as f32 + 0.5, y as f32 + 0.5, area),
+            stroke.as_ref().map(|(mask, color)| (mask, *color)),
+            shadow.as_ref().map(|(mask, color)| (mask, *color)),
+        )))
+    }
+}
+
+// Everything a text layer inks, before effects
+pub(crate) struct TextInk {
+    pub(crate) outlines: Vec<Outline>,
+    // Underlines and the like as x, y, width and height
+    pub(crate) decorations: Vec<(u32, u32, u32, u32)>,
+    // The box around the outlines and decorations, if anything is inked
+    pub(crate) bounds: Option<(i32, i32, i32, i32)>,
+}
+
+// A line of wrapped text before placement
+#[derive(Default)]
+pub(crate) struct TextLine {
+    // Each word as its runs of one bidi embedding level, in logical order
+    pub(crate) words: Vec<Vec<TextRun>>,
+    // The embedding level of the space after each word but the last
+    pub(crate) spaces: Vec<unicode_bidi::Level>,
+    // Advance width of the words and the spaces between them
+    pub(crate) width: f32,
+    pub(crate) height: u32,
+    // Whether the line ends its paragraph, rather than being wrapped
+    pub(crate) ends_paragraph: bool,
+    // Added to each space between words by justification
+    pub(crate) extra_gap: f32,
+    // Added between the letters of a justified line with a single word
+    pub(crate) letter_spread: f32,
+}
+
+// Part of a word shaped in a single direction
+pub(crate) struct TextRun {
+    pub(crate) text: String,
+    pub(crate) shaped: ShapedText,
+    pub(crate) level: unicode_bidi::Level,
+}
+
+impl TextLine {
+    /// The runs of the line in the order they are drawn, left to right, each
+    /// with its distance from the start of the line. The spaces between words
+    /// are reordered along with the runs, so a space keeps to the direction
+    /// around it. Runs that meet inside a word are `letter_spacing` apart,
+    /// plus any justification spread.
+    fn into_visual_runs(self, space_width: f32, letter_spacing: f32) -> Vec<(TextRun, f32)> {
+        let mut items = Vec::new();
+        let mut levels = Vec::new();
+        for (index, runs) in self.words.into_iter().enumerate() {
+            if index > 0 {
+                items.push(None);
+                levels.push(self.spaces[index - 1]);
+            }
+            for run in runs {
+                levels.push(run.level);
+                items.push(Some(run));
+            }
+        }
+
+        let mut items: Vec<_> = items.into_iter().map(Some).collect();
+        let mut placed = Vec::new();
+        let mut x = 0.0;
+        let mut after_run = false;
+        for item in shaping::visual_order(&levels).into_iter().filter_map(|index| items[index].take()) {
+            match item {
+                Some(run) => {
+                    if after_run {
+                        x += letter_spacing + self.letter_spread;
+                    }
+                    let width = run.shaped.width;
+                    placed.push((run, x));
+                    x += width;
+                    after_run = true;
+                }
+                None => {
+                    x += space_width + self.extra_gap;
+                    after_run = false;
+                }
+            }
+        }
+        placed
+    }
+}
+
+// A run of a text layer placed on the canvas
+pub(crate) struct PlacedWord {
+    pub(crate) text: String,
+    pub(crate) x: f32,
+    pub(crate) shaped: ShapedText,
+    // Extra space between its letters from justification
+    pub(crate) letter_spread: f32,
+}
+
+pub(crate) struct PlacedLine {
+    pub(crate) words: Vec<PlacedWord>,
+    pub(crate) baseline: f32,
+    pub(crate) top: u32,
+    pub(crate) height: u32,
+}
+
+impl PlacedLine {
+    /// Start and width of the line from its first word to the end of its last.
+    pub(crate) fn extent(&self) -> Option<(f32, f32)> {
+        let first = self.words.first()?;
+        let last = self.words.last()?;
+        Some((first.x, last.x + last.shaped.width - first.x))
+    }
+}
+
+pub(crate) struct TextLayout {
+    pub(crate) lines: Vec<PlacedLine>,
+}
+
+impl TextLayout {
+    /// Every placed word along with the line it sits on.
+    pub(crate) fn words(&self) -> impl Iterator<Item = (&PlacedLine, &PlacedWord)> {
+        self.lines.iter().flat_map(|line| line.words.iter().map(move |word| (line, word)))
+    }
+
+    fn glyphs(&self) -> Vec<PlacedGlyph> {
+        self.words()
+            .flat_map(|(line, word)| {
+                word.shaped.glyphs.iter().map(move |glyph| PlacedGlyph {
+                    font: glyph.font,
+                    id: glyph.id,
+                    text: glyph.text.clone(),
+                    x: word.x + glyph.x,
+                    y: line.baseline + glyph.y,
+                    angle: 0.0,
+                    size: glyph.size,
+                })
+            })
+            .collect()
+    }
+}
+
+// A glyph of a text layer placed on the canvas, with its pen origin at `(x, y)`
+// and its baseline turned `angle` radians clockwise
+pub(crate) struct PlacedGlyph {
+    // Index of the font in the fallback chain
+    pub(crate) font: usize,
+    pub(crate) id: rusttype::GlyphId,
+    pub(crate) text: String,
+    pub(crate) x: f32,
+    pub(crate) y: f32,
+    pub(crate) angle: f32,
+    // Size relative to the layer's font size
+    pub(crate) size: f32,
+}
+
+impl PlacedGlyph {
+    fn outline(&self, fonts: &[LoadedFont], scale: Scale) -> Outline {
+        let scale = Scale { x: scale.x * self.size, y: scale.y * self.size };
+        let glyph = fonts[self.font].font().glyph(self.id).scaled(scale);
+        Outline::rotated(&glyph, ab_glyph_rasterizer::point(self.x, self.y), self.angle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn largest_fitting_size_finds_the_limit_within_a_tenth() {
+        let size = largest_fitting_size(1.0, 100.0, |size| size <= 42.5).unwrap();
+        assert!(size <= 42.5 && size > 42.4, "{}", size);
+    }
+
+    #[test]
+    fn largest_fitting_size_takes_the_ends_when_they_fit() {
+        assert_eq!(largest_fitting_size(1.0, 100.0, |_| true), Some(100.0));
+        assert_eq!(largest_fitting_size(10.0, 100.0, |size| size <= 10.0), Some(10.0));
+    }
+
+    #[test]
+    fn largest_fitting_size_gives_up_below_min() {
+        assert_eq!(largest_fitting_size(10.0, 100.0, |size| size < 5.0), None);
+    }
+
+    fn justified(text: &str, justify_last_line: bool) -> Vec<TextLine> {
+        let layer: TextLayer = serde_json::from_value(serde_json::json!({
+            "type": "text",
+            "name": "text",
+            "text": text,
+            "font": { "family": "Arial", "size": 20, "color": "#000000" },
+            "alignment": "left",
+            "justification": "justify",
+            "justify_last_line": justify_last_line,
+            "max_width": 300,
+        }))
+        .unwrap();
+        let font = LoadedFont::from_data(std::fs::read("fonts/arial.ttf").unwrap()).unwrap();
+        layer.wrap_lines(&[font], Scale::uniform(20.0))
+    }
+
+    #[test]
+    fn justify_leaves_a_single_line_paragraph_alone() {
+        let lines = justified("one two three\nfour", false);
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.width < 300.0 && line.extra_gap == 0.0 && line.letter_spread == 0.0));
+    }
+
+    #[test]
+    fn justify_last_line_spreads_a_single_line_paragraph() {
+        let lines = justified("one two three\nfour", true);
+        assert_eq!(lines[0].width, 300.0);
+        assert!(lines[0].extra_gap > 0.0);
+        // A lone word spreads its letters instead
+        assert_eq!(lines[1].width, 300.0);
+        assert!(lines[1].letter_spread > 0.0);
+    }
+
+    #[test]
+    fn justify_leaves_the_last_line_of_a_wrapped_paragraph() {
+        let text = "alpha beta gamma delta epsilon zeta eta theta iota kappa";
+        let lines = justified(text, false);
+        assert!(lines.len() > 1);
+        let (last, wrapped) = lines.split_last().unwrap();
+        assert!(wrapped.iter().all(|line| line.width == 300.0));
+        assert!(last.width < 300.0 && last.extra_gap == 0.0);
+
+        let lines = justified(text, true);
+        assert!(lines.iter().all(|line| line.width == 300.0));
+    }
+
+    #[test]
+    fn justified_runs_end_at_the_box_edge() {
+        let lines = justified("abc", true);
+        let letter_spacing = 0.0;
+        let runs = lines.into_iter().next().unwrap().into_visual_runs(5.0, letter_spacing);
+        let (run, x) = runs.last().unwrap();
+        assert!((x + run.shaped.width - 300.0).abs() < 0.01);
+    }
+
+    struct Frame(Option<(f64, f64, f64, f64)>);
+
+    impl SourceLayer for Frame {
+        fn name(&self) -> &str { "frame" }
+        fn content(&self) -> &str { "from the source" }
+        fn bounds(&self) -> Option<(f64, f64, f64, f64)> { self.0 }
+        fn font_name(&self) -> Option<&str> { None }
+        fn font_size(&self) -> Option<f32> { None }
+        fn color(&self) -> Option<(u8, u8, u8)> { None }
+    }
+
+    fn sourced(extra: serde_json::Value, bounds: Option<(f64, f64, f64, f64)>) -> TextLayer {
+        let mut settings = serde_json::json!({
+            "type": "text",
+            "name": "frame",
+            "font": { "family": "Arial", "size": 20, "color": "#000000" },
+            "alignment": "left",
+            "justification": "left",
+        });
+        settings.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        let mut layer: TextLayer = serde_json::from_value(settings).unwrap();
+        layer.apply_source(&Frame(bounds));
+        layer
+    }
+
+    #[test]
+    fn source_bounds_become_the_text_box() {
+        let layer = sourced(serde_json::json!({ "source_box": true }), Some((300.0, 80.0, 100.4, 20.0)));
+        assert_eq!(layer.text, "from the source");
+        assert_eq!((layer.max_width, layer.max_height), (Some(200), Some(60)));
+
+        // Point text's bounds only hug the text, so they aren't a box by default
+        let layer = sourced(serde_json::json!({}), Some((300.0, 80.0, 100.4, 20.0)));
+        assert_eq!((layer.max_width, layer.max_height), (None, None));
+    }
+
+    #[test]
+    fn template_box_wins_over_source_bounds() {
+        let layer = sourced(serde_json::json!({ "source_box": true, "max_width": 50 }), Some((0.0, 0.0, 200.0, 60.0)));
+        assert_eq!((layer.max_width, layer.max_height), (Some(50), Some(60)));
+
+        let layer = sourced(serde_json::json!({ "source_box": true }), Some((10.0, 10.0, 10.0, 10.0)));
+        assert_eq!((layer.max_width, layer.max_height), (None, None));
+        let layer = sourced(serde_json::json!({ "source_box": true }), None);
+        assert_eq!((layer.max_width, layer.max_height), (None, None));
+    }
+
+    #[test]
+    fn effects_pad_the_measured_box() {
+        let measure = |extra: serde_json::Value| {
+            let mut settings = serde_json::json!({
+                "type": "text",
+                "name": "text",
+                "text": "Padded",
+                "font": { "family": "Arial", "size": 20, "color": "#000000" },
+                "alignment": "left",
+            });
+            settings.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            let layer: TextLayer = serde_json::from_value(settings).unwrap();
+            let font = LoadedFont::from_data(std::fs::read("fonts/arial.ttf").unwrap()).unwrap();
+            layer.measure(&[font])
+        };
+        let plain = measure(serde_json::json!({}));
+        let padded = measure(serde_json::json!({
+            "stroke": { "color": "#FFFFFF", "width": 2 },
+            "shadow": { "color": "#000000", "offset": { "x": 3, "y": -4.5 }, "blur": 1 },
+        }));
+        // Two pixels left and below, six right and eight above
+        assert_eq!(padded.width, plain.width + 8);
+        assert_eq!(padded.height, plain.height + 10);
+    }
+
+    #[test]
+    fn transforms_change_case() {
+        let text = "hello wORLD\nstraße 2nd";
+        assert_eq!(TextTransform::None.apply(text), text);
+        assert_eq!(TextTransform::Uppercase.apply(text), "HELLO WORLD\nSTRASSE 2ND");
+        assert_eq!(TextTransform::Lowercase.apply(text), "hello world\nstraße 2nd");
+        // Only a word's first letter changes, even after digits
+        assert_eq!(TextTransform::Capitalize.apply(text), "Hello WORLD\nStraße 2Nd");
+    }
+
+    fn spacing(json: &str) -> Result<Spacing, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn spacing_reads_pixels_and_ems() {
+        let pixels = spacing("3").unwrap();
+        assert_eq!((pixels.value, pixels.em), (3.0, false));
+        assert_eq!(spacing("\"-1.5px\"").unwrap().pixels(40.0), -1.5);
+        assert_eq!(spacing("\"2\"").unwrap().pixels(40.0), 2.0);
+        assert_eq!(spacing("\"0.1em\"").unwrap().pixels(40.0), 4.0);
+        assert_eq!(spacing("\" 0.5 em\"").unwrap().pixels(10.0), 5.0);
+    }
+
+    #[test]
+    fn spacing_rejects_other_units() {
+        let error = spacing("\"2pt\"").err().unwrap();
+        assert!(error.to_string().contains("invalid spacing '2pt'"), "{}", error);
+        assert!(spacing("true").is_err());
+    }
+}
@@ -0,0 +1,584 @@
+use std::f32::consts::TAU;
+use ab_glyph_rasterizer::{point, Point};
+use serde::Deserialize;
+use crate::effects::{add, direction, distance, left_normal, scale, sub};
+use crate::error::{KitError, Location};
+use crate::text_layer::TextAlignment;
+
+// Longest wave drawn, in pixels, however long the text
+const MAX_WAVE_LENGTH: f32 = 100_000.0;
+
+/// A curve for a text layer's baseline to follow. Angles are in degrees,
+/// clockwise from the top for circles.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TextPath {
+    // Along a circle of `radius`, with the text aligned on the point at
+    // `start_angle`. Counterclockwise text runs along the bottom of the
+    // circle, reading left to right with its tops toward the center.
+    Arc {
+        radius: f32,
+        #[serde(default)]
+        start_angle: f32,
+        #[serde(default = "default_clockwise")]
+        clockwise: bool,
+    },
+    // All the way around a circle from `start_angle`, with the letters spread
+    // out to close the ring
+    Circle {
+        radius: f32,
+        #[serde(default)]
+        start_angle: f32,
+    },
+    // A sine wave rising and falling `amplitude` pixels over each
+    // `wavelength`, starting `phase` degrees into its cycle
+    Wave {
+        amplitude: f32,
+        wavelength: f32,
+        #[serde(default)]
+        phase: f32,
+    },
+    // The first subpath of SVG path data, in pixels
+    Svg {
+        d: String,
+    },
+}
+
+fn default_clockwise() -> bool {
+    true
+}
+
+/// A path resolved into something glyphs can be placed along.
+pub enum Curve {
+    // A circle around the origin; distance zero lands `start` radians
+    // clockwise from the top
+    Circle { radius: f32, start: f32, clockwise: bool },
+    // Points joined by straight segments, with the distance along the path to
+    // each point
+    Polyline { points: Vec<Point>, distances: Vec<f32> },
+}
+
+impl TextPath {
+    /// The path data, the only string setting of a path.
+    pub fn strings_mut(&mut self) -> Vec<(String, &mut String)> {
+        match self {
+            TextPath::Svg { d } => vec![("d".to_string(), d)],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), KitError> {
+        let positive = |value: f32, name: &str| {
+            if value > 0.0 {
+                Ok(())
+            } else {
+                Err(KitError::Invalid {
+                    at: Location::field(&format!("path.{}", name)),
+                    message: format!("path {} must be positive", name),
+                })
+            }
+        };
+        match self {
+            TextPath::Arc { radius, .. } | TextPath::Circle { radius, .. } => positive(*radius, "radius"),
+            TextPath::Wave { wavelength, .. } => positive(*wavelength, "wavelength"),
+            TextPath::Svg { d } => match parse_svg_path(d) {
+                Ok(points) if points.len() >= 2 => Ok(()),
+                Ok(_) => Err(KitError::Invalid {
+                    at: Location::field("path.d"),
+                    message: "path needs at least two distinct points".to_string(),
+                }),
+                Err(message) => Err(KitError::Invalid { at: Location::field("path.d"), message }),
+            },
+        }
+    }
+
+    /// The curve for text up to `text_length` pixels long to follow.
+    pub fn curve(&self, text_length: f32) -> Curve {
+        match self {
+            TextPath::Arc { radius, start_angle, clockwise } => {
+                Curve::Circle { radius: *radius, start: start_angle.to_radians(), clockwise: *clockwise }
+            }
+            TextPath::Circle { radius, start_angle } => {
+                Curve::Circle { radius: *radius, start: start_angle.to_radians(), clockwise: true }
+            }
+            TextPath::Wave { amplitude, wavelength, phase } => {
+                // Positive amplitudes rise first, so y is negated
+                let mut points = Vec::new();
+                let mut length = 0.0;
+                let mut x = 0.0;
+                while length <= text_length && x <= MAX_WAVE_LENGTH {
+                    let p = point(x, -amplitude * (TAU * x / wavelength + phase.to_radians()).sin());
+                    if let Some(&last) = points.last() {
+                        length += distance(last, p);
+                    }
+                    points.push(p);
+                    x += 1.0;
+                }
+                Curve::polyline(points)
+            }
+            TextPath::Svg { d } => Curve::polyline(parse_svg_path(d).unwrap_or_default()),
+        }
+    }
+
+    /// Where along `curve` a line `length` pixels long with `glyphs` glyphs
+    /// starts, and how much extra space goes after each glyph. Lines after the
+    /// first sit `offset` pixels below the path.
+    pub fn line_start(
+        &self,
+        curve: &Curve,
+        length: f32,
+        glyphs: usize,
+        alignment: &TextAlignment,
+        offset: f32,
+    ) -> (f32, f32) {
+        match self {
+            // Aligned on the start angle rather than along a stretch of path
+            TextPath::Arc { .. } => match alignment {
+                TextAlignment::Left => (0.0, 0.0),
+                TextAlignment::Center => (-length / 2.0, 0.0),
+                TextAlignment::Right => (-length, 0.0),
+            },
+            TextPath::Circle { .. } => {
+                let circumference = curve.length(offset);
+                (0.0, ((circumference - length) / glyphs.max(1) as f32).max(0.0))
+            }
+            TextPath::Wave { .. } | TextPath::Svg { .. } => {
+                let path_length = curve.length(0.0);
+                match alignment {
+                    TextAlignment::Left => (0.0, 0.0),
+                    TextAlignment::Center => ((path_length - length) / 2.0, 0.0),
+                    TextAlignment::Right => (path_length - length, 0.0),
+                }
+            }
+        }
+    }
+}
+
+impl Curve {
+    fn polyline(mut points: Vec<Point>) -> Self {
+        points.dedup_by(|a, b| distance(*a, *b) <= 1e-3);
+        // A path too short to have a direction runs straight to the right
+        if points.len() < 2 {
+            let start = points.first().copied().unwrap_or(point(0.0, 0.0));
+            points = vec![start, add(start, point(1.0, 0.0))];
+        }
+        let mut distances = vec![0.0];
+        for pair in points.windows(2) {
+            distances.push(distances[distances.len() - 1] + distance(pair[0], pair[1]));
+        }
+        Curve::Polyline { points, distances }
+    }
+
+    // Radius of the circle `offset` pixels below a circular path
+    fn offset_radius(radius: f32, clockwise: bool, offset: f32) -> f32 {
+        // Below is toward the center on a clockwise circle, away from it otherwise
+        let radius = if clockwise { radius - offset } else { radius + offset };
+        radius.max(1e-3)
+    }
+
+    /// The length of the path `offset` pixels below this one.
+    pub fn length(&self, offset: f32) -> f32 {
+        match self {
+            Curve::Circle { radius, clockwise, .. } => TAU * Curve::offset_radius(*radius, *clockwise, offset),
+            Curve::Polyline { distances, .. } => distances[distances.len() - 1],
+        }
+    }
+
+    /// The point `along` pixels along the path `offset` pixels below this one, and
+    /// the direction the path runs there. Open paths carry on straight past
+    /// their ends.
+    pub fn at(&self, along: f32, offset: f32) -> (Point, Point) {
+        match self {
+            Curve::Circle { radius, start, clockwise } => {
+                let radius = Curve::offset_radius(*radius, *clockwise, offset);
+                let sign = if *clockwise { 1.0 } else { -1.0 };
+                let angle = start + sign * along / radius;
+                let position = point(radius * angle.sin(), -radius * angle.cos());
+                (position, point(sign * angle.cos(), sign * angle.sin()))
+            }
+            Curve::Polyline { points, distances } => {
+                let segment = distances.partition_point(|&d| d <= along).clamp(1, points.len() - 1) - 1;
+                let (from, to) = (points[segment], points[segment + 1]);
+                let heading = direction(from, to);
+                let on_path = add(from, scale(heading, along - distances[segment]));
+                (add(on_path, scale(left_normal(heading), offset)), heading)
+            }
+        }
+    }
+}
+
+/// Reads SVG path data into the points of its first subpath, with curves and
+/// arcs split into short straight pieces.
+fn parse_svg_path(data: &str) -> Result<Vec<Point>, String> {
+    let mut tokens = PathTokens { data: data.as_bytes(), position: 0 };
+    let mut points: Vec<Point> = Vec::new();
+    let mut current = point(0.0, 0.0);
+    let mut subpath_start = current;
+    // Reflected control point for the smooth curve commands
+    let mut last_control: Option<(u8, Point)> = None;
+    let mut command = None;
+
+    loop {
+        let next = match tokens.command() {
+            Some(letter) => letter,
+            None if tokens.at_end() => break,
+            // Repeated arguments continue the previous command, with moves
+            // turning into lines
+            None => match command {
+                Some(b'M') => b'L',
+                Some(b'm') => b'l',
+                Some(letter) => letter,
+                None => return Err("path data must start with a command".to_string()),
+            },
+        };
+        if points.is_empty() && !next.eq_ignore_ascii_case(&b'M') {
+            return Err("path data must start with a move".to_string());
+        }
+        command = Some(next);
+        let relative = next.is_ascii_lowercase();
+        let base = if relative { current } else { point(0.0, 0.0) };
+        let mut control = None;
+
+        match next.to_ascii_uppercase() {
+            b'M' => {
+                // Only the first subpath is followed
+                if !points.is_empty() {
+                    break;
+                }
+                current = add(base, tokens.point()?);
+                subpath_start = current;
+                points.push(current);
+            }
+            b'L' => {
+                current = add(base, tokens.point()?);
+                points.push(current);
+            }
+            b'H' => {
+                current = point(base.x + tokens.number()?, current.y);
+                points.push(current);
+            }
+            b'V' => {
+                current = point(current.x, base.y + tokens.number()?);
+                points.push(current);
+            }
+            b'C' | b'S' => {
+                let first = if next.eq_ignore_ascii_case(&b'C') {
+                    add(base, tokens.point()?)
+                } else {
+                    reflect(last_control, b'C', current)
+                };
+                let second = add(base, tokens.point()?);
+                let end = add(base, tokens.point()?);
+                push_cubic(&mut points, current, first, second, end);
+                control = Some((b'C', second));
+                current = end;
+            }
+            b'Q' | b'T' => {
+                let quad_control = if next.eq_ignore_ascii_case(&b'Q') {
+                    add(base, tokens.point()?)
+                } else {
+                    reflect(last_control, b'Q', current)
+                };
+                let end = add(base, tokens.point()?);
+                // A quadratic is the cubic with controls two thirds of the way to its control
+                let first = add(current, scale(sub(quad_control, current), 2.0 / 3.0));
+                let second = add(end, scale(sub(quad_control, end), 2.0 / 3.0));
+                push_cubic(&mut points, current, first, second, end);
+                control = Some((b'Q', quad_control));
+                current = end;
+            }
+            b'A' => {
+                let radii = point(tokens.number()?.abs(), tokens.number()?.abs());
+                let rotation = tokens.number()?.to_radians();
+                let large_arc = tokens.flag()?;
+                let sweep = tokens.flag()?;
+                let end = add(base, tokens.point()?);
+                push_arc(&mut points, current, radii, rotation, large_arc, sweep, end);
+                current = end;
+            }
+            b'Z' => {
+                current = subpath_start;
+                points.push(current);
+            }
+            _ => return Err(format!("unknown path command '{}'", next as char)),
+        }
+        last_control = control;
+    }
+
+    points.dedup_by(|a, b| distance(*a, *b) <= 1e-3);
+    Ok(points)
+}
+
+// The smooth curve commands mirror the previous curve's last control point,
+// if it was the same kind of curve
+fn reflect(last_control: Option<(u8, Point)>, kind: u8, current: Point) -> Point {
+    match last_control {
+        Some((last_kind, control)) if last_kind == kind => sub(scale(current, 2.0), control),
+        _ => current,
+    }
+}
+
+// Pieces to split a curve into; roughly one per pixel of its control polygon
+fn curve_steps(length: f32) -> usize {
+    length.ceil().clamp(1.0, 256.0) as usize
+}
+
+fn push_cubic(points: &mut Vec<Point>, start: Point, first: Point, second: Point, end: Point) {
+    let steps = curve_steps(distance(start, first) + distance(first, second) + distance(second, end));
+    for step in 1..=steps {
+        let t = step as f32 / steps as f32;
+        let u = 1.0 - t;
+        points.push(point(
+            u * u * u * start.x + 3.0 * u * u * t * first.x + 3.0 * u * t * t * second.x + t * t * t * end.x,
+            u * u * u * start.y + 3.0 * u * u * t * first.y + 3.0 * u * t * t * second.y + t * t * t * end.y,
+        ));
+    }
+}
+
+// An elliptical arc from its endpoints, converted to its center form as in
+// the SVG specification's implementation notes
+fn push_arc(points: &mut Vec<Point>, start: Point, radii: Point, rotation: f32, large_arc: bool, sweep: bool, end: Point) {
+    if radii.x == 0.0 || radii.y == 0.0 {
+        points.push(end);
+        return;
+    }
+    let (sin, cos) = rotation.sin_cos();
+    let half = scale(sub(start, end), 0.5);
+    let x1 = cos * half.x + sin * half.y;
+    let y1 = -sin * half.x + cos * half.y;
+
+    // Radii too small to reach the end are scaled up until they just do
+    let (mut rx, mut ry) = (radii.x, radii.y);
+    let lambda = (x1 / rx).powi(2) + (y1 / ry).powi(2);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+
+    let numerator = (rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1).max(0.0);
+    let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let mut factor = if denominator > 0.0 { (numerator / denominator).sqrt() } else { 0.0 };
+    if large_arc == sweep {
+        factor = -factor;
+    }
+    let cx1 = factor * rx * y1 / ry;
+    let cy1 = -factor * ry * x1 / rx;
+    let center = point(
+        cos * cx1 - sin * cy1 + (start.x + end.x) / 2.0,
+        sin * cx1 + cos * cy1 + (start.y + end.y) / 2.0,
+    );
+
+    let angle = |x: f32, y: f32| y.atan2(x);
+    let start_angle = angle((x1 - cx1) / rx, (y1 - cy1) / ry);
+    let mut sweep_angle = angle((-x1 - cx1) / rx, (-y1 - cy1) / ry) - start_angle;
+    if sweep && sweep_angle < 0.0 {
+        sweep_angle += TAU;
+    } else if !sweep && sweep_angle > 0.0 {
+        sweep_angle -= TAU;
+    }
+
+    let steps = curve_steps(sweep_angle.abs() * rx.max(ry));
+    for step in 1..=steps {
+        let theta = start_angle + sweep_angle * step as f32 / steps as f32;
+        let (x, y) = (rx * theta.cos(), ry * theta.sin());
+        points.push(point(cos * x - sin * y + center.x, sin * x + cos * y + center.y));
+    }
+}
+
+// Commands, numbers and flags of SVG path data, skipping separators
+struct PathTokens<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl PathTokens<'_> {
+    fn skip_separators(&mut self) {
+        while self.position < self.data.len() && (self.data[self.position].is_ascii_whitespace() || self.data[self.position] == b',') {
+            self.position += 1;
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.position >= self.data.len()
+    }
+
+    fn command(&mut self) -> Option<u8> {
+        self.skip_separators();
+        let byte = *self.data.get(self.position)?;
+        // 'e' only appears inside numbers
+        if byte.is_ascii_alphabetic() && !byte.eq_ignore_ascii_case(&b'e') {
+            self.position += 1;
+            Some(byte)
+        } else {
+            None
+        }
+    }
+
+    fn number(&mut self) -> Result<f32, String> {
+        self.skip_separators();
+        let start = self.position;
+        let mut seen_dot = false;
+        let mut seen_digit = false;
+        if matches!(self.data.get(self.position), Some(b'+' | b'-')) {
+            self.position += 1;
+        }
+        while let Some(&byte) = self.data.get(self.position) {
+            match byte {
+                b'0'..=b'9' => seen_digit = true,
+                // A second dot starts the next number, as in "0.5.5"
+                b'.' if !seen_dot => seen_dot = true,
+                b'e' | b'E' if seen_digit => {
+                    self.position += 1;
+                    if matches!(self.data.get(self.position), Some(b'+' | b'-')) {
+                        self.position += 1;
+                    }
+                    while self.data.get(self.position).is_some_and(|byte| byte.is_ascii_digit()) {
+                        self.position += 1;
+                    }
+                    break;
+                }
+                _ => break,
+            }
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.data[start..self.position])
+            .ok()
+            .and_then(|text| text.parse().ok())
+            .ok_or_else(|| format!("expected a number at offset {}", start))
+    }
+
+    fn point(&mut self) -> Result<Point, String> {
+        Ok(point(self.number()?, self.number()?))
+    }
+
+    // Arc flags are single digits that may run into the next number
+    fn flag(&mut self) -> Result<bool, String> {
+        self.skip_separators();
+        match self.data.get(self.position) {
+            Some(b'0') => {
+                self.position += 1;
+                Ok(false)
+            }
+            Some(b'1') => {
+                self.position += 1;
+                Ok(true)
+            }
+            _ => Err(format!("expected an arc flag at offset {}", self.position)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: Point, expected: (f32, f32)) {
+        assert!(
+            (actual.x - expected.0).abs() < 1e-3 && (actual.y - expected.1).abs() < 1e-3,
+            "({}, {}) is not ({}, {})", actual.x, actual.y, expected.0, expected.1,
+        );
+    }
+
+    #[test]
+    fn clockwise_circles_start_at_the_top_heading_right() {
+        let curve = Curve::Circle { radius: 100.0, start: 0.0, clockwise: true };
+        let (position, heading) = curve.at(0.0, 0.0);
+        assert_near(position, (0.0, -100.0));
+        assert_near(heading, (1.0, 0.0));
+
+        // A quarter of the way round is the right-hand side, heading down
+        let (position, heading) = curve.at(TAU * 100.0 / 4.0, 0.0);
+        assert_near(position, (100.0, 0.0));
+        assert_near(heading, (0.0, 1.0));
+
+        // Below a clockwise path is toward the center
+        assert_near(curve.at(0.0, 10.0).0, (0.0, -90.0));
+        assert!((curve.length(10.0) - TAU * 90.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn counterclockwise_circles_read_left_to_right_along_the_bottom() {
+        let curve = TextPath::Arc { radius: 50.0, start_angle: 180.0, clockwise: false }.curve(0.0);
+        let (position, heading) = curve.at(0.0, 0.0);
+        assert_near(position, (0.0, 50.0));
+        assert_near(heading, (1.0, 0.0));
+        assert_near(curve.at(0.0, 5.0).0, (0.0, 55.0));
+    }
+
+    #[test]
+    fn polylines_carry_on_straight_past_their_ends() {
+        let curve = Curve::polyline(vec![point(0.0, 0.0), point(10.0, 0.0), point(10.0, 10.0)]);
+        assert_eq!(curve.length(0.0), 20.0);
+
+        let (position, heading) = curve.at(15.0, 0.0);
+        assert_near(position, (10.0, 5.0));
+        assert_near(heading, (0.0, 1.0));
+        assert_near(curve.at(-5.0, 0.0).0, (-5.0, 0.0));
+        assert_near(curve.at(25.0, 0.0).0, (10.0, 15.0));
+        // Below a path heading right is further down the canvas
+        assert_near(curve.at(5.0, 2.0).0, (5.0, 2.0));
+    }
+
+    #[test]
+    fn circles_spread_the_spare_circumference_between_glyphs() {
+        let path = TextPath::Circle { radius: 10.0, start_angle: 0.0 };
+        let curve = path.curve(0.0);
+        let (start, gap) = path.line_start(&curve, TAU * 10.0 - 20.0, 4, &TextAlignment::Left, 0.0);
+        assert_eq!(start, 0.0);
+        assert!((gap - 5.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn paths_center_text_along_their_length() {
+        let path = TextPath::Svg { d: "M0 0 H100".to_string() };
+        let curve = path.curve(0.0);
+        assert_eq!(path.line_start(&curve, 40.0, 4, &TextAlignment::Center, 0.0), (30.0, 0.0));
+        assert_eq!(path.line_start(&curve, 40.0, 4, &TextAlignment::Right, 0.0), (60.0, 0.0));
+    }
+
+    #[test]
+    fn parses_lines_and_relative_commands() {
+        let points = parse_svg_path("M0 0 L10 0 H20 V5 Z").unwrap();
+        let expected = [(0.0, 0.0), (10.0, 0.0), (20.0, 0.0), (20.0, 5.0), (0.0, 0.0)];
+        assert_eq!(points.len(), expected.len());
+        for (point, expected) in points.into_iter().zip(expected) {
+            assert_near(point, expected);
+        }
+
+        // Extra pairs after a move are lines, and numbers may run together
+        let points = parse_svg_path("m1,1 2,0 2-1.5.5.5").unwrap();
+        assert_eq!(points.len(), 4);
+        assert_near(points[2], (5.0, -0.5));
+        assert_near(points[3], (5.5, 0.0));
+
+        // Only the first subpath is followed
+        assert_eq!(parse_svg_path("M0 0 L1 0 M5 5 L6 6").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn splits_curves_and_arcs_into_points_on_them() {
+        let points = parse_svg_path("M0 0 C0 10 20 10 20 0").unwrap();
+        assert!(points.len() > 10);
+        assert_near(*points.last().unwrap(), (20.0, 0.0));
+
+        // A half circle sweeping clockwise goes over the top
+        let points = parse_svg_path("M0 0 A10 10 0 0 1 20 0").unwrap();
+        assert_near(*points.last().unwrap(), (20.0, 0.0));
+        for point in &points {
+            assert!((distance(*point, ab_glyph_rasterizer::point(10.0, 0.0)) - 10.0).abs() < 1e-3);
+        }
+        let top = points.iter().map(|point| point.y).fold(f32::MAX, f32::min);
+        assert!((top + 10.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn rejects_malformed_path_data() {
+        assert!(parse_svg_path("L1 1").is_err());
+        assert!(parse_svg_path("M0 0 X1 1").is_err());
+        assert!(parse_svg_path("M0 0 A1 1 0 2 1 3 3").is_err());
+        assert!(parse_svg_path("M0 0 L1").is_err());
+        assert!(TextPath::Svg { d: "M0 0".to_string() }.validate().is_err());
+        assert!(TextPath::Arc { radius: 0.0, start_angle: 0.0, clockwise: true }.validate().is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use crate::error::VariableError;
+
+/// Values bound to `{{name}}` placeholders in template text.
+pub type Variables = HashMap<String, String>;
+
+/// A `{{name}}` or `{{name|default}}` placeholder found in template text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placeholder {
+    pub name: String,
+    pub default: Option<String>,
+}
+
+enum Segment<'a> {
+    Literal(&'a str),
+    Placeholder(Placeholder),
+}
+
+fn parse(text: &str) -> Result<Vec<Segment<'_>>, VariableError> {
+    let mut segments = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            segments.push(Segment::Literal(&rest[..start]));
+        }
+        let after_open = &rest[start + 2..];
+        let end = after_open
+            .find("}}")
+            .ok_or_else(|| VariableError::Unterminated(text.to_string()))?;
+        let inner = &after_open[..end];
+
+        let (name, default) = match inner.split_once('|') {
+            Some((name, default)) => (name.trim(), Some(default.trim().to_string())),
+            None => (inner.trim(), None),
+        };
+        if name.is_empty() {
+            return Err(VariableError::EmptyName(text.to_string()));
+        }
+
+        segments.push(Segment::Placeholder(Placeholder { name: name.to_string(), default }));
+        rest = &after_open[end + 2..];
+    }
+
+    if !rest.is_empty() {
+        segments.push(Segment::Literal(rest));
+    }
+    Ok(segments)
+}
+
+/// Lists the placeholders referenced by `text`, in order of appearance.
+pub fn placeholders(text: &str) -> Result<Vec<Placeholder>, VariableError> {
+    Ok(parse(text)?
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Placeholder(placeholder) => Some(placeholder),
+            Segment::Literal(_) => None,
+        })
+        .collect())
+}
+
+/// Replaces every placeholder in `text` with its bound value, falling back to the
+/// placeholder's default. A placeholder with neither is an error.
+pub fn substitute(text: &str, variables: &Variables) -> Result<String, VariableError> {
+    let mut result = String::with_capacity(text.len());
+
+    for segment in parse(text)? {
+        match segment {
+            Segment::Literal(literal) => result.push_str(literal),
+            Segment::Placeholder(placeholder) => {
+                let value = variables
+                    .get(&placeholder.name)
+                    .or(placeholder.default.as_ref())
+                    .ok_or_else(|| VariableError::Unbound(placeholder.name.clone()))?;
+                result.push_str(value);
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bind(pairs: &[(&str, &str)]) -> Variables {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn lists_placeholders_in_order_with_defaults() {
+        let found = placeholders("Hi {{ name }}, from {{city|Springfield}}{{name}}").unwrap();
+        assert_eq!(found, vec![
+            Placeholder { name: "name".to_string(), default: None },
+            Placeholder { name: "city".to_string(), default: Some("Springfield".to_string()) },
+            Placeholder { name: "name".to_string(), default: None },
+        ]);
+        assert!(placeholders("no placeholders").unwrap().is_empty());
+    }
+
+    #[test]
+    fn substitutes_values_then_defaults() {
+        let variables = bind(&[("name", "Ada")]);
+        assert_eq!(substitute("Hi {{name}}!", &variables).unwrap(), "Hi Ada!");
+        assert_eq!(substitute("{{city|Springfield}}", &variables).unwrap(), "Springfield");
+        assert_eq!(substitute("{{name|Bob}}", &variables).unwrap(), "Ada");
+        // A default may be empty
+        assert_eq!(substitute("[{{city|}}]", &variables).unwrap(), "[]");
+        assert_eq!(substitute("plain", &variables).unwrap(), "plain");
+    }
+
+    #[test]
+    fn rejects_malformed_and_unbound_placeholders() {
+        let variables = Variables::new();
+        assert_eq!(substitute("Hi {{name", &variables), Err(VariableError::Unterminated("Hi {{name".to_string())));
+        assert_eq!(substitute("{{ |x}}", &variables), Err(VariableError::EmptyName("{{ |x}}".to_string())));
+        assert_eq!(substitute("{{name}}", &variables), Err(VariableError::Unbound("name".to_string())));
+    }
+}
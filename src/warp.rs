@@ -0,0 +1,303 @@
+use std::f32::consts::{PI, TAU};
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+use crate::error::{KitError, Location};
+
+// Samples taken across each output pixel, per side
+const SUPERSAMPLING: u32 = 3;
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WarpStyle {
+    // Bent around a circle, the letters fanning out
+    Arc,
+    // Only the bottom edge bows out
+    ArcLower,
+    // Only the top edge bows out
+    ArcUpper,
+    // Bowed up in the middle, every column moving by the same amount
+    Arch,
+    // The top bows up and the bottom down
+    Bulge,
+    // One sine wave along the text
+    Flag,
+    // Rising from left to right in an S curve
+    Rise,
+    // Pinched in horizontally around the middle of the text's height
+    Squeeze,
+}
+
+/// An Illustrator-style envelope warp. The text is drawn flat, then every
+/// pixel is moved through the envelope.
+#[derive(Deserialize, Clone)]
+pub struct TextWarp {
+    pub style: WarpStyle,
+    // Strength in percent, from -100 to 100; negative bends the other way
+    #[serde(default = "default_bend")]
+    pub bend: f32,
+}
+
+fn default_bend() -> f32 {
+    50.0
+}
+
+// Rises from 0 at the edges to 1 in the middle of `0..=1`
+fn hump(t: f32) -> f32 {
+    1.0 - (2.0 * t - 1.0).powi(2)
+}
+
+impl TextWarp {
+    pub fn validate(&self) -> Result<(), KitError> {
+        if !(-100.0..=100.0).contains(&self.bend) {
+            return Err(KitError::Invalid {
+                at: Location::field("warp.bend"),
+                message: "warp bend must be between -100 and 100".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn strength(&self) -> f32 {
+        self.bend / 100.0
+    }
+
+    // How far the top and bottom edges of the column at `u`, from 0 to 1
+    // across the text, move down, for the styles that only move columns
+    fn edge_shifts(&self, u: f32, height: f32) -> (f32, f32) {
+        let bend = self.strength() * height;
+        match self.style {
+            WarpStyle::ArcLower => (0.0, bend * hump(u)),
+            WarpStyle::ArcUpper => (-bend * hump(u), 0.0),
+            WarpStyle::Arch => (-bend * hump(u), -bend * hump(u)),
+            WarpStyle::Bulge => (-bend / 2.0 * hump(u), bend / 2.0 * hump(u)),
+            WarpStyle::Flag => {
+                let shift = -bend / 2.0 * (TAU * u).sin();
+                (shift, shift)
+            }
+            WarpStyle::Rise => {
+                let shift = -bend * u * u * (3.0 - 2.0 * u);
+                (shift, shift)
+            }
+            WarpStyle::Arc | WarpStyle::Squeeze => (0.0, 0.0),
+        }
+    }
+
+    // Angle the arc style spans and the radius of the text's middle line;
+    // negative for arcs bending down
+    fn arc(&self, width: f32) -> Option<(f32, f32)> {
+        let angle = self.strength() * PI;
+        (angle.abs() > 1e-4).then(|| (angle, width / angle))
+    }
+
+    // Horizontal scale of the row at `v`, from 0 to 1 down the text, for squeeze
+    fn squeeze(&self, v: f32) -> f32 {
+        (1.0 - self.strength() / 2.0 * hump(v)).max(1e-3)
+    }
+
+    /// Where the point `(x, y)` of a flat `width` by `height` text box ends up.
+    pub fn forward(&self, x: f32, y: f32, width: f32, height: f32) -> (f32, f32) {
+        let (u, v) = (x / width, y / height);
+        match self.style {
+            WarpStyle::Arc => {
+                let Some((angle, radius)) = self.arc(width) else {
+                    return (x, y);
+                };
+                let theta = (u - 0.5) * angle;
+                let r = radius + (height / 2.0 - y);
+                let center = (width / 2.0, height / 2.0 + radius);
+                (center.0 + r * theta.sin(), center.1 - r * theta.cos())
+            }
+            WarpStyle::Squeeze => (width / 2.0 + (x - width / 2.0) * self.squeeze(v), y),
+            _ => {
+                let (top, bottom) = self.edge_shifts(u, height);
+                (x, y + top + v * (bottom - top))
+            }
+        }
+    }
+
+    /// The point of the flat text box that lands on `(x, y)`, undoing `forward`.
+    pub fn inverse(&self, x: f32, y: f32, width: f32, height: f32) -> (f32, f32) {
+        match self.style {
+            WarpStyle::Arc => {
+                let Some((angle, radius)) = self.arc(width) else {
+                    return (x, y);
+                };
+                let center = (width / 2.0, height / 2.0 + radius);
+                // The radius has the arc's sign, so flip to measure from the center
+                let sign = angle.signum();
+                let (dx, dy) = (sign * (x - center.0), sign * (center.1 - y));
+                let r = sign * dx.hypot(dy);
+                let theta = dx.atan2(dy);
+                ((theta / angle + 0.5) * width, height / 2.0 - (r - radius))
+            }
+            WarpStyle::Squeeze => {
+                let v = y / height;
+                (width / 2.0 + (x - width / 2.0) / self.squeeze(v), y)
+            }
+            _ => {
+                let (top, bottom) = self.edge_shifts(x / width, height);
+                let stretch = (1.0 + (bottom - top) / height).max(1e-3);
+                (x, (y - top) / stretch)
+            }
+        }
+    }
+
+    /// The box, `(min_x, min_y, max_x, max_y)`, a flat `width` by `height`
+    /// text box fills once warped. The envelope is smooth, so its edge
+    /// outlines the result.
+    pub fn bounds(&self, width: f32, height: f32) -> (f32, f32, f32, f32) {
+        if width <= 0.0 || height <= 0.0 {
+            return (0.0, 0.0, width, height);
+        }
+        let steps = (width.max(height).ceil() as u32).max(1);
+        let mut bounds = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+        for step in 0..=steps {
+            let t = step as f32 / steps as f32;
+            for (x, y) in [(t * width, 0.0), (t * width, height), (0.0, t * height), (width, t * height)] {
+                let (x, y) = self.forward(x, y, width, height);
+                bounds = (bounds.0.min(x), bounds.1.min(y), bounds.2.max(x), bounds.3.max(y));
+            }
+        }
+        bounds
+    }
+
+    /// The size of a `width` by `height` text box once warped.
+    pub fn warped_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let (min_x, min_y, max_x, max_y) = self.bounds(width as f32, height as f32);
+        ((max_x.ceil() - min_x.floor()) as u32, (max_y.ceil() - min_y.floor()) as u32)
+    }
+
+    /// Remaps the flat rendering of a text box through the envelope. Each
+    /// output pixel averages several samples so stretched and squeezed parts
+    /// stay smooth.
+    pub fn apply(&self, flat: &RgbaImage) -> RgbaImage {
+        let (width, height) = (flat.width() as f32, flat.height() as f32);
+        let (min_x, min_y, _, _) = self.bounds(width, height);
+        let (min_x, min_y) = (min_x.floor(), min_y.floor());
+        let (out_width, out_height) = self.warped_size(flat.width(), flat.height());
+
+        RgbaImage::from_fn(out_width, out_height, |x, y| {
+            let mut sum = [0.0f32; 4];
+            for sub_y in 0..SUPERSAMPLING {
+                for sub_x in 0..SUPERSAMPLING {
+                    let sample_x = min_x + x as f32 + (sub_x as f32 + 0.5) / SUPERSAMPLING as f32;
+                    let sample_y = min_y + y as f32 + (sub_y as f32 + 0.5) / SUPERSAMPLING as f32;
+                    let (source_x, source_y) = self.inverse(sample_x, sample_y, width, height);
+                    let sample = sample_premultiplied(flat, source_x, source_y);
+                    for channel in 0..4 {
+                        sum[channel] += sample[channel];
+                    }
+                }
+            }
+            let count = (SUPERSAMPLING * SUPERSAMPLING) as f32;
+            let alpha = sum[3] / count;
+            if alpha <= 0.0 {
+                return Rgba([0, 0, 0, 0]);
+            }
+            Rgba([
+                (sum[0] / count / alpha).round().min(255.0) as u8,
+                (sum[1] / count / alpha).round().min(255.0) as u8,
+                (sum[2] / count / alpha).round().min(255.0) as u8,
+                (alpha * 255.0).round() as u8,
+            ])
+        })
+    }
+}
+
+// Bilinear sample at a point, with pixel centers at half-pixel offsets and
+// transparency outside the image. Colors come back multiplied by alpha, which
+// runs from 0 to 1, so transparent pixels don't darken the edges.
+fn sample_premultiplied(image: &RgbaImage, x: f32, y: f32) -> [f32; 4] {
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (left, top) = (x.floor(), y.floor());
+    let (fx, fy) = (x - left, y - top);
+
+    let mut result = [0.0; 4];
+    for (dx, dy, weight) in [(0, 0, (1.0 - fx) * (1.0 - fy)), (1, 0, fx * (1.0 - fy)), (0, 1, (1.0 - fx) * fy), (1, 1, fx * fy)] {
+        let (px, py) = (left as i64 + dx, top as i64 + dy);
+        if weight <= 0.0 || px < 0 || py < 0 || px >= image.width() as i64 || py >= image.height() as i64 {
+            continue;
+        }
+        let pixel = image.get_pixel(px as u32, py as u32);
+        let alpha = pixel[3] as f32 / 255.0;
+        for channel in 0..3 {
+            result[channel] += weight * pixel[channel] as f32 * alpha;
+        }
+        result[3] += weight * alpha;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STYLES: [WarpStyle; 8] = [
+        WarpStyle::Arc,
+        WarpStyle::ArcLower,
+        WarpStyle::ArcUpper,
+        WarpStyle::Arch,
+        WarpStyle::Bulge,
+        WarpStyle::Flag,
+        WarpStyle::Rise,
+        WarpStyle::Squeeze,
+    ];
+
+    #[test]
+    fn inverse_undoes_forward() {
+        let (width, height) = (200.0, 50.0);
+        for style in STYLES {
+            // At full strength some styles pinch a column down to nothing
+            for bend in [-90.0, -50.0, 0.0, 30.0, 90.0] {
+                let warp = TextWarp { style, bend };
+                for step_x in 0..=10 {
+                    for step_y in 0..=10 {
+                        let (x, y) = (step_x as f32 * width / 10.0, step_y as f32 * height / 10.0);
+                        let (warped_x, warped_y) = warp.forward(x, y, width, height);
+                        let (back_x, back_y) = warp.inverse(warped_x, warped_y, width, height);
+                        assert!(
+                            (back_x - x).abs() < 0.01 && (back_y - y).abs() < 0.01,
+                            "bend {} moved ({}, {}) to ({}, {})", bend, x, y, back_x, back_y,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_bend_leaves_the_box_as_it_is() {
+        for style in STYLES {
+            let warp = TextWarp { style, bend: 0.0 };
+            assert_eq!(warp.forward(30.0, 10.0, 200.0, 50.0), (30.0, 10.0));
+            assert_eq!(warp.warped_size(200, 50), (200, 50));
+        }
+    }
+
+    #[test]
+    fn bounds_grow_by_the_bend() {
+        // An arch lifts the middle of the text by half its height
+        let (min_x, min_y, max_x, max_y) = TextWarp { style: WarpStyle::Arch, bend: 50.0 }.bounds(200.0, 50.0);
+        assert_eq!((min_x, max_x), (0.0, 200.0));
+        assert!((min_y + 25.0).abs() < 0.01 && (max_y - 50.0).abs() < 0.01);
+
+        let (width, height) = TextWarp { style: WarpStyle::Bulge, bend: 100.0 }.warped_size(200, 50);
+        assert_eq!((width, height), (200, 100));
+    }
+
+    #[test]
+    fn apply_keeps_solid_text_solid() {
+        let flat = RgbaImage::from_pixel(40, 20, Rgba([200, 100, 50, 255]));
+        let warped = TextWarp { style: WarpStyle::Flag, bend: 40.0 }.apply(&flat);
+        let (width, height) = TextWarp { style: WarpStyle::Flag, bend: 40.0 }.warped_size(40, 20);
+        assert_eq!(warped.dimensions(), (width, height));
+        // The middle column is where the wave crosses its rest line
+        assert_eq!(*warped.get_pixel(width / 2, height / 2), Rgba([200, 100, 50, 255]));
+    }
+
+    #[test]
+    fn bend_must_be_a_percentage() {
+        assert!(TextWarp { style: WarpStyle::Arc, bend: 100.0 }.validate().is_ok());
+        assert!(TextWarp { style: WarpStyle::Arc, bend: -100.5 }.validate().is_err());
+    }
+}